@@ -0,0 +1,230 @@
+//! Reading tracks from audio files (FLAC, MP3 and WAV).
+
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+use symphonia::core::units::TimeBase;
+
+use crate::{Duration, Track, Tracklist};
+
+/// File extensions `Tracklist::from_directory` picks up.
+const AUDIO_EXTENSIONS: [&str; 3] = ["flac", "mp3", "wav"];
+
+/// Why an audio file couldn't be read, with the file it concerns.
+#[derive(Debug)]
+pub enum AudioError {
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    /// Not a supported format, or malformed.
+    Format {
+        path: PathBuf,
+        source: SymphoniaError,
+    },
+    /// The file holds no audio track, or one without a timing.
+    NoDuration(PathBuf),
+}
+
+impl std::fmt::Display for AudioError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AudioError::Io { path, source } => write!(f, "{}: {source}", path.display()),
+            AudioError::Format { path, source } => write!(f, "{}: {source}", path.display()),
+            AudioError::NoDuration(path) => {
+                write!(f, "{}: no audio duration found", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for AudioError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AudioError::Io { source, .. } => Some(source),
+            AudioError::Format { source, .. } => Some(source),
+            AudioError::NoDuration(_) => None,
+        }
+    }
+}
+
+fn seconds(time_base: TimeBase, ts: u64) -> Duration {
+    let time = time_base.calc_time(ts);
+    time.seconds as f64 + time.frac
+}
+
+/// Length of the audio in `path`, in seconds.
+///
+/// Uses the frame count from the file's header when there is one; otherwise
+/// (e.g. MP3 without a Xing/VBRI header) reads through every packet.
+fn audio_duration(path: &Path) -> Result<Duration, AudioError> {
+    let file = File::open(path).map_err(|source| AudioError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let format_error = |source| AudioError::Format {
+        path: path.to_path_buf(),
+        source,
+    };
+    let mut hint = Hint::new();
+    if let Some(extension) = path.extension().and_then(|e| e.to_str()) {
+        hint.with_extension(extension);
+    }
+    let mut format = symphonia::default::get_probe()
+        .format(
+            &hint,
+            MediaSourceStream::new(Box::new(file), Default::default()),
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .map_err(format_error)?
+        .format;
+
+    let track = format
+        .default_track()
+        .ok_or_else(|| AudioError::NoDuration(path.to_path_buf()))?;
+    let id = track.id;
+    let params = &track.codec_params;
+    let time_base = params
+        .time_base
+        .or_else(|| params.sample_rate.map(|rate| TimeBase::new(1, rate)))
+        .ok_or_else(|| AudioError::NoDuration(path.to_path_buf()))?;
+    if let Some(frames) = params.n_frames {
+        return Ok(seconds(time_base, frames));
+    }
+
+    let mut end = 0;
+    loop {
+        match format.next_packet() {
+            Ok(packet) if packet.track_id() == id => end = end.max(packet.ts() + packet.dur()),
+            Ok(_) => {}
+            Err(SymphoniaError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                break;
+            }
+            Err(e) => return Err(format_error(e)),
+        }
+    }
+    Ok(seconds(time_base, end))
+}
+
+/// Title for an audio file: its name without extension, minus a leading
+/// track number such as `01 `, `01 - ` or `1. `.
+fn title_from_path(path: &Path) -> String {
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy())
+        .unwrap_or_default();
+    let unnumbered = stem
+        .trim_start_matches(|c: char| c.is_ascii_digit())
+        .trim_start_matches(['.', '-', '_', ' ']);
+    let numbered = unnumbered.len() < stem.len()
+        && stem.starts_with(|c: char| c.is_ascii_digit())
+        && !unnumbered.is_empty();
+    if numbered {
+        unnumbered.to_string()
+    } else {
+        stem.into_owned()
+    }
+}
+
+impl Track {
+    /// A track for the audio file at `path`, with its exact duration and a
+    /// title taken from the file name (dropping the extension and any
+    /// leading track number, so `03 - Ballad.flac` becomes `Ballad`).
+    pub fn from_audio_path(path: impl AsRef<Path>) -> Result<Track, AudioError> {
+        let path = path.as_ref();
+        Ok(Track::new(title_from_path(path), audio_duration(path)?))
+    }
+}
+
+impl Tracklist {
+    /// Every FLAC, MP3 and WAV file directly in `dir`, as by
+    /// `Track::from_audio_path`, in file name order. Other files and
+    /// subdirectories are skipped; any unreadable audio file is an error.
+    pub fn from_directory(dir: impl AsRef<Path>) -> Result<Tracklist, AudioError> {
+        let dir = dir.as_ref();
+        let io_error = |source| AudioError::Io {
+            path: dir.to_path_buf(),
+            source,
+        };
+        let mut paths = Vec::new();
+        for entry in std::fs::read_dir(dir).map_err(io_error)? {
+            let path = entry.map_err(io_error)?.path();
+            let is_audio = path
+                .extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|e| AUDIO_EXTENSIONS.contains(&e.to_ascii_lowercase().as_str()));
+            if is_audio && path.is_file() {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+        paths
+            .iter()
+            .map(Track::from_audio_path)
+            .collect::<Result<_, _>>()
+            .map(Tracklist)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A silent 16-bit mono WAV file of `frames` samples at `rate` Hz.
+    fn wav(rate: u32, frames: u32) -> Vec<u8> {
+        let data = frames * 2;
+        let mut bytes = Vec::new();
+        bytes.extend(b"RIFF");
+        bytes.extend((36 + data).to_le_bytes());
+        bytes.extend(b"WAVEfmt ");
+        bytes.extend(16u32.to_le_bytes());
+        bytes.extend(1u16.to_le_bytes()); // PCM
+        bytes.extend(1u16.to_le_bytes()); // mono
+        bytes.extend(rate.to_le_bytes());
+        bytes.extend((rate * 2).to_le_bytes());
+        bytes.extend(2u16.to_le_bytes());
+        bytes.extend(16u16.to_le_bytes());
+        bytes.extend(b"data");
+        bytes.extend(data.to_le_bytes());
+        bytes.resize(bytes.len() + data as usize, 0);
+        bytes
+    }
+
+    #[test]
+    fn test_title_from_path() {
+        let title = |path: &str| title_from_path(Path::new(path));
+        assert_eq!(title("music/03 - Ballad.flac"), "Ballad");
+        assert_eq!(title("1. Intro.mp3"), "Intro");
+        assert_eq!(title("07_Outro.wav"), "Outro");
+        assert_eq!(title("1999.wav"), "1999");
+        assert_eq!(title("Song 2.mp3"), "Song 2");
+    }
+
+    #[test]
+    fn test_from_directory() {
+        let dir = std::env::temp_dir().join(format!("albumseq-audio-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("02 Second.wav"), wav(8000, 12_000)).unwrap();
+        std::fs::write(dir.join("01 - First.WAV"), wav(44_100, 44_100 * 2)).unwrap();
+        std::fs::write(dir.join("notes.txt"), "not audio").unwrap();
+
+        let single = Track::from_audio_path(dir.join("02 Second.wav"));
+        let tracklist = Tracklist::from_directory(&dir);
+        std::fs::write(dir.join("03 Broken.mp3"), "not audio either").unwrap();
+        let broken = Tracklist::from_directory(&dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let single = single.unwrap();
+        assert_eq!((single.title.as_str(), single.duration), ("Second", 1.5));
+        let tracklist = tracklist.unwrap();
+        assert_eq!(tracklist.titles(), vec!["First", "Second"]);
+        assert_eq!(tracklist.0[0].duration, 2.0);
+        assert!(broken.unwrap_err().to_string().contains("03 Broken.mp3"));
+    }
+}
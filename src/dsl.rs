@@ -0,0 +1,306 @@
+//! A line-based text format for constraints.
+//!
+//! Each non-blank line holds one constraint: a quoted track title, a
+//! relation, and optional modifiers. `#` starts a comment.
+//!
+//! ```text
+//! "Intro" at 0 weight 7
+//! "First" then "Second" weight 5
+//! "Second" same-side "Third" weight 2
+//! "Ballad" between 3 and 5 priority 1
+//! "Single" next-to "Remix" penalty
+//! ```
+//!
+//! Relations, with `"A"` the leading title:
+//!
+//! | Text                      | Kind                          |
+//! |---------------------------|-------------------------------|
+//! | `at N`                    | `AtPosition(A, N)`            |
+//! | `between N and M`         | `InPositionRange(A, N, M)`    |
+//! | `on-side N`               | `OnSide(A, N)`                |
+//! | `opens-side`              | `OpensSide(A)`                |
+//! | `closes-side`             | `ClosesSide(A)`               |
+//! | `closes-album`            | `ClosesAlbum(A)`              |
+//! | `then "B"`                | `Adjacent(A, B)`              |
+//! | `next-to "B"`             | `AdjacentEither(A, B)`        |
+//! | `not-next-to "B"`         | `NotAdjacent(A, B)`           |
+//! | `before "B"`              | `Before(A, B)`                |
+//! | `after "B"`               | `Before(B, A)`                |
+//! | `same-side "B"`           | `OnSameSide(A, B)`            |
+//! | `other-side "B"`          | `NotOnSameSide(A, B)`         |
+//! | `apart-from "B" by N`     | `Separated(A, B, N)`          |
+//!
+//! Modifiers, in any order: `weight N` (default 1, may be fractional such as
+//! `2.5`), `priority N`, `hard` and `penalty`, as on `Constraint`. Titles may
+//! contain `\"` and `\\`.
+
+use crate::{Constraint, ConstraintKind};
+
+/// A line that couldn't be parsed, see `parse_constraints`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// One-based line number.
+    pub line: usize,
+    pub message: String,
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Title(String),
+    Word(String),
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Title(title) => write!(f, "{title:?}"),
+            Token::Word(word) => write!(f, "`{word}`"),
+        }
+    }
+}
+
+/// Split a line into quoted titles and bare words, dropping any comment.
+fn tokenize(line: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = line.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c == '#' {
+            break;
+        } else if c.is_whitespace() {
+            chars.next();
+        } else if c == '"' {
+            chars.next();
+            let mut title = String::new();
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => match chars.next() {
+                        Some(escaped @ ('"' | '\\')) => title.push(escaped),
+                        _ => return Err("expected `\\\"` or `\\\\` after `\\`".into()),
+                    },
+                    Some(c) => title.push(c),
+                    None => return Err("unterminated title".into()),
+                }
+            }
+            tokens.push(Token::Title(title));
+        } else {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() || c == '"' || c == '#' {
+                    break;
+                }
+                word.push(c);
+                chars.next();
+            }
+            tokens.push(Token::Word(word));
+        }
+    }
+    Ok(tokens)
+}
+
+/// Cursor over one line's tokens.
+struct Tokens {
+    tokens: std::vec::IntoIter<Token>,
+}
+
+impl Tokens {
+    fn title(&mut self, what: &str) -> Result<String, String> {
+        match self.tokens.next() {
+            Some(Token::Title(title)) => Ok(title),
+            Some(other) => Err(format!("expected {what} title, found {other}")),
+            None => Err(format!("expected {what} title")),
+        }
+    }
+
+    fn word(&mut self, what: &str) -> Result<String, String> {
+        match self.tokens.next() {
+            Some(Token::Word(word)) => Ok(word),
+            Some(other) => Err(format!("expected {what}, found {other}")),
+            None => Err(format!("expected {what}")),
+        }
+    }
+
+    fn number(&mut self, what: &str) -> Result<usize, String> {
+        let word = self.word(what)?;
+        word.parse()
+            .map_err(|_| format!("expected {what}, found `{word}`"))
+    }
+
+    fn weight(&mut self) -> Result<f64, String> {
+        let word = self.word("a weight")?;
+        word.parse()
+            .ok()
+            .filter(|w: &f64| w.is_finite() && *w >= 0.0)
+            .ok_or_else(|| format!("expected a weight, found `{word}`"))
+    }
+
+    fn keyword(&mut self, keyword: &str) -> Result<(), String> {
+        match self.word(&format!("`{keyword}`"))? {
+            word if word == keyword => Ok(()),
+            word => Err(format!("expected `{keyword}`, found `{word}`")),
+        }
+    }
+}
+
+/// Parse one constraint from a line holding at least one token.
+fn parse_line(tokens: Vec<Token>) -> Result<Constraint, String> {
+    let mut tokens = Tokens {
+        tokens: tokens.into_iter(),
+    };
+    let a = tokens.title("a")?;
+    let relation = tokens.word("a relation")?;
+    let kind = match relation.as_str() {
+        "at" => ConstraintKind::AtPosition(a, tokens.number("a position")?),
+        "between" => {
+            let min = tokens.number("a position")?;
+            tokens.keyword("and")?;
+            ConstraintKind::InPositionRange(a, min, tokens.number("a position")?)
+        }
+        "on-side" => ConstraintKind::OnSide(a, tokens.number("a side index")?),
+        "opens-side" => ConstraintKind::OpensSide(a),
+        "closes-side" => ConstraintKind::ClosesSide(a),
+        "closes-album" => ConstraintKind::ClosesAlbum(a),
+        "then" => ConstraintKind::Adjacent(a, tokens.title("a second")?),
+        "next-to" => ConstraintKind::AdjacentEither(a, tokens.title("a second")?),
+        "not-next-to" => ConstraintKind::NotAdjacent(a, tokens.title("a second")?),
+        "before" => ConstraintKind::Before(a, tokens.title("a second")?),
+        "after" => ConstraintKind::Before(tokens.title("a second")?, a),
+        "same-side" => ConstraintKind::OnSameSide(a, tokens.title("a second")?),
+        "other-side" => ConstraintKind::NotOnSameSide(a, tokens.title("a second")?),
+        "apart-from" => {
+            let b = tokens.title("a second")?;
+            tokens.keyword("by")?;
+            ConstraintKind::Separated(a, b, tokens.number("a track count")?)
+        }
+        other => return Err(format!("unknown relation `{other}`")),
+    };
+
+    let mut constraint = Constraint::new(kind, 1);
+    while let Some(token) = tokens.tokens.next() {
+        match token {
+            Token::Word(word) if word == "weight" => {
+                constraint.weight = tokens.weight()?;
+            }
+            Token::Word(word) if word == "priority" => {
+                constraint.priority = tokens.number("a priority")?;
+            }
+            Token::Word(word) if word == "hard" => constraint.hard = true,
+            Token::Word(word) if word == "penalty" => constraint.penalty = true,
+            other => return Err(format!("unexpected {other}")),
+        }
+    }
+    Ok(constraint)
+}
+
+/// Parse constraints written one per line in the format described in the
+/// module docs. Stops at the first malformed line.
+pub fn parse_constraints(text: &str) -> Result<Vec<Constraint>, ParseError> {
+    let mut constraints = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let error = |message| ParseError {
+            line: i + 1,
+            message,
+        };
+        let tokens = tokenize(line).map_err(error)?;
+        if !tokens.is_empty() {
+            constraints.push(parse_line(tokens).map_err(error)?);
+        }
+    }
+    Ok(constraints)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Medium, Tracklist, score_tracklist};
+
+    #[test]
+    fn test_parse_constraints() {
+        let constraints = parse_constraints(
+            r#"
+            # Opening run
+            "Intro" at 0 weight 7
+            "First" then "Second" weight 5
+            "Second" same-side "Third" weight 2   # keep them together
+            "Say \"Hi\"" after "Intro" priority 1 hard
+            "Third" between 1 and 3 weight 0.5
+            "First" apart-from "Third" by 1 penalty weight 4
+            "#,
+        )
+        .unwrap();
+
+        let kinds: Vec<String> = constraints
+            .iter()
+            .map(|c| format!("{:?}", c.kind))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                r#"AtPosition("Intro", 0)"#,
+                r#"Adjacent("First", "Second")"#,
+                r#"OnSameSide("Second", "Third")"#,
+                r#"Before("Intro", "Say \"Hi\"")"#,
+                r#"InPositionRange("Third", 1, 3)"#,
+                r#"Separated("First", "Third", 1)"#,
+            ]
+        );
+        let flags: Vec<(f64, usize, bool, bool)> = constraints
+            .iter()
+            .map(|c| (c.weight, c.priority, c.hard, c.penalty))
+            .collect();
+        assert_eq!(
+            flags,
+            vec![
+                (7.0, 0, false, false),
+                (5.0, 0, false, false),
+                (2.0, 0, false, false),
+                (1.0, 1, true, false),
+                (0.5, 0, false, false),
+                (4.0, 0, false, true),
+            ]
+        );
+
+        let tracklist = Tracklist::from(vec![
+            ("Intro", 1.0),
+            ("First", 1.0),
+            ("Second", 1.0),
+            ("Third", 1.0),
+            ("Say \"Hi\"", 1.0),
+        ]);
+        let medium = Medium::new("test_medium", 1, 10.0);
+        assert_eq!(score_tracklist(&tracklist, &constraints, &medium), 15.5);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = |text| parse_constraints(text).unwrap_err().to_string();
+        assert_eq!(
+            error("\"A\" at 0\n\"B\" near \"C\""),
+            "line 2: unknown relation `near`"
+        );
+        assert_eq!(
+            error("\"A\" at first"),
+            "line 1: expected a position, found `first`"
+        );
+        assert_eq!(error("at 0"), "line 1: expected a title, found `at`");
+        assert_eq!(error("\"A\" then"), "line 1: expected a second title");
+        assert_eq!(error("\"A\" at 0 heavy"), "line 1: unexpected `heavy`");
+        assert_eq!(
+            error("\"A\" at 0 weight -1"),
+            "line 1: expected a weight, found `-1`"
+        );
+        assert_eq!(error("\"A at 0"), "line 1: unterminated title");
+        assert_eq!(
+            error("\"A\" between 1 or 2"),
+            "line 1: expected `and`, found `or`"
+        );
+    }
+}
@@ -0,0 +1,307 @@
+//! Rendering tracklists for other tools.
+
+use crate::{Duration, Medium, Track, Tracklist};
+
+/// One track's placement on a medium's timeline.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct TimelineEntry {
+    pub title: String,
+    /// Zero-based side index, as in `Medium::side_indices`.
+    pub side: usize,
+    /// Start time from the beginning of the side, including lead-in and gaps.
+    pub start_seconds: Duration,
+    pub duration_seconds: Duration,
+}
+
+/// Format seconds as `m:ss`, rounding to the nearest second.
+fn format_mmss(seconds: Duration) -> String {
+    let total = seconds.max(0.0).round() as u64;
+    format!("{}:{:02}", total / 60, total % 60)
+}
+
+/// Side label: A, B, ... Z, then 27, 28, ...
+fn side_label(side: usize) -> String {
+    match u8::try_from(side) {
+        Ok(i) if i < 26 => char::from(b'A' + i).to_string(),
+        _ => (side + 1).to_string(),
+    }
+}
+
+/// Format seconds as a CUE time, `mm:ss:ff` with 75 frames per second,
+/// rounding to the nearest frame.
+fn format_cue_time(seconds: Duration) -> String {
+    let frames = (seconds.max(0.0) * 75.0).round() as u64;
+    format!(
+        "{:02}:{:02}:{:02}",
+        frames / (75 * 60),
+        frames / 75 % 60,
+        frames % 75
+    )
+}
+
+/// A CUE string value; CUE has no escapes, so double quotes become single.
+fn cue_quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('"', "'"))
+}
+
+/// Render a readable side-by-side plan of `tracklist` on `medium`.
+///
+/// One line per side, e.g. `Side A (9:15): 1. Intro 3:45 / 2. Song 5:30`,
+/// with the side's total time (including lead-in and gaps) in the header and
+/// track numbers counted across the whole album. Uses the same packing as
+/// `Medium::split_sides`. Tracks longer than a side are marked
+/// `(longer than a side)`, and tracks spilling past the medium's last side are
+/// listed on a final `Unplaced:` line.
+pub fn format_layout(tracklist: &Tracklist, medium: &Medium) -> String {
+    let mut out = String::new();
+    let mut number = 0;
+    let mut unplaced = Vec::new();
+
+    for (side, (tracks, stat)) in medium
+        .split_sides(tracklist)
+        .iter()
+        .zip(medium.side_stats(tracklist))
+        .enumerate()
+    {
+        let entries: Vec<String> = tracks
+            .iter()
+            .map(|track| {
+                number += 1;
+                let mut entry =
+                    format!("{number}. {} {}", track.title, format_mmss(track.duration));
+                if medium.lead_in + track.duration > medium.effective_capacity() {
+                    entry.push_str(" (longer than a side)");
+                }
+                entry
+            })
+            .collect();
+
+        if side < medium.sides {
+            out.push_str(&format!(
+                "Side {} ({}): {}\n",
+                side_label(side),
+                format_mmss(stat.used),
+                entries.join(" / ")
+            ));
+        } else {
+            unplaced.extend(entries);
+        }
+    }
+
+    if !unplaced.is_empty() {
+        out.push_str(&format!("Unplaced: {}\n", unplaced.join(" / ")));
+    }
+    out
+}
+
+impl Tracklist {
+    /// Lay the tracks out on `medium`'s sides with absolute start times.
+    ///
+    /// Each side starts at the medium's `lead_in`, and tracks on the same
+    /// side are separated by its `gap` (or overlap by its `crossfade`), using
+    /// the same packing as `split_sides`.
+    pub fn timeline(&self, medium: &Medium) -> Vec<TimelineEntry> {
+        let mut entries: Vec<TimelineEntry> = Vec::with_capacity(self.0.len());
+
+        for (i, (track, side)) in self.0.iter().zip(medium.side_indices(self)).enumerate() {
+            let start_seconds = match entries.last() {
+                Some(prev) if prev.side == side => {
+                    prev.start_seconds
+                        + prev.duration_seconds
+                        + medium.spacing(&self.0[i - 1], track)
+                }
+                _ => medium.lead_in,
+            };
+            entries.push(TimelineEntry {
+                title: track.title.clone(),
+                side,
+                start_seconds,
+                duration_seconds: track.duration,
+            });
+        }
+        entries
+    }
+
+    /// Render one CUE sheet per side of `medium`, for mastering each side as
+    /// a single file named by `file_name(side)`.
+    ///
+    /// Start times come from `timeline`, so they include the lead-in and
+    /// gaps. A track preceded by silence (the lead-in, or a gap) gets an
+    /// `INDEX 00` where the silence starts, and its `INDEX 01` where it
+    /// starts playing. Tracks are numbered across the whole album, with the
+    /// artist as `PERFORMER` when known. Tracks spilling past the medium's
+    /// last side get sheets of their own.
+    pub fn to_cue_sheets(
+        &self,
+        medium: &Medium,
+        file_name: impl Fn(usize) -> String,
+    ) -> Vec<String> {
+        let mut sheets: Vec<String> = Vec::new();
+        let mut previous_end = 0.0;
+        for (i, (entry, track)) in self.timeline(medium).iter().zip(&self.0).enumerate() {
+            if sheets.len() == entry.side {
+                sheets.push(format!("FILE {} WAVE\n", cue_quote(&file_name(entry.side))));
+                previous_end = 0.0;
+            }
+            let sheet = sheets.last_mut().expect("a sheet per side");
+            sheet.push_str(&format!("  TRACK {:02} AUDIO\n", i + 1));
+            sheet.push_str(&format!("    TITLE {}\n", cue_quote(&track.title)));
+            if let Some(artist) = &track.artist {
+                sheet.push_str(&format!("    PERFORMER {}\n", cue_quote(artist)));
+            }
+            if entry.start_seconds > previous_end {
+                sheet.push_str(&format!("    INDEX 00 {}\n", format_cue_time(previous_end)));
+            }
+            sheet.push_str(&format!(
+                "    INDEX 01 {}\n",
+                format_cue_time(entry.start_seconds)
+            ));
+            previous_end = entry.start_seconds + entry.duration_seconds;
+        }
+        sheets
+    }
+
+    /// Render an extended M3U playlist, readable by
+    /// `Tracklist::from_m3u_reader`.
+    ///
+    /// Each track gets an `#EXTINF:<seconds>,<title>` line with its exact
+    /// duration (whole seconds are written without a fraction), followed by
+    /// the path or URL `location` returns for it. Write the result as UTF-8 (`.m3u8`).
+    pub fn to_m3u(&self, location: impl Fn(&Track) -> String) -> String {
+        let mut out = String::from("#EXTM3U\n");
+        for track in &self.0 {
+            out.push_str(&format!(
+                "#EXTINF:{},{}\n{}\n",
+                track.duration.max(0.0),
+                track.title,
+                location(track)
+            ));
+        }
+        out
+    }
+
+    /// The `timeline` as a JSON array of
+    /// `{title, side, start_seconds, duration_seconds}` objects.
+    #[cfg(feature = "serde")]
+    pub fn to_timeline_json(&self, medium: &Medium) -> String {
+        serde_json::to_string(&self.timeline(medium)).expect("timeline entries always serialize")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timeline() {
+        let medium = Medium::new("test_medium", 2, 12.0)
+            .with_gap(0.5)
+            .with_lead_in(1.0);
+        // Side 0: 1.0 + A(4) + 0.5 + B(5) = 10.5; C would overflow
+        let tracks = Tracklist::from(vec![("A", 4.0), ("B", 5.0), ("C", 3.0), ("D", 2.0)]);
+        let timeline = tracks.timeline(&medium);
+
+        let starts: Vec<(usize, Duration)> =
+            timeline.iter().map(|e| (e.side, e.start_seconds)).collect();
+        assert_eq!(starts, vec![(0, 1.0), (0, 5.5), (1, 1.0), (1, 4.5)]);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_to_timeline_json() {
+        let medium = Medium::new("test_medium", 2, 10.0).with_lead_in(2.0);
+        let tracks = Tracklist::from(vec![("A", 4.0), ("B", 4.0), ("C", 3.0)]);
+
+        let json: serde_json::Value =
+            serde_json::from_str(&tracks.to_timeline_json(&medium)).unwrap();
+        // C starts side 1 again at the lead-in offset
+        assert_eq!(json[2]["title"], "C");
+        assert_eq!(json[2]["side"], 1);
+        assert_eq!(json[2]["start_seconds"], 2.0);
+        assert_eq!(json[2]["duration_seconds"], 3.0);
+        assert_eq!(json[1]["start_seconds"], 6.0);
+    }
+
+    #[test]
+    fn test_format_layout() {
+        let tracks = Tracklist::from(vec![
+            ("Intro", 225.0),
+            ("Song", 330.0),
+            ("Ballad", 270.0),
+            ("Closer", 200.4),
+        ]);
+
+        let lp = Medium::new("lp", 2, 600.0);
+        assert_eq!(
+            format_layout(&tracks, &lp),
+            "Side A (9:15): 1. Intro 3:45 / 2. Song 5:30\n\
+             Side B (7:50): 3. Ballad 4:30 / 4. Closer 3:20\n"
+        );
+
+        // Spill past the last side is reported, not dropped
+        let single = Medium::new("single", 1, 600.0);
+        assert_eq!(
+            format_layout(&tracks, &single),
+            "Side A (9:15): 1. Intro 3:45 / 2. Song 5:30\n\
+             Unplaced: 3. Ballad 4:30 / 4. Closer 3:20\n"
+        );
+
+        let epic = Tracklist::from(vec![("Epic", 720.0), ("Short", 65.0)]);
+        assert_eq!(
+            format_layout(&epic, &lp),
+            "Side A (12:00): 1. Epic 12:00 (longer than a side)\n\
+             Side B (1:05): 2. Short 1:05\n"
+        );
+    }
+
+    #[test]
+    fn test_to_m3u() {
+        let tracks = Tracklist::from(vec![("Intro", 224.6), ("Hello, World", 245.0)]);
+        let m3u = tracks.to_m3u(|t| format!("music/{}.flac", t.title));
+        assert_eq!(
+            m3u,
+            "#EXTM3U\n\
+             #EXTINF:224.6,Intro\nmusic/Intro.flac\n\
+             #EXTINF:245,Hello, World\nmusic/Hello, World.flac\n"
+        );
+
+        let read = Tracklist::from_m3u_reader(m3u.as_bytes()).unwrap();
+        assert_eq!(read, tracks);
+        let durations: Vec<Duration> = read.0.iter().map(|t| t.duration).collect();
+        assert_eq!(durations, vec![224.6, 245.0]);
+    }
+
+    #[test]
+    fn test_to_cue_sheets() {
+        let medium = Medium::new("test_medium", 2, 600.0)
+            .with_lead_in(2.0)
+            .with_gap(1.5);
+        let tracks = Tracklist::new(vec![
+            Track::new("Intro", 200.2).with_artist("Band"),
+            Track::new("Say \"Hi\"", 300.0),
+            Track::new("Outro", 250.0),
+        ]);
+        let sheets = tracks.to_cue_sheets(&medium, |side| format!("side {}.wav", side_label(side)));
+        assert_eq!(
+            sheets,
+            vec![
+                "FILE \"side A.wav\" WAVE\n  \
+                   TRACK 01 AUDIO\n    \
+                     TITLE \"Intro\"\n    \
+                     PERFORMER \"Band\"\n    \
+                     INDEX 00 00:00:00\n    \
+                     INDEX 01 00:02:00\n  \
+                   TRACK 02 AUDIO\n    \
+                     TITLE \"Say 'Hi'\"\n    \
+                     INDEX 00 03:22:15\n    \
+                     INDEX 01 03:23:53\n",
+                "FILE \"side B.wav\" WAVE\n  \
+                   TRACK 03 AUDIO\n    \
+                     TITLE \"Outro\"\n    \
+                     INDEX 00 00:00:00\n    \
+                     INDEX 01 00:02:00\n",
+            ]
+        );
+    }
+}
@@ -0,0 +1,92 @@
+//! `arbitrary::Arbitrary` implementations for fuzzing, pairing with
+//! `check_invariants` as an oracle.
+
+use arbitrary::{Arbitrary, Result, Unstructured};
+
+use crate::{Constraint, ConstraintKind, Duration, Medium, Track, Tracklist};
+
+/// A finite, non-negative duration (up to about 71 minutes in seconds).
+fn duration(u: &mut Unstructured<'_>) -> Result<Duration> {
+    Ok(f64::from(u.arbitrary::<u32>()?) / 1_000_000.0)
+}
+
+impl<'a> Arbitrary<'a> for Track {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let mut track = Track::new(u.arbitrary::<String>()?, duration(u)?);
+        track.genre = u.arbitrary()?;
+        track.artist = u.arbitrary()?;
+        track.bpm = u.arbitrary()?;
+        track.key = u.arbitrary()?;
+        track.energy = u.arbitrary()?;
+        track.mood = u.arbitrary()?;
+        track.version = u.arbitrary()?;
+        track.id = u.arbitrary()?;
+        track.tags = u.arbitrary()?;
+        track.hidden = u.arbitrary()?;
+        Ok(track)
+    }
+}
+
+impl<'a> Arbitrary<'a> for Tracklist {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(Tracklist::new(u.arbitrary()?))
+    }
+}
+
+impl<'a> Arbitrary<'a> for Medium {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let mut medium = Medium::new(
+            u.arbitrary::<String>()?,
+            u.int_in_range(0..=16)?,
+            duration(u)?,
+        );
+        medium.gap = duration(u)? / 100.0;
+        medium.lead_in = duration(u)? / 100.0;
+        medium.overfill_ratio = f64::from(u.int_in_range(0..=100u8)?) / 100.0;
+        medium.crossfade = duration(u)? / 100.0;
+        medium.max_tracks_per_side = u
+            .arbitrary::<Option<u8>>()?
+            .map(|max| usize::from(max).max(1));
+        medium.min_tracks_per_side = u.int_in_range(0..=3)?;
+        Ok(medium)
+    }
+}
+
+impl<'a> Arbitrary<'a> for Constraint {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        // Weights stay finite and non-negative, in quarters to cover fractional ones.
+        let kind: ConstraintKind = u.arbitrary()?;
+        let weight = f64::from(u.arbitrary::<u16>()?) / 4.0;
+        let mut constraint =
+            Constraint::new(kind, weight).with_priority(usize::from(u.arbitrary::<u8>()?));
+        constraint.hard = u.arbitrary()?;
+        constraint.penalty = u.arbitrary()?;
+        Ok(constraint)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{check_invariants, score_tracklist};
+
+    #[test]
+    fn test_arbitrary_inputs_hold_invariants() {
+        let bytes: Vec<u8> = (0..4096u32)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8)
+            .collect();
+        let mut u = Unstructured::new(&bytes);
+        while !u.is_empty() {
+            let Ok((tracklist, medium, constraints)) =
+                <(Tracklist, Medium, Vec<Constraint>)>::arbitrary(&mut u)
+            else {
+                break;
+            };
+            for track in &tracklist.0 {
+                assert!(track.duration.is_finite() && track.duration >= 0.0);
+            }
+            check_invariants(&tracklist, &medium);
+            score_tracklist(&tracklist, &constraints, &medium);
+        }
+    }
+}
@@ -0,0 +1,471 @@
+//! Reading tracklists from other tools.
+
+use std::io::BufRead;
+
+use crate::{Duration, Track, Tracklist};
+
+/// Why a tracklist couldn't be imported.
+#[derive(Debug)]
+pub enum ImportError {
+    Io(std::io::Error),
+    /// A malformed row, with its one-based line number.
+    Parse {
+        line: usize,
+        message: String,
+    },
+}
+
+impl std::fmt::Display for ImportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImportError::Io(e) => write!(f, "{e}"),
+            ImportError::Parse { line, message } => write!(f, "line {line}: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ImportError::Io(e) => Some(e),
+            ImportError::Parse { .. } => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ImportError {
+    fn from(e: std::io::Error) -> Self {
+        ImportError::Io(e)
+    }
+}
+
+/// Parse a duration given as seconds (`225`, `225.5`), `m:ss` (`3:45`) or
+/// `h:mm:ss` (`1:02:03`). Minutes and seconds after a colon must be below 60.
+pub fn parse_duration(text: &str) -> Option<Duration> {
+    let parts: Vec<&str> = text.trim().split(':').collect();
+    if parts.len() > 3 {
+        return None;
+    }
+    let (last, leading) = parts.split_last()?;
+    let seconds: f64 = last.parse().ok()?;
+    if !seconds.is_finite() || seconds < 0.0 || (!leading.is_empty() && seconds >= 60.0) {
+        return None;
+    }
+    let mut total = 0.0;
+    for (i, part) in leading.iter().enumerate() {
+        let value: u32 = part.parse().ok()?;
+        // Only the leading unit may exceed 59
+        if i > 0 && value >= 60 {
+            return None;
+        }
+        total = total * 60.0 + f64::from(value);
+    }
+    Some(total * 60.0 + seconds)
+}
+
+/// Parse a CUE time, `mm:ss:ff` with 75 frames per second.
+fn parse_cue_time(text: &str) -> Option<Duration> {
+    let mut parts = text.split(':').map(|part| part.parse::<u32>().ok());
+    let (Some(Some(minutes)), Some(Some(seconds)), Some(Some(frames)), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return None;
+    };
+    (seconds < 60 && frames < 75)
+        .then(|| f64::from(minutes) * 60.0 + f64::from(seconds) + f64::from(frames) / 75.0)
+}
+
+/// The first argument of a CUE command: a quoted string, or a bare word.
+fn cue_argument(rest: &str) -> Result<&str, String> {
+    match rest.strip_prefix('"') {
+        Some(quoted) => quoted
+            .split_once('"')
+            .map(|(value, _)| value)
+            .ok_or_else(|| "unterminated string".to_string()),
+        None => Ok(rest.split_whitespace().next().unwrap_or("")),
+    }
+}
+
+/// A `TRACK` of a CUE sheet being read.
+struct CueTrack {
+    file: usize,
+    number: String,
+    title: Option<String>,
+    artist: Option<String>,
+    pregap: Option<Duration>,
+    start: Option<Duration>,
+    line: usize,
+}
+
+/// Split one CSV row into fields. Fields may be quoted, with `""` standing
+/// for a quote inside them.
+fn split_row(row: &str) -> Result<Vec<String>, String> {
+    let mut fields = Vec::new();
+    let mut chars = row.chars().peekable();
+    loop {
+        let mut field = String::new();
+        if chars.peek() == Some(&'"') {
+            chars.next();
+            loop {
+                match chars.next() {
+                    Some('"') if chars.peek() == Some(&'"') => {
+                        chars.next();
+                        field.push('"');
+                    }
+                    Some('"') => break,
+                    Some(c) => field.push(c),
+                    None => return Err("unterminated quoted field".into()),
+                }
+            }
+            match chars.next() {
+                None => {
+                    fields.push(field);
+                    return Ok(fields);
+                }
+                Some(',') => {}
+                Some(c) => return Err(format!("unexpected {c:?} after quoted field")),
+            }
+        } else {
+            loop {
+                match chars.next() {
+                    None => {
+                        fields.push(field.trim().to_string());
+                        return Ok(fields);
+                    }
+                    Some(',') => break,
+                    Some(c) => field.push(c),
+                }
+            }
+            field = field.trim().to_string();
+        }
+        fields.push(field);
+    }
+}
+
+impl Tracklist {
+    /// Read `title,duration` rows, e.g. exported from a spreadsheet.
+    ///
+    /// Durations may be seconds or `m:ss`, see `parse_duration`. A first row
+    /// reading `title,duration` (any case) is taken as a header and skipped,
+    /// as are blank rows; further columns are ignored. Titles may be quoted
+    /// to hold commas.
+    pub fn from_csv_reader(reader: impl BufRead) -> Result<Tracklist, ImportError> {
+        let mut tracks = Vec::new();
+        for (i, row) in reader.lines().enumerate() {
+            let row = row?;
+            if row.trim().is_empty() {
+                continue;
+            }
+            let error = |message: String| ImportError::Parse {
+                line: i + 1,
+                message,
+            };
+            let fields = split_row(&row).map_err(error)?;
+            let [title, duration, ..] = fields.as_slice() else {
+                return Err(error("expected `title,duration`".into()));
+            };
+            if i == 0
+                && title.eq_ignore_ascii_case("title")
+                && duration.eq_ignore_ascii_case("duration")
+            {
+                continue;
+            }
+            let duration = parse_duration(duration)
+                .ok_or_else(|| error(format!("invalid duration {duration:?}")))?;
+            tracks.push(Track::new(title.as_str(), duration));
+        }
+        Ok(Tracklist(tracks))
+    }
+
+    /// Read a CUE sheet, taking titles (and `PERFORMER` as the artist) from
+    /// its tracks and durations from their `INDEX` points.
+    ///
+    /// A track runs from its `INDEX 01` to the next track's `INDEX 00` if it
+    /// has one (so pregaps aren't counted), otherwise to its `INDEX 01`. The
+    /// last track in each `FILE` runs to the end of the file, whose length in
+    /// seconds `file_length` gives from the name as written in the sheet.
+    /// Tracks without a `TITLE` are named `Track NN`. Sheets written by
+    /// `Tracklist::to_cue_sheets` read back with their original durations,
+    /// to the frame.
+    pub fn from_cue_reader(
+        reader: impl BufRead,
+        file_length: impl Fn(&str) -> Option<Duration>,
+    ) -> Result<Tracklist, ImportError> {
+        let mut files: Vec<String> = Vec::new();
+        let mut cue_tracks: Vec<CueTrack> = Vec::new();
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim().trim_start_matches('\u{feff}');
+            let error = |message: String| ImportError::Parse {
+                line: i + 1,
+                message,
+            };
+            let (command, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let rest = rest.trim();
+            match command {
+                "FILE" => files.push(cue_argument(rest).map_err(error)?.to_string()),
+                "TRACK" => {
+                    if files.is_empty() {
+                        return Err(error("TRACK before any FILE".into()));
+                    }
+                    cue_tracks.push(CueTrack {
+                        file: files.len() - 1,
+                        number: cue_argument(rest).map_err(error)?.to_string(),
+                        title: None,
+                        artist: None,
+                        pregap: None,
+                        start: None,
+                        line: i + 1,
+                    });
+                }
+                // Before the first TRACK these describe the whole album
+                "TITLE" | "PERFORMER" | "INDEX" if cue_tracks.is_empty() => {}
+                "TITLE" | "PERFORMER" | "INDEX" => {
+                    let track = cue_tracks.last_mut().expect("checked non-empty");
+                    if command == "INDEX" {
+                        let (number, time) =
+                            rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+                        let time = parse_cue_time(time.trim()).ok_or_else(|| {
+                            error(format!("invalid INDEX time {:?}", time.trim()))
+                        })?;
+                        match number {
+                            "00" | "0" => track.pregap = Some(time),
+                            "01" | "1" => track.start = Some(time),
+                            _ => {}
+                        }
+                    } else {
+                        let value = Some(cue_argument(rest).map_err(error)?.to_string());
+                        if command == "TITLE" {
+                            track.title = value;
+                        } else {
+                            track.artist = value;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let mut tracks = Vec::with_capacity(cue_tracks.len());
+        for (k, cue_track) in cue_tracks.iter().enumerate() {
+            let error = |message: String| ImportError::Parse {
+                line: cue_track.line,
+                message,
+            };
+            let start = cue_track
+                .start
+                .ok_or_else(|| error(format!("track {} has no INDEX 01", cue_track.number)))?;
+            let end = match cue_tracks.get(k + 1) {
+                Some(next) if next.file == cue_track.file => next
+                    .pregap
+                    .or(next.start)
+                    .ok_or_else(|| error(format!("track {} has no INDEX 01", next.number)))?,
+                _ => {
+                    let file = &files[cue_track.file];
+                    file_length(file)
+                        .ok_or_else(|| error(format!("no length for file {file:?}")))?
+                }
+            };
+            if end < start {
+                return Err(error(format!(
+                    "track {} ends before it starts",
+                    cue_track.number
+                )));
+            }
+            let title = cue_track
+                .title
+                .clone()
+                .unwrap_or_else(|| format!("Track {}", cue_track.number));
+            let mut track = Track::new(title, end - start);
+            track.artist = cue_track.artist.clone();
+            tracks.push(track);
+        }
+        Ok(Tracklist(tracks))
+    }
+
+    /// Read an M3U or M3U8 playlist, taking each entry's duration and title
+    /// from its `#EXTINF:<seconds>,<title>` line.
+    ///
+    /// Other `#` lines are ignored, attributes between the duration and the
+    /// comma are skipped, and an entry whose `#EXTINF` has no title is named
+    /// after its file (without directory or extension). Entries without an
+    /// `#EXTINF` line, or with an unknown duration (`-1`), are errors since
+    /// they can't be packed.
+    pub fn from_m3u_reader(reader: impl BufRead) -> Result<Tracklist, ImportError> {
+        let mut tracks = Vec::new();
+        let mut info: Option<(Duration, String)> = None;
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim().trim_start_matches('\u{feff}');
+            let error = |message: String| ImportError::Parse {
+                line: i + 1,
+                message,
+            };
+            if let Some(rest) = line.strip_prefix("#EXTINF:") {
+                let (head, title) = rest.split_once(',').unwrap_or((rest, ""));
+                let duration = head.split_whitespace().next().unwrap_or("");
+                let duration = duration
+                    .parse::<Duration>()
+                    .ok()
+                    .filter(|d| d.is_finite() && *d >= 0.0)
+                    .ok_or_else(|| error(format!("invalid duration {duration:?}")))?;
+                info = Some((duration, title.trim().to_string()));
+            } else if !line.is_empty() && !line.starts_with('#') {
+                let Some((duration, title)) = info.take() else {
+                    return Err(error(format!("no #EXTINF duration for {line:?}")));
+                };
+                let title = if title.is_empty() {
+                    let file = line.rsplit(['/', '\\']).next().unwrap_or(line);
+                    file.rsplit_once('.')
+                        .map_or(file, |(stem, _)| stem)
+                        .to_string()
+                } else {
+                    title
+                };
+                tracks.push(Track::new(title, duration));
+            }
+        }
+        Ok(Tracklist(tracks))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("225"), Some(225.0));
+        assert_eq!(parse_duration(" 3:45 "), Some(225.0));
+        assert_eq!(parse_duration("3:45.5"), Some(225.5));
+        assert_eq!(parse_duration("72:00"), Some(4320.0));
+        assert_eq!(parse_duration("1:02:03"), Some(3723.0));
+        assert_eq!(parse_duration("3:60"), None);
+        assert_eq!(parse_duration("1:60:00"), None);
+        assert_eq!(parse_duration("-5"), None);
+        assert_eq!(parse_duration("3:"), None);
+        assert_eq!(parse_duration("long"), None);
+    }
+
+    #[test]
+    fn test_from_csv_reader() {
+        let csv = "Title,Duration\n\
+                   Intro,1:30\n\
+                   \"Hello, \"\"World\"\"\", 245.5 ,single\n\
+                   \n\
+                   Outro,3:05\n";
+        let tracklist = Tracklist::from_csv_reader(csv.as_bytes()).unwrap();
+        assert_eq!(
+            tracklist.titles(),
+            vec!["Intro", "Hello, \"World\"", "Outro"]
+        );
+        let durations: Vec<Duration> = tracklist.0.iter().map(|t| t.duration).collect();
+        assert_eq!(durations, vec![90.0, 245.5, 185.0]);
+
+        let error = |csv: &str| {
+            Tracklist::from_csv_reader(csv.as_bytes())
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(error("A,1:00\nB,4m"), "line 2: invalid duration \"4m\"");
+        assert_eq!(error("A"), "line 1: expected `title,duration`");
+        assert_eq!(error("\"A,1:00"), "line 1: unterminated quoted field");
+    }
+
+    #[test]
+    fn test_from_m3u_reader() {
+        let m3u = "\u{feff}#EXTM3U\n\
+                   #EXTINF:225,Intro\n\
+                   music/01 Intro.flac\n\
+                   \n\
+                   #EXTINF:245.5 tvg-id=\"x\",Hello, World\n\
+                   # a comment\n\
+                   https://example.com/hello.mp3\n\
+                   #EXTINF:185,\n\
+                   C:\\Music\\Outro.ogg\n";
+        let tracklist = Tracklist::from_m3u_reader(m3u.as_bytes()).unwrap();
+        assert_eq!(tracklist.titles(), vec!["Intro", "Hello, World", "Outro"]);
+        let durations: Vec<Duration> = tracklist.0.iter().map(|t| t.duration).collect();
+        assert_eq!(durations, vec![225.0, 245.5, 185.0]);
+
+        let error = |m3u: &str| {
+            Tracklist::from_m3u_reader(m3u.as_bytes())
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(
+            error("#EXTM3U\nsong.mp3"),
+            "line 2: no #EXTINF duration for \"song.mp3\""
+        );
+        assert_eq!(
+            error("#EXTINF:-1,Stream\nhttp://radio"),
+            "line 1: invalid duration \"-1\""
+        );
+    }
+
+    #[test]
+    fn test_from_cue_reader() {
+        let cue = "REM GENRE Rock\n\
+                   PERFORMER \"Band\"\n\
+                   TITLE \"Album\"\n\
+                   FILE \"side A.wav\" WAVE\n  \
+                     TRACK 01 AUDIO\n    \
+                       TITLE \"Intro\"\n    \
+                       PERFORMER \"Guest\"\n    \
+                       INDEX 01 00:00:00\n  \
+                     TRACK 02 AUDIO\n    \
+                       INDEX 00 03:20:00\n    \
+                       INDEX 01 03:22:00\n\
+                   FILE side_b.wav WAVE\n  \
+                     TRACK 03 AUDIO\n    \
+                       TITLE \"Outro\"\n    \
+                       INDEX 01 00:01:37\n";
+        let lengths = |file: &str| match file {
+            "side A.wav" => Some(400.0),
+            "side_b.wav" => Some(100.0),
+            _ => None,
+        };
+        let tracklist = Tracklist::from_cue_reader(cue.as_bytes(), lengths).unwrap();
+        assert_eq!(tracklist.titles(), vec!["Intro", "Track 02", "Outro"]);
+        let durations: Vec<Duration> = tracklist.0.iter().map(|t| t.duration).collect();
+        assert_eq!(durations, vec![200.0, 198.0, 100.0 - (1.0 + 37.0 / 75.0)]);
+        assert_eq!(tracklist.0[0].artist.as_deref(), Some("Guest"));
+
+        let error = |cue: &str| {
+            Tracklist::from_cue_reader(cue.as_bytes(), |_| None)
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(
+            error("FILE a.wav WAVE\nTRACK 01 AUDIO\nINDEX 01 00:00:00"),
+            "line 2: no length for file \"a.wav\""
+        );
+        assert_eq!(
+            error("FILE a.wav WAVE\nTRACK 01 AUDIO\nINDEX 01 00:61:00"),
+            "line 3: invalid INDEX time \"00:61:00\""
+        );
+        assert_eq!(error("TRACK 01 AUDIO"), "line 1: TRACK before any FILE");
+    }
+
+    #[test]
+    fn test_cue_round_trip() {
+        let medium = crate::Medium::new("test_medium", 2, 600.0)
+            .with_lead_in(2.0)
+            .with_gap(1.5);
+        let tracks = Tracklist::from(vec![("Intro", 200.4), ("Song", 300.0), ("Outro", 250.0)]);
+        let timeline = tracks.timeline(&medium);
+        let sheets = tracks.to_cue_sheets(&medium, |side| format!("side{side}.wav"));
+
+        let side_length = |file: &str| {
+            timeline
+                .iter()
+                .rfind(|e| format!("side{}.wav", e.side) == file)
+                .map(|e| e.start_seconds + e.duration_seconds)
+        };
+        let read = Tracklist::from_cue_reader(sheets.concat().as_bytes(), side_length).unwrap();
+        assert_eq!(read, tracks);
+        for (read, original) in read.0.iter().zip(&tracks.0) {
+            assert!((read.duration - original.duration).abs() < 1.0 / 75.0);
+        }
+    }
+}
@@ -0,0 +1,114 @@
+//! Whole sequencing jobs loaded from config files.
+
+use serde::{Deserialize, Deserializer};
+
+use crate::optimize::best_arrangement;
+use crate::{Constraint, Duration, Medium, Track, Tracklist};
+
+/// A medium, a track pool and the constraints to sequence it by.
+///
+/// Deserializes from JSON like
+///
+/// ```json
+/// {
+///   "medium": { "name": "LP", "sides": 2, "max_duration_per_side": 1320.0 },
+///   "tracks": [{ "title": "Intro", "duration": 225.0, "tags": ["single"] }],
+///   "constraints": [{ "kind": { "AtPosition": ["Intro", 0] }, "weight": 5 }]
+/// }
+/// ```
+///
+/// Optional track metadata, medium gap/lead-in/overfill and constraint
+/// priority may be left out. Each constraint kind is an object keyed by the
+/// `ConstraintKind` variant name, holding its arguments in order.
+#[derive(Debug, Deserialize)]
+pub struct SequencingJob {
+    pub medium: Medium,
+    pub tracks: Vec<Track>,
+    #[serde(default)]
+    pub constraints: Vec<Constraint>,
+}
+
+impl SequencingJob {
+    /// Parse a job from JSON. Unknown constraint kinds, missing fields and
+    /// malformed or negative durations are reported with their location.
+    pub fn from_json_str(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    /// The best arrangement of the pool and its score, via `best_arrangement`;
+    /// `None` if no ordering is feasible.
+    pub fn solve(&self) -> Option<(Tracklist, f64)> {
+        best_arrangement(&self.tracks, &self.constraints, &self.medium)
+    }
+}
+
+/// Track durations must be finite and non-negative.
+pub(crate) fn non_negative_duration<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Duration, D::Error> {
+    let duration = Duration::deserialize(deserializer)?;
+    if duration.is_finite() && duration >= 0.0 {
+        Ok(duration)
+    } else {
+        Err(serde::de::Error::custom(format!(
+            "invalid duration {duration}, expected a non-negative number"
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_solve_json_job() {
+        let job = SequencingJob::from_json_str(
+            r#"{
+                "medium": { "name": "LP", "sides": 2, "max_duration_per_side": 10.0 },
+                "tracks": [
+                    { "title": "A", "duration": 4.0 },
+                    { "title": "B", "duration": 5.0, "genre": "rock" },
+                    { "title": "C", "duration": 3.0, "tags": ["single"] },
+                    { "title": "D", "duration": 6.0 }
+                ],
+                "constraints": [
+                    { "kind": { "AtPosition": ["C", 0] }, "weight": 3 },
+                    { "kind": { "Adjacent": ["A", "D"] }, "weight": 2 },
+                    { "kind": { "OnSameSide": ["B", "C"] }, "weight": 1, "priority": 1 }
+                ]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(job.tracks[1].genre.as_deref(), Some("rock"));
+        assert!(job.tracks[2].has_tag("single"));
+
+        let (best, score) = job.solve().unwrap();
+        assert_eq!(best.titles(), vec!["C", "B", "A", "D"]);
+        assert_eq!(score, 6.0);
+    }
+
+    #[test]
+    fn test_job_errors() {
+        let unknown_kind = r#"{
+            "medium": { "name": "LP", "sides": 2, "max_duration_per_side": 10.0 },
+            "tracks": [{ "title": "A", "duration": 4.0 }],
+            "constraints": [{ "kind": { "Nearby": ["A", "B"] }, "weight": 1 }]
+        }"#;
+        let err = SequencingJob::from_json_str(unknown_kind).unwrap_err();
+        assert!(err.to_string().contains("unknown variant `Nearby`"));
+
+        let bad_duration = r#"{
+            "medium": { "name": "LP", "sides": 2, "max_duration_per_side": 10.0 },
+            "tracks": [{ "title": "A", "duration": "3:45" }]
+        }"#;
+        let err = SequencingJob::from_json_str(bad_duration).unwrap_err();
+        assert!(err.to_string().contains("expected f64"));
+
+        let negative = r#"{
+            "medium": { "name": "LP", "sides": 2, "max_duration_per_side": 10.0 },
+            "tracks": [{ "title": "A", "duration": -1.0 }]
+        }"#;
+        let err = SequencingJob::from_json_str(negative).unwrap_err();
+        assert!(err.to_string().contains("invalid duration -1"));
+    }
+}
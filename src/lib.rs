@@ -1,12 +1,53 @@
 use itertools::{Itertools, Permutations}; // for permutations()
+use std::sync::Arc;
+
+#[cfg(feature = "audio")]
+pub mod audio;
+pub mod dsl;
+pub mod export;
+#[cfg(feature = "arbitrary")]
+mod fuzzing;
+pub mod import;
+#[cfg(feature = "serde")]
+pub mod job;
+pub mod optimize;
+#[cfg(feature = "serde")]
+pub mod project;
+mod rng;
+mod sha256;
 
 /// Duration type (seconds, using f64)
 pub type Duration = f64;
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Track {
     pub title: String,
+    #[cfg_attr(
+        feature = "serde",
+        serde(deserialize_with = "job::non_negative_duration")
+    )]
     pub duration: Duration,
+    pub genre: Option<String>,
+    pub artist: Option<String>,
+    pub bpm: Option<f64>,
+    /// Musical key, free-form (e.g. "8A" in Camelot notation).
+    pub key: Option<String>,
+    /// Perceived energy/intensity, any consistent scale.
+    pub energy: Option<f64>,
+    /// Mood on a numeric scale (e.g. valence), used by `MoodTrajectory`.
+    pub mood: Option<f64>,
+    /// Version label (e.g. "live", "acoustic") distinguishing tracks sharing a title.
+    pub version: Option<String>,
+    /// Stable identity in the original track pool, used by the `*Idx` constraint kinds.
+    pub id: Option<usize>,
+    /// Free-form labels (e.g. "single", "ballad").
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub tags: Vec<String>,
+    /// Hidden track or pregap audio: it still takes up side time, but is
+    /// skipped when counting positions for `AtPosition` and the adjacency kinds.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub hidden: bool,
 }
 
 impl Track {
@@ -14,12 +55,142 @@ impl Track {
         Self {
             title: title.into(),
             duration,
+            genre: None,
+            artist: None,
+            bpm: None,
+            key: None,
+            energy: None,
+            mood: None,
+            version: None,
+            id: None,
+            tags: Vec::new(),
+            hidden: false,
+        }
+    }
+
+    /// Builder: set the genre.
+    pub fn with_genre<T: Into<String>>(mut self, genre: T) -> Self {
+        self.genre = Some(genre.into());
+        self
+    }
+
+    /// Builder: set the artist.
+    pub fn with_artist<T: Into<String>>(mut self, artist: T) -> Self {
+        self.artist = Some(artist.into());
+        self
+    }
+
+    /// Builder: set the tempo in beats per minute.
+    pub fn with_bpm(mut self, bpm: f64) -> Self {
+        self.bpm = Some(bpm);
+        self
+    }
+
+    /// Builder: set the musical key.
+    pub fn with_key<T: Into<String>>(mut self, key: T) -> Self {
+        self.key = Some(key.into());
+        self
+    }
+
+    /// Builder: set the energy level.
+    pub fn with_energy(mut self, energy: f64) -> Self {
+        self.energy = Some(energy);
+        self
+    }
+
+    /// Builder: set the mood.
+    pub fn with_mood(mut self, mood: f64) -> Self {
+        self.mood = Some(mood);
+        self
+    }
+
+    /// Builder: set the version label.
+    pub fn with_version<T: Into<String>>(mut self, version: T) -> Self {
+        self.version = Some(version.into());
+        self
+    }
+
+    /// Builder: set the pool id.
+    pub fn with_id(mut self, id: usize) -> Self {
+        self.id = Some(id);
+        self
+    }
+
+    /// Builder: add a tag.
+    pub fn with_tag<T: Into<String>>(mut self, tag: T) -> Self {
+        self.tags.push(tag.into());
+        self
+    }
+
+    /// Builder: mark the track as hidden (see `hidden`).
+    pub fn with_hidden(mut self) -> Self {
+        self.hidden = true;
+        self
+    }
+
+    /// Whether the track matches a tag selector.
+    ///
+    /// `"field=value"` compares one of the `genre`, `artist`, `key` or
+    /// `version` fields (unknown fields never match); anything else must be
+    /// one of the track's `tags`.
+    pub fn has_tag(&self, selector: &str) -> bool {
+        match selector.split_once('=') {
+            Some((field, value)) => {
+                let field = match field {
+                    "genre" => &self.genre,
+                    "artist" => &self.artist,
+                    "key" => &self.key,
+                    "version" => &self.version,
+                    _ => return false,
+                };
+                field.as_deref() == Some(value)
+            }
+            None => self.tags.iter().any(|t| t == selector),
         }
     }
 }
 
+/// Equality compares only titles, matching `Tracklist` and the crate's
+/// title-keyed constraints: two tracks with the same title but different
+/// durations or metadata are considered equal.
+impl PartialEq for Track {
+    fn eq(&self, other: &Self) -> bool {
+        self.title == other.title
+    }
+}
+impl Eq for Track {}
+
+/// Hashes only the title, consistent with `PartialEq`.
+impl std::hash::Hash for Track {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.title.hash(state);
+    }
+}
+
+/// Index outside the valid range of a tracklist operation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexError {
+    pub index: usize,
+    pub len: usize,
+}
+
+impl std::fmt::Display for IndexError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "index {} out of range for tracklist of length {}",
+            self.index, self.len
+        )
+    }
+}
+
+impl std::error::Error for IndexError {}
+
 /// A Tracklist wrapper (ordered list of tracks).
+///
+/// Serializes as a plain array of tracks.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Tracklist(pub Vec<Track>);
 
 impl Tracklist {
@@ -36,6 +207,273 @@ impl Tracklist {
     pub fn duration(&self) -> Duration {
         self.0.iter().map(|t| t.duration).sum()
     }
+
+    /// Position of the first track whose title matches `title` under `options`.
+    fn position_of(&self, title: &str, options: &MatchOptions) -> Option<usize> {
+        self.0.iter().position(|t| options.matches(&t.title, title))
+    }
+
+    /// Tracks in order, skipping hidden ones (see `Track::hidden`).
+    fn audible(&self) -> impl Iterator<Item = &Track> + Clone {
+        self.0.iter().filter(|t| !t.hidden)
+    }
+
+    /// Like `position_of`, but over `audible` tracks only.
+    fn audible_position_of(&self, title: &str, options: &MatchOptions) -> Option<usize> {
+        self.audible()
+            .position(|t| options.matches(&t.title, title))
+    }
+
+    /// Position of the track with the given `id`.
+    fn position_of_id(&self, id: usize) -> Option<usize> {
+        self.0.iter().position(|t| t.id == Some(id))
+    }
+
+    /// Titles that appear more than once, each reported once in order of first appearance.
+    pub fn duplicate_titles(&self) -> Vec<String> {
+        let mut duplicates: Vec<String> = Vec::new();
+        for (i, track) in self.0.iter().enumerate() {
+            if self.0[..i].iter().any(|t| t.title == track.title)
+                && !duplicates.contains(&track.title)
+            {
+                duplicates.push(track.title.clone());
+            }
+        }
+        duplicates
+    }
+
+    /// Set every track's `id` to its current index, making this order the
+    /// reference pool for `AtPositionIdx`, `AdjacentIdx` and `OnSameSideIdx`.
+    pub fn assign_ids(&mut self) {
+        for (i, track) in self.0.iter_mut().enumerate() {
+            track.id = Some(i);
+        }
+    }
+
+    /// Append a track at the end.
+    pub fn push(&mut self, track: Track) {
+        self.0.push(track);
+    }
+
+    /// Remove and return the first track with the given title.
+    pub fn remove_by_title(&mut self, title: &str) -> Option<Track> {
+        let pos = self.0.iter().position(|t| t.title == title)?;
+        Some(self.0.remove(pos))
+    }
+
+    /// Insert a track at `index`, shifting later tracks back. `index` may equal the length.
+    pub fn insert_at(&mut self, index: usize, track: Track) -> Result<(), IndexError> {
+        if index > self.0.len() {
+            return Err(IndexError {
+                index,
+                len: self.0.len(),
+            });
+        }
+        self.0.insert(index, track);
+        Ok(())
+    }
+
+    /// Move the track at `from` to `to`, shifting the tracks in between
+    /// (like dragging a row in a playlist) rather than swapping.
+    pub fn move_track(&mut self, from: usize, to: usize) -> Result<(), IndexError> {
+        let len = self.0.len();
+        for index in [from, to] {
+            if index >= len {
+                return Err(IndexError { index, len });
+            }
+        }
+        let track = self.0.remove(from);
+        self.0.insert(to, track);
+        Ok(())
+    }
+
+    /// Sum of `similarity` over adjacent pairs; higher means a smoother album.
+    pub fn coherence(&self, similarity: impl Fn(&Track, &Track) -> f64) -> f64 {
+        self.0.windows(2).map(|w| similarity(&w[0], &w[1])).sum()
+    }
+
+    /// Index at which inserting `track` scores highest, with that score.
+    /// Ties resolve to the earliest index.
+    pub fn best_insertion(
+        &self,
+        track: Track,
+        constraints: &[Constraint],
+        medium: &Medium,
+    ) -> (usize, f64) {
+        let mut candidate = self.clone();
+        candidate.0.insert(0, track);
+        let mut best = (0, score_tracklist(&candidate, constraints, medium));
+
+        for index in 1..candidate.0.len() {
+            candidate.0.swap(index - 1, index);
+            let score = score_tracklist(&candidate, constraints, medium);
+            if score > best.1 {
+                best = (index, score);
+            }
+        }
+        best
+    }
+
+    /// The side total after appending `track` to a side holding `self`, or
+    /// `None` if it would overflow `medium`'s effective capacity or the side
+    /// already holds `max_tracks_per_side` tracks.
+    ///
+    /// `self` is the side's tracks so far and `current_side_total` their used
+    /// time, starting from `medium.lead_in` for an empty side. The gap (less
+    /// any crossfade with the last track) is added unless the side is empty,
+    /// matching the packing in `Medium::side_indices`.
+    pub fn append_cost(
+        &self,
+        current_side_total: Duration,
+        track: &Track,
+        medium: &Medium,
+    ) -> Option<Duration> {
+        if medium
+            .max_tracks_per_side
+            .is_some_and(|max| self.0.len() >= max)
+        {
+            return None;
+        }
+        let spacing = self
+            .0
+            .last()
+            .map_or(0.0, |last| medium.spacing(last, track));
+        let total = current_side_total + spacing + track.duration;
+        (total <= medium.effective_capacity()).then_some(total)
+    }
+
+    /// SHA-256 digest of the ordered titles, ignoring durations and other
+    /// metadata, for deduplicating orderings. Each title is length-prefixed
+    /// so different splits of the same characters never collide.
+    pub fn order_hash(&self) -> [u8; 32] {
+        let mut bytes = Vec::new();
+        for track in &self.0 {
+            bytes.extend_from_slice(&(track.title.len() as u64).to_le_bytes());
+            bytes.extend_from_slice(track.title.as_bytes());
+        }
+        sha256::sha256(&bytes)
+    }
+
+    /// Where to break this ordering into `sides` non-empty contiguous sides so
+    /// the longest side is as short as possible, ignoring any medium.
+    ///
+    /// Returns the `sides - 1` indices of the tracks that start a new side, in
+    /// increasing order, or `None` if `sides` is 0 or exceeds the track count.
+    /// Side length is the plain sum of durations. Uses O(sides * n^2) dynamic
+    /// programming; ties between equally good splits resolve deterministically.
+    pub fn optimal_side_splits(&self, sides: usize) -> Option<Vec<usize>> {
+        let n = self.0.len();
+        if sides == 0 || sides > n {
+            return None;
+        }
+        let mut prefix = vec![0.0; n + 1];
+        for (i, track) in self.0.iter().enumerate() {
+            prefix[i + 1] = prefix[i] + track.duration;
+        }
+
+        // best[k][i]: smallest longest side splitting the first i tracks into
+        // k sides; start[k][i]: where the last of those sides starts.
+        let mut best = vec![vec![f64::INFINITY; n + 1]; sides + 1];
+        let mut start = vec![vec![0; n + 1]; sides + 1];
+        best[0][0] = 0.0;
+        for k in 1..=sides {
+            for i in k..=n {
+                for j in k - 1..i {
+                    let longest = best[k - 1][j].max(prefix[i] - prefix[j]);
+                    if longest < best[k][i] {
+                        best[k][i] = longest;
+                        start[k][i] = j;
+                    }
+                }
+            }
+        }
+
+        let mut breaks = Vec::with_capacity(sides - 1);
+        let mut end = n;
+        for k in (2..=sides).rev() {
+            end = start[k][end];
+            breaks.push(end);
+        }
+        breaks.reverse();
+        Some(breaks)
+    }
+
+    /// Side indices in the order an automatic record changer plays them.
+    ///
+    /// Changer sets pair the outer sides on each disc, so a `medium.sides`
+    /// set comes off the changer as 1, n, 2, n-1, ... (for a double LP:
+    /// sides 0, 3, 1, 2). Sides this tracklist leaves empty are skipped.
+    pub fn changer_play_order(&self, medium: &Medium) -> Vec<usize> {
+        let occupied = medium.split_sides(self).len();
+        let n = medium.sides;
+        (0..n.div_ceil(2))
+            .flat_map(|i| [i, n - 1 - i])
+            .dedup()
+            .filter(|&side| side < occupied)
+            .collect()
+    }
+
+    /// The tracks in the order a record changer plays them: side by side in
+    /// `changer_play_order`, keeping each side's running order.
+    pub fn changer_tracklist(&self, medium: &Medium) -> Tracklist {
+        let sides = medium.split_sides(self);
+        Tracklist::new(
+            self.changer_play_order(medium)
+                .into_iter()
+                .flat_map(|side| sides[side].iter().map(|&t| t.clone()))
+                .collect(),
+        )
+    }
+
+    /// Changes going from `self` (old) to `other` (new), keyed by title.
+    /// Duplicate titles resolve to their first occurrence.
+    pub fn diff<'a>(&'a self, other: &'a Tracklist) -> TracklistDiff<'a> {
+        let strict = MatchOptions::default();
+        let added = other
+            .0
+            .iter()
+            .filter(|t| self.position_of(&t.title, &strict).is_none())
+            .collect();
+        let mut removed = Vec::new();
+        let mut moved = Vec::new();
+
+        for (from, track) in self.0.iter().enumerate() {
+            match other.position_of(&track.title, &strict) {
+                None => removed.push(track),
+                Some(to) if to != from => moved.push(TrackMove {
+                    title: &track.title,
+                    from,
+                    to,
+                }),
+                Some(_) => {}
+            }
+        }
+
+        TracklistDiff {
+            added,
+            removed,
+            moved,
+        }
+    }
+}
+
+/// A track present in both tracklists of a diff, at different positions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrackMove<'a> {
+    pub title: &'a str,
+    pub from: usize,
+    pub to: usize,
+}
+
+/// Title-keyed changes from one tracklist to another, see `Tracklist::diff`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TracklistDiff<'a> {
+    /// Tracks only in the new tracklist, in its order.
+    pub added: Vec<&'a Track>,
+    /// Tracks only in the old tracklist, in its order.
+    pub removed: Vec<&'a Track>,
+    /// Tracks in both at different positions, in old-tracklist order.
+    pub moved: Vec<TrackMove<'a>>,
 }
 
 /// Equality compares only the ordered titles to avoid f64 Eq issues.
@@ -87,282 +525,4429 @@ impl<'a> Iterator for TracklistPermutations<'a> {
     }
 }
 
-/// Physical medium with sides and max duration per side.
-pub struct Medium {
-    pub name: String,
-    pub sides: usize,
-    pub max_duration_per_side: Duration,
+/// The permutations of `tracks` consistent with a set of position pins, in
+/// `TracklistPermutations` order.
+///
+/// Pins are `AtPosition` and `AtPositionIdx` kinds (others are ignored);
+/// `AtPosition` counts audible positions as in scoring. Tracks are placed one
+/// slot at a time and a branch is abandoned as soon as a slot breaks a pin, so
+/// pinning one of `n` tracks leaves `(n - 1)!` orderings to generate instead
+/// of `n!`. Yields exactly the permutations satisfying every pin.
+pub struct PinnedPermutations<'a> {
+    tracks: &'a [Track],
+    pins: Vec<ConstraintKind>,
+    /// Indices into `tracks` placed so far.
+    order: Vec<usize>,
+    used: Vec<bool>,
+    /// Next index to try in the slot after `order`.
+    cursor: usize,
+    done: bool,
 }
 
-impl Medium {
-    /// Check if tracklist fits medium sides without splitting tracks.
-    pub fn fits(&self, tracklist: &Tracklist) -> bool {
-        let durations: Vec<Duration> = tracklist.0.iter().map(|t| t.duration).collect();
-
-        if tracklist.duration() > self.sides as f64 * self.max_duration_per_side {
-            return false;
+impl<'a> PinnedPermutations<'a> {
+    pub fn new(tracks: &'a [Track], pins: &[ConstraintKind]) -> Self {
+        let audible = tracks.iter().filter(|t| !t.hidden).count();
+        let pins: Vec<ConstraintKind> = pins
+            .iter()
+            .filter(|kind| {
+                matches!(
+                    kind,
+                    ConstraintKind::AtPosition(..) | ConstraintKind::AtPositionIdx(..)
+                )
+            })
+            .cloned()
+            .collect();
+        // A pin past the end can never be met.
+        let unreachable = pins.iter().any(|kind| match kind {
+            ConstraintKind::AtPosition(_, pos) => *pos >= audible,
+            ConstraintKind::AtPositionIdx(_, pos) => *pos >= tracks.len(),
+            _ => false,
+        });
+        Self {
+            tracks,
+            pins,
+            order: Vec::with_capacity(tracks.len()),
+            used: vec![false; tracks.len()],
+            cursor: 0,
+            done: unreachable,
         }
+    }
 
-        let mut sides_used = 1;
-        let mut current_sum = 0.0;
-
-        for &d in &durations {
-            if d > self.max_duration_per_side {
-                return false; // track too long for a side
+    /// Whether `tracks[index]` may go in the next free slot.
+    fn allows(&self, index: usize) -> bool {
+        let track = &self.tracks[index];
+        let slot = self.order.len();
+        let audible_slot = self
+            .order
+            .iter()
+            .filter(|&&i| !self.tracks[i].hidden)
+            .count();
+        self.pins.iter().all(|kind| match kind {
+            ConstraintKind::AtPosition(title, pos) => {
+                track.hidden || *pos != audible_slot || track.title == *title
             }
-            if current_sum + d <= self.max_duration_per_side {
-                current_sum += d;
-            } else {
-                sides_used += 1;
-                if sides_used > self.sides {
-                    return false;
-                }
-                current_sum = d;
+            ConstraintKind::AtPositionIdx(id, pos) => *pos != slot || track.id == Some(*id),
+            _ => true,
+        })
+    }
+
+    /// Undo the last placement and resume after it, or finish.
+    fn backtrack(&mut self) {
+        match self.order.pop() {
+            Some(last) => {
+                self.used[last] = false;
+                self.cursor = last + 1;
             }
+            None => self.done = true,
         }
-        true
     }
+}
 
-    /// Returns true if the two tracks are on the same side when split by duration.
-    pub fn on_same_side(&self, tracklist: &Tracklist, t1: &str, t2: &str) -> bool {
-        let mut sides_used = 0;
-        let mut current_sum = 0.0;
-        let mut side_indices = Vec::with_capacity(tracklist.0.len());
+impl<'a> Iterator for PinnedPermutations<'a> {
+    type Item = Vec<&'a Track>;
 
-        for track in &tracklist.0 {
-            if current_sum + track.duration <= self.max_duration_per_side {
-                current_sum += track.duration;
-            } else {
-                sides_used += 1;
-                current_sum = track.duration;
+    fn next(&mut self) -> Option<Self::Item> {
+        let n = self.tracks.len();
+        while !self.done {
+            if self.order.len() == n {
+                let tracks = self.tracks;
+                let perm = self.order.iter().map(|&i| &tracks[i]).collect();
+                self.backtrack();
+                return Some(perm);
+            }
+            match (self.cursor..n).find(|&i| !self.used[i] && self.allows(i)) {
+                Some(i) => {
+                    self.order.push(i);
+                    self.used[i] = true;
+                    self.cursor = 0;
+                }
+                None => self.backtrack(),
             }
-            side_indices.push(sides_used);
         }
+        None
+    }
+}
 
-        let pos1 = tracklist.0.iter().position(|t| t.title == t1);
-        let pos2 = tracklist.0.iter().position(|t| t.title == t2);
+/// `n!`, or `None` if it doesn't fit in a `u128` (n > 34).
+fn factorial(n: usize) -> Option<u128> {
+    (1..=n as u128).try_fold(1u128, |acc, k| acc.checked_mul(k))
+}
 
-        if let (Some(i1), Some(i2)) = (pos1, pos2) {
-            side_indices[i1] == side_indices[i2]
-        } else {
-            false // one or both tracks not found
+/// The permutation of `tracks` at `index` in `TracklistPermutations` order
+/// (lexicographic by position in `tracks`), or `None` if `index >= n!`.
+///
+/// Together with `permutation_index` this lets a search be split into index
+/// ranges or resumed from a known index without replaying the iterator.
+pub fn nth_permutation(tracks: &[Track], index: u128) -> Option<Tracklist> {
+    let order = nth_order(tracks.len(), index)?;
+    Some(Tracklist::new(
+        order.into_iter().map(|i| tracks[i].clone()).collect(),
+    ))
+}
+
+/// Positions `0..n` permuted as in `nth_permutation`.
+fn nth_order(n: usize, mut index: u128) -> Option<Vec<usize>> {
+    if factorial(n).is_some_and(|total| index >= total) {
+        return None;
+    }
+    let mut remaining: Vec<usize> = (0..n).collect();
+    let mut order = Vec::with_capacity(n);
+    while !remaining.is_empty() {
+        // A block bigger than any u128 means the index is inside the first one.
+        let block = factorial(remaining.len() - 1);
+        let digit = block.map_or(0, |b| (index / b) as usize);
+        if let Some(b) = block {
+            index %= b;
         }
+        order.push(remaining.remove(digit));
     }
+    Some(order)
 }
 
-/// Kind of constraint (without weight).
-#[derive(Debug, Clone)]
-pub enum ConstraintKind {
-    AtPosition(String, usize),  // (track title, position)
-    Adjacent(String, String),   // (track1, track2)
-    OnSameSide(String, String), // (track1, track2)
+/// Advance `order` to the next permutation in lexicographic order, applying
+/// the same rearrangement to `follow` (of equal length). Returns `false`, and
+/// leaves both unchanged, if `order` was the last permutation.
+fn next_order<T>(order: &mut [usize], follow: &mut [T]) -> bool {
+    let Some(pivot) = (1..order.len()).rev().find(|&i| order[i - 1] < order[i]) else {
+        return false;
+    };
+    let successor = (pivot..order.len())
+        .rev()
+        .find(|&i| order[i] > order[pivot - 1])
+        .expect("order[pivot] is larger than the pivot");
+    order.swap(pivot - 1, successor);
+    follow.swap(pivot - 1, successor);
+    order[pivot..].reverse();
+    follow[pivot..].reverse();
+    true
 }
 
-/// Constraint with explicit weight.
-#[derive(Debug, Clone)]
-pub struct Constraint {
-    pub kind: ConstraintKind,
-    pub weight: usize,
+/// Every permutation of `tracks` in `TracklistPermutations` order, without
+/// allocating per permutation.
+///
+/// Each step rearranges an internal buffer in place and lends out the index
+/// order together with a `Tracklist` kept in step with it (tracks are moved,
+/// never cloned), ready to score. Lending means it can't be an `Iterator`;
+/// loop with `while let Some((order, tracklist)) = perms.next_permutation()`.
+pub struct IndexPermutations {
+    order: Vec<usize>,
+    tracklist: Tracklist,
+    started: bool,
 }
 
-/// Score the tracklist against constraints and medium.
-pub fn score_tracklist(
-    tracklist: &Tracklist,
-    constraints: &[Constraint],
-    medium: &Medium,
-) -> usize {
-    if !medium.fits(tracklist) {
-        return 0; // No score if it doesn't fit the medium
+impl IndexPermutations {
+    pub fn new(tracks: &[Track]) -> Self {
+        Self::starting_at(tracks, 0).expect("index 0 always exists")
     }
 
-    let mut score = 0;
+    /// Start at the permutation `nth_permutation(tracks, index)` would
+    /// return, or `None` if `index >= n!`.
+    pub fn starting_at(tracks: &[Track], index: u128) -> Option<Self> {
+        let order = nth_order(tracks.len(), index)?;
+        let tracklist = Tracklist::new(order.iter().map(|&i| tracks[i].clone()).collect());
+        Some(Self {
+            order,
+            tracklist,
+            started: false,
+        })
+    }
 
-    for constraint in constraints {
-        match &constraint.kind {
-            ConstraintKind::AtPosition(title, pos) => {
-                if let Some(track) = tracklist.0.get(*pos) {
-                    if &track.title == title {
-                        score += constraint.weight;
-                    }
-                }
-            }
-            ConstraintKind::Adjacent(t1, t2) => {
-                if tracklist
-                    .0
-                    .windows(2)
-                    .any(|w| w[0].title == *t1 && w[1].title == *t2)
-                {
-                    score += constraint.weight;
-                }
-            }
-            ConstraintKind::OnSameSide(t1, t2) => {
-                if medium.on_same_side(tracklist, t1, t2) {
-                    score += constraint.weight;
-                }
+    /// The next permutation as indices into `tracks` and as a tracklist, or
+    /// `None` once every permutation has been seen.
+    pub fn next_permutation(&mut self) -> Option<(&[usize], &Tracklist)> {
+        if self.started {
+            if !next_order(&mut self.order, &mut self.tracklist.0) {
+                return None;
             }
+        } else {
+            self.started = true;
         }
+        Some((&self.order, &self.tracklist))
     }
+}
 
-    score
+/// The index of `tracklist` in `TracklistPermutations::new(tracks)` order,
+/// the inverse of `nth_permutation`.
+///
+/// Tracks are matched by title (duplicates take the earliest unused track).
+/// `None` if `tracklist` isn't a permutation of `tracks` or its index
+/// doesn't fit in a `u128`.
+pub fn permutation_index(tracks: &[Track], tracklist: &Tracklist) -> Option<u128> {
+    if tracks.len() != tracklist.0.len() {
+        return None;
+    }
+    let mut remaining: Vec<&Track> = tracks.iter().collect();
+    let mut index = 0u128;
+    for track in &tracklist.0 {
+        let digit = remaining.iter().position(|t| *t == track)?;
+        remaining.remove(digit);
+        if digit > 0 {
+            let block = factorial(remaining.len())?;
+            index = index.checked_add(block.checked_mul(digit as u128)?)?;
+        }
+    }
+    Some(index)
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::cmp::Ordering;
+/// Fill statistics for one side of a packed tracklist.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SideStat {
+    /// Time used on the side, including lead-in and gaps.
+    pub used: Duration,
+    /// Nominal capacity, so an overfilled side reports a ratio above 1.0.
+    pub capacity: Duration,
+    /// `used / capacity`.
+    pub fill_ratio: f64,
+    pub track_count: usize,
+}
 
-    // For testing: lex order on track titles.
-    impl PartialOrd for Tracklist {
-        fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-            Some(self.cmp(other))
+/// Why a manual side split doesn't work, see `Medium::split_at_breaks`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FitError {
+    /// A break is out of order, repeated, or outside `1..len`.
+    InvalidBreak { index: usize },
+    /// The breaks ask for more sides than the medium has.
+    TooManySides { requested: usize, available: usize },
+    /// A side's used time exceeds the effective capacity.
+    SideOverflow {
+        side: usize,
+        used: Duration,
+        capacity: Duration,
+    },
+    /// A side holds fewer than `min_tracks_per_side` or more than
+    /// `max_tracks_per_side` tracks.
+    SideTrackCount { side: usize, count: usize },
+}
+
+impl std::fmt::Display for FitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FitError::InvalidBreak { index } => write!(f, "invalid side break at index {index}"),
+            FitError::TooManySides {
+                requested,
+                available,
+            } => write!(
+                f,
+                "{requested} sides requested but the medium has {available}"
+            ),
+            FitError::SideOverflow {
+                side,
+                used,
+                capacity,
+            } => write!(f, "side {side} uses {used} of {capacity}"),
+            FitError::SideTrackCount { side, count } => {
+                write!(
+                    f,
+                    "side {side} holds {count} tracks, outside the medium's limits"
+                )
+            }
         }
     }
-    impl Ord for Tracklist {
-        fn cmp(&self, other: &Self) -> Ordering {
-            self.0
-                .iter()
-                .map(|t| &t.title)
-                .cmp(other.0.iter().map(|t| &t.title))
+}
+
+impl std::error::Error for FitError {}
+
+/// Physical medium with sides and max duration per side.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Medium {
+    pub name: String,
+    pub sides: usize,
+    pub max_duration_per_side: Duration,
+    /// Silence between consecutive tracks on a side; counts toward capacity.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub gap: Duration,
+    /// Offset before the first track of each side; counts toward capacity.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub lead_in: Duration,
+    /// Tolerated overrun per side as a fraction of `max_duration_per_side` (default 0.0).
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub overfill_ratio: f64,
+    /// Overlap between consecutive tracks on the same side, subtracted from the
+    /// side's used time. Never crosses a side break, and is capped at the
+    /// shorter of the two tracks (default 0.0).
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub crossfade: Duration,
+    /// Most tracks a side can hold; packing starts a new side once a side is
+    /// full, whatever time is left (default: no limit).
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub max_tracks_per_side: Option<usize>,
+    /// Fewest tracks every used side must hold for the tracklist to fit
+    /// (default 0).
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub min_tracks_per_side: usize,
+}
+
+impl Medium {
+    pub fn new<T: Into<String>>(name: T, sides: usize, max_duration_per_side: Duration) -> Self {
+        Self {
+            name: name.into(),
+            sides,
+            max_duration_per_side,
+            gap: 0.0,
+            lead_in: 0.0,
+            overfill_ratio: 0.0,
+            crossfade: 0.0,
+            max_tracks_per_side: None,
+            min_tracks_per_side: 0,
         }
     }
 
-    #[test]
-    fn test_tracklist_permutations() {
-        let tracks = vec![
-            Track::new("A", 3.5),
-            Track::new("B", 4.0),
-            Track::new("C", 2.75),
-        ];
+    /// 12" LP: two sides of 22 minutes, with durations in seconds.
+    pub fn lp() -> Self {
+        Self::new("LP", 2, 22.0 * 60.0)
+    }
 
-        let mut perms: Vec<Tracklist> = TracklistPermutations::new(&tracks)
-            .map(|perm| Tracklist::new(perm.into_iter().cloned().collect()))
-            .collect();
+    /// Double LP: four sides of 22 minutes, with durations in seconds.
+    pub fn double_lp() -> Self {
+        Self::new("Double LP", 4, 22.0 * 60.0)
+    }
 
-        assert_eq!(perms.len(), 6);
+    /// CD: a single 80-minute side, with durations in seconds.
+    pub fn cd() -> Self {
+        Self::new("CD", 1, 80.0 * 60.0)
+    }
 
-        let mut expected: Vec<Tracklist> = vec![
-            Tracklist::from(vec![("A", 3.5), ("B", 4.0), ("C", 2.75)]),
-            Tracklist::from(vec![("A", 3.5), ("C", 2.75), ("B", 4.0)]),
-            Tracklist::from(vec![("B", 4.0), ("A", 3.5), ("C", 2.75)]),
-            Tracklist::from(vec![("B", 4.0), ("C", 2.75), ("A", 3.5)]),
-            Tracklist::from(vec![("C", 2.75), ("A", 3.5), ("B", 4.0)]),
-            Tracklist::from(vec![("C", 2.75), ("B", 4.0), ("A", 3.5)]),
-        ];
+    /// Total nominal capacity: `sides * max_duration_per_side`.
+    pub fn total_capacity(&self) -> Duration {
+        self.sides as f64 * self.max_duration_per_side
+    }
 
-        perms.sort();
-        expected.sort();
+    /// Builder: allow each side to overrun by `overfill_ratio` (e.g. 0.05 for 5%).
+    ///
+    /// There is no separate `Medium::with_overfill(sides, max, ratio)`
+    /// constructor; chain this onto `Medium::new` or a preset instead, e.g.
+    /// `Medium::lp().with_overfill(0.05)`, like the other `with_*` options.
+    pub fn with_overfill(mut self, overfill_ratio: f64) -> Self {
+        self.overfill_ratio = overfill_ratio;
+        self
+    }
 
-        assert_eq!(perms, expected);
+    /// Capacity actually used for packing: `max_duration_per_side * (1.0 + overfill_ratio)`.
+    pub fn effective_capacity(&self) -> Duration {
+        self.max_duration_per_side * (1.0 + self.overfill_ratio)
     }
 
-    #[test]
-    fn test_medium_fits_and_same_side() {
-        let tracks = Tracklist::from(vec![("A", 10.0), ("B", 8.0), ("C", 12.0), ("D", 7.0)]);
-        let medium = Medium {
-            name: "test_medium".into(),
-            sides: 2,
-            max_duration_per_side: 20.0,
-        };
-        assert!(medium.fits(&tracks)); // (A+B=18), (C+D=19)
-        assert!(medium.on_same_side(&tracks, "A", "B"));
-        assert!(medium.on_same_side(&tracks, "C", "D"));
-        assert!(!medium.on_same_side(&tracks, "B", "C"));
-        assert!(!medium.on_same_side(&tracks, "A", "D"));
+    /// Builder: set the gap between tracks.
+    pub fn with_gap(mut self, gap: Duration) -> Self {
+        self.gap = gap;
+        self
+    }
 
-        let medium2 = Medium {
-            name: "test_medium".into(),
-            sides: 2,
-            max_duration_per_side: 15.0,
-        };
-        assert!(!medium2.fits(&tracks)); // no 2-side split possible with max 15
+    /// Builder: set the per-side lead-in.
+    pub fn with_lead_in(mut self, lead_in: Duration) -> Self {
+        self.lead_in = lead_in;
+        self
+    }
 
-        let tracks2 = Tracklist::from(vec![("A", 10.0), ("B", 5.0), ("C", 7.0), ("D", 7.0)]);
-        let medium3 = Medium {
-            name: "test_medium".into(),
-            sides: 3,
-            max_duration_per_side: 12.0,
-        };
-        assert!(medium3.fits(&tracks2)); // (A=10), (B+C=12), (D=7)
-        assert!(medium3.on_same_side(&tracks2, "B", "C"));
-        assert!(!medium3.on_same_side(&tracks2, "A", "B"));
+    /// Builder: cap the number of tracks per side (at least 1).
+    pub fn with_max_tracks_per_side(mut self, max: usize) -> Self {
+        self.max_tracks_per_side = Some(max.max(1));
+        self
+    }
 
-        let tracks3 = Tracklist::from(vec![("A", 21.0), ("B", 5.0)]);
-        let medium4 = Medium {
-            name: "test_medium".into(),
-            sides: 2,
-            max_duration_per_side: 20.0,
-        };
-        assert!(!medium4.fits(&tracks3));
+    /// Builder: require at least `min` tracks on every used side.
+    pub fn with_min_tracks_per_side(mut self, min: usize) -> Self {
+        self.min_tracks_per_side = min;
+        self
     }
 
-    #[test]
-    fn test_score_tracklist() {
-        let medium = Medium {
-            name: "test_medium".into(),
-            sides: 2,
-            max_duration_per_side: 10.0,
-        };
+    /// Builder: set the crossfade between tracks.
+    pub fn with_crossfade(mut self, crossfade: Duration) -> Self {
+        self.crossfade = crossfade;
+        self
+    }
 
+    /// Time from the end of `prev` to the start of `next` on the same side:
+    /// the gap, less the crossfade overlap (at most the shorter track).
+    fn spacing(&self, prev: &Track, next: &Track) -> Duration {
+        self.gap - self.crossfade.min(prev.duration).min(next.duration)
+    }
+
+    /// Check if tracklist fits medium sides without splitting tracks.
+    pub fn fits(&self, tracklist: &Tracklist) -> bool {
+        self.fits_any_order(tracklist) && self.fits_sides(&self.side_indices(tracklist), false)
+    }
+
+    /// Whether `tracklist` could still be the start of a fitting one: like
+    /// `fits`, but its last side may be short of `min_tracks_per_side`.
+    fn fits_prefix(&self, tracklist: &Tracklist) -> bool {
+        self.fits_any_order(tracklist) && self.fits_sides(&self.side_indices(tracklist), true)
+    }
+
+    /// The checks behind `fits` on a side assignment from `side_indices`.
+    fn fits_sides(&self, sides: &[usize], last_open: bool) -> bool {
+        let used = sides.last().map_or(0, |&side| side + 1);
+        if used > self.sides {
+            return false;
+        }
+        let closed = used.saturating_sub(usize::from(last_open));
+        sides
+            .iter()
+            .dedup_with_count()
+            .take(closed)
+            .all(|(count, _)| count >= self.min_tracks_per_side)
+    }
+
+    /// The checks behind `fits` that don't depend on the track order.
+    fn fits_any_order(&self, tracklist: &Tracklist) -> bool {
+        let capacity = self.effective_capacity();
+        // Crossfades can make the plain sum an overestimate.
+        if self.crossfade == 0.0 && tracklist.duration() > self.sides as f64 * capacity {
+            return false;
+        }
+        // No track too long for a side
+        tracklist
+            .0
+            .iter()
+            .all(|t| self.lead_in + t.duration <= capacity)
+    }
+
+    /// Side index of each track under greedy packing: tracks fill a side in
+    /// order and a new side starts when the next track would overflow it, or
+    /// when the side already holds `max_tracks_per_side` tracks.
+    /// Each side starts with `lead_in` and tracks on a side are `gap` apart,
+    /// less any `crossfade` overlap.
+    ///
+    /// This is the packing used by `fits`, `on_same_side` and `split_sides`.
+    /// It does not cap the number of sides at `self.sides`.
+    pub fn side_indices(&self, tracklist: &Tracklist) -> Vec<usize> {
+        let capacity = self.effective_capacity();
+        let mut side = 0;
+        let mut current_sum = self.lead_in;
+        let mut on_side = 0;
+        let mut indices = Vec::with_capacity(tracklist.0.len());
+
+        for (i, track) in tracklist.0.iter().enumerate() {
+            if i > 0 {
+                let spacing = self.spacing(&tracklist.0[i - 1], track);
+                let full = self.max_tracks_per_side.is_some_and(|max| on_side >= max);
+                if full || current_sum + spacing + track.duration > capacity {
+                    side += 1;
+                    current_sum = self.lead_in;
+                    on_side = 0;
+                } else {
+                    current_sum += spacing;
+                }
+            }
+            current_sum += track.duration;
+            on_side += 1;
+            indices.push(side);
+        }
+        indices
+    }
+
+    /// Group the tracks by side using the same packing as `side_indices`.
+    pub fn split_sides<'a>(&self, tracklist: &'a Tracklist) -> Vec<Vec<&'a Track>> {
+        let mut sides: Vec<Vec<&Track>> = Vec::new();
+        for (track, side) in tracklist.0.iter().zip(self.side_indices(tracklist)) {
+            if side == sides.len() {
+                sides.push(Vec::new());
+            }
+            sides[side].push(track);
+        }
+        sides
+    }
+
+    /// Per-side fill statistics, using the same packing as `split_sides`.
+    pub fn side_stats(&self, tracklist: &Tracklist) -> Vec<SideStat> {
+        self.split_sides(tracklist)
+            .iter()
+            .map(|tracks| {
+                let used = self.side_used(tracks);
+                SideStat {
+                    used,
+                    capacity: self.max_duration_per_side,
+                    fill_ratio: used / self.max_duration_per_side,
+                    track_count: tracks.len(),
+                }
+            })
+            .collect()
+    }
+
+    /// Used time of a side holding `tracks`: lead-in, durations and spacing.
+    fn side_used(&self, tracks: &[&Track]) -> Duration {
+        self.lead_in
+            + tracks.iter().map(|t| t.duration).sum::<Duration>()
+            + tracks
+                .windows(2)
+                .map(|w| self.spacing(w[0], w[1]))
+                .sum::<Duration>()
+    }
+
+    /// Group the tracks into sides at fixed `breaks` instead of greedy packing.
+    ///
+    /// `breaks` lists the indices of the tracks that start a new side, in
+    /// strictly increasing order within `1..tracklist.0.len()`. Each side's
+    /// used time (as in `side_stats`) must stay within the effective capacity,
+    /// each side's track count within `min_tracks_per_side` and
+    /// `max_tracks_per_side`, and there may be at most `self.sides` sides.
+    pub fn split_at_breaks<'a>(
+        &self,
+        tracklist: &'a Tracklist,
+        breaks: &[usize],
+    ) -> Result<Vec<Vec<&'a Track>>, FitError> {
+        let n = tracklist.0.len();
+        let mut start = 0;
+        for &index in breaks {
+            if index <= start || index >= n {
+                return Err(FitError::InvalidBreak { index });
+            }
+            start = index;
+        }
+        if breaks.len() + 1 > self.sides {
+            return Err(FitError::TooManySides {
+                requested: breaks.len() + 1,
+                available: self.sides,
+            });
+        }
+
+        let bounds: Vec<usize> = std::iter::once(0)
+            .chain(breaks.iter().copied())
+            .chain(std::iter::once(n))
+            .collect();
+        let sides: Vec<Vec<&Track>> = bounds
+            .windows(2)
+            .map(|w| tracklist.0[w[0]..w[1]].iter().collect())
+            .collect();
+        let capacity = self.effective_capacity();
+        for (side, tracks) in sides.iter().enumerate() {
+            let used = self.side_used(tracks);
+            if used > capacity {
+                return Err(FitError::SideOverflow {
+                    side,
+                    used,
+                    capacity,
+                });
+            }
+            let count = tracks.len();
+            if count < self.min_tracks_per_side
+                || self.max_tracks_per_side.is_some_and(|max| count > max)
+            {
+                return Err(FitError::SideTrackCount { side, count });
+            }
+        }
+        Ok(sides)
+    }
+
+    /// Per packed side, how far its used time exceeds `max_duration_per_side`
+    /// (0.0 when within budget), for turning overruns into a soft penalty
+    /// instead of the all-or-nothing `fits`.
+    ///
+    /// Uses the same packing and used time as `side_stats`, so sides can only
+    /// run over by the `overfill_ratio` allowance, or by a single track too
+    /// long for any side. Sides spilling past `self.sides` are included.
+    pub fn side_overrun(&self, tracklist: &Tracklist) -> Vec<Duration> {
+        self.side_stats(tracklist)
+            .iter()
+            .map(|stat| (stat.used - self.max_duration_per_side).max(0.0))
+            .collect()
+    }
+
+    /// Returns true if the two tracks are on the same side when split by duration.
+    /// Duplicate titles resolve to their first occurrence.
+    pub fn on_same_side(&self, tracklist: &Tracklist, t1: &str, t2: &str) -> bool {
+        self.on_same_side_with(tracklist, t1, t2, &MatchOptions::default())
+    }
+
+    /// `on_same_side` with title matching relaxed by `options`.
+    pub fn on_same_side_with(
+        &self,
+        tracklist: &Tracklist,
+        t1: &str,
+        t2: &str,
+        options: &MatchOptions,
+    ) -> bool {
+        let side_indices = self.side_indices(tracklist);
+
+        let pos1 = tracklist.position_of(t1, options);
+        let pos2 = tracklist.position_of(t2, options);
+
+        if let (Some(i1), Some(i2)) = (pos1, pos2) {
+            side_indices[i1] == side_indices[i2]
+        } else {
+            false // one or both tracks not found
+        }
+    }
+}
+
+/// A constraint the library doesn't know about, wrapped with
+/// `ConstraintKind::custom` to mix with the built-in kinds.
+///
+/// Any `Fn(&Tracklist, &Medium) -> bool` closure is one, earning its full
+/// weight when it returns `true`. Implement the trait directly for partial
+/// credit.
+pub trait ConstraintEval: Send + Sync {
+    /// Fraction of the weight earned by `tracklist`, in `0.0..=1.0`.
+    fn satisfaction(&self, tracklist: &Tracklist, medium: &Medium) -> f64;
+}
+
+impl<F> ConstraintEval for F
+where
+    F: Fn(&Tracklist, &Medium) -> bool + Send + Sync,
+{
+    fn satisfaction(&self, tracklist: &Tracklist, medium: &Medium) -> f64 {
+        if self(tracklist, medium) { 1.0 } else { 0.0 }
+    }
+}
+
+/// Shared handle to a `ConstraintEval`, held by `ConstraintKind::Custom`.
+#[derive(Clone)]
+pub struct CustomConstraint(Arc<dyn ConstraintEval>);
+
+impl std::fmt::Debug for CustomConstraint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("CustomConstraint(..)")
+    }
+}
+
+/// Kind of constraint (without weight).
+///
+/// The `*Idx` kinds identify tracks by `Track::id` rather than title, which
+/// survives duplicate titles and skips title lookups. Ids travel with the
+/// tracks through `TracklistPermutations`, so for `best_arrangement` and the
+/// other searches give the pool ids first (e.g. `Tracklist::assign_ids`);
+/// tracks without an id never match.
+///
+/// The position kinds (`AtPosition`, `InPositionRange`, `WithinFirst`,
+/// `WithinLast`, `PositionParity`, `ClosesAlbum`) and the neighbor kinds
+/// (`Adjacent`, `AdjacentEither`, `NotAdjacent`, `BpmWithin`) count positions
+/// over audible tracks only: hidden tracks (`Track::hidden`) are skipped, so
+/// position 0 is the first non-hidden track and two tracks with only hidden
+/// ones between them are adjacent. A hidden track itself never satisfies the
+/// positive kinds, and is never anyone's neighbor. Every other kind, and all
+/// packing (`Medium::fits`, `split_sides`, `on_same_side`), still sees hidden
+/// tracks at their raw index and counts their duration.
+///
+/// `Custom` holds a user-defined evaluator; it can't be serialized or
+/// deserialized (serializing one is an error), and the searches treat it as
+/// depending on the whole order.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum ConstraintKind {
+    AtPosition(String, usize),          // (track title, position)
+    Adjacent(String, String),           // (track1 then track2; see AdjacentEither)
+    OnSameSide(String, String),         // (track1, track2)
+    OpenerNotGenre(String),             // (genre the first track must not have)
+    BpmWithin(String, String, f64),     // (track1, track2, max BPM difference)
+    EnergyCurve(Vec<f64>),              // (target energy per position, normalized to 0..=1)
+    FirstOnSide(String, usize),         // (track title, side index)
+    LastOnSide(String, usize),          // (track title, side index)
+    SideTrackCountArc,                  // (per-side track counts rise then fall)
+    WithinFirst(String, usize),         // (track title, number of leading slots)
+    WithinLast(String, usize),          // (track title, number of trailing slots)
+    SeparateVersions(String),           // (base title shared by every version)
+    AtPositionIdx(usize, usize),        // (track id, position)
+    AdjacentIdx(usize, usize),          // (track1 id, track2 id)
+    OnSameSideIdx(usize, usize),        // (track1 id, track2 id)
+    MaxTagRun(String, usize), // (tag selector, see `Track::has_tag`; max consecutive matches)
+    SymmetricDuration(Duration), // (max duration difference between track i and track n-1-i)
+    TagSequence(Vec<String>), // (tag selector per leading slot, "*" matches any track)
+    PositionParity(String, bool), // (track title, true for an even index, false for odd)
+    MoodTrajectory(f64),      // (desired mood change between consecutive tracks)
+    MaxShortPerSide(Duration, usize), // (tracks shorter than this are short; max short tracks per side)
+    AdjacentEither(String, String),   // (track1, track2, neighbors in either order)
+    OnSameSideCentered(String, String), // (track1, track2, credit grows toward the side's center)
+    NearSideBoundary(String),         // (track title, ending a side or opening the next)
+    #[cfg_attr(feature = "serde", serde(skip))]
+    #[cfg_attr(feature = "arbitrary", arbitrary(skip))]
+    Custom(CustomConstraint), // (user-defined evaluator, see `ConstraintKind::custom`)
+    NotAdjacent(String, String),      // (track1, track2, both present but never neighbors)
+    NotOnSameSide(String, String),    // (track1, track2, both present on different sides)
+    Before(String, String), // (track1, track2, track1 anywhere before track2; swap them for "after")
+    OpensSide(String),      // (track title, first on any side; see `FirstOnSide` for a given side)
+    ClosesSide(String),     // (track title, last on any side; see `LastOnSide` for a given side)
+    OnSide(String, usize),  // (track title, side index)
+    Group(Vec<String>, bool), // (track titles played consecutively, true to also keep the listed order)
+    InPositionRange(String, usize, usize), // (track title, first and last allowed position, inclusive)
+    ClosesAlbum(String),                   // (track title, last audible track)
+    Separated(String, String, usize),      // (track1, track2, min tracks between them)
+}
+
+/// Constraint with explicit weight.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Constraint {
+    pub kind: ConstraintKind,
+    /// Score earned when fully satisfied. May be fractional, but should be
+    /// finite and non-negative.
+    pub weight: f64,
+    /// Lexicographic priority level; lower values are optimized first (default 0).
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub priority: usize,
+    /// A hard constraint must be fully satisfied: like not fitting the
+    /// medium, violating it makes the whole tracklist infeasible (see
+    /// `is_feasible`). Still earns its weight when satisfied.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub hard: bool,
+    /// A penalty constraint describes something to avoid: it earns its weight
+    /// when its kind is *not* met (proportional kinds earn the unmet share).
+    /// Up to the constant sum of penalty weights this is the same as
+    /// subtracting the weight when it is met, which `score_tracklist_signed`
+    /// reports. A hard penalty must be avoided outright.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub penalty: bool,
+}
+
+/// How constraint titles are compared against track titles.
+///
+/// The default is strict, byte-for-byte equality.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MatchOptions {
+    /// Compare titles ignoring case.
+    pub case_insensitive: bool,
+    /// Ignore leading and trailing whitespace.
+    pub trim: bool,
+}
+
+impl MatchOptions {
+    /// Whether two titles match under these options.
+    pub fn matches(&self, a: &str, b: &str) -> bool {
+        let (a, b) = if self.trim {
+            (a.trim(), b.trim())
+        } else {
+            (a, b)
+        };
+        if self.case_insensitive {
+            a.to_lowercase() == b.to_lowercase()
+        } else {
+            a == b
+        }
+    }
+}
+
+impl ConstraintKind {
+    /// A user-defined constraint, e.g.
+    /// `ConstraintKind::custom(|t: &Tracklist, _: &Medium| t.0.len() > 3)`.
+    pub fn custom(eval: impl ConstraintEval + 'static) -> Self {
+        ConstraintKind::Custom(CustomConstraint(Arc::new(eval)))
+    }
+
+    /// Track titles named by this constraint, in argument order.
+    fn titles(&self) -> Vec<&str> {
+        match self {
+            ConstraintKind::AtPosition(title, _)
+            | ConstraintKind::FirstOnSide(title, _)
+            | ConstraintKind::LastOnSide(title, _)
+            | ConstraintKind::WithinFirst(title, _)
+            | ConstraintKind::WithinLast(title, _)
+            | ConstraintKind::PositionParity(title, _)
+            | ConstraintKind::OnSide(title, _)
+            | ConstraintKind::InPositionRange(title, ..)
+            | ConstraintKind::ClosesAlbum(title)
+            | ConstraintKind::OpensSide(title)
+            | ConstraintKind::ClosesSide(title)
+            | ConstraintKind::NearSideBoundary(title)
+            | ConstraintKind::SeparateVersions(title) => vec![title],
+            ConstraintKind::Group(titles, _) => titles.iter().map(String::as_str).collect(),
+            ConstraintKind::Adjacent(t1, t2)
+            | ConstraintKind::AdjacentEither(t1, t2)
+            | ConstraintKind::NotAdjacent(t1, t2)
+            | ConstraintKind::OnSameSide(t1, t2)
+            | ConstraintKind::NotOnSameSide(t1, t2)
+            | ConstraintKind::Before(t1, t2)
+            | ConstraintKind::Separated(t1, t2, _)
+            | ConstraintKind::OnSameSideCentered(t1, t2)
+            | ConstraintKind::BpmWithin(t1, t2, _) => vec![t1, t2],
+            _ => Vec::new(),
+        }
+    }
+
+    /// Positions of the tracks this constraint is about: the named titles
+    /// (first occurrence, when present), the opener for `OpenerNotGenre`, and
+    /// every track for whole-album kinds like `EnergyCurve`.
+    fn referenced_positions(&self, tracklist: &Tracklist, options: &MatchOptions) -> Vec<usize> {
+        let find = |title: &str| tracklist.position_of(title, options);
+        match self {
+            ConstraintKind::AtPosition(title, _)
+            | ConstraintKind::FirstOnSide(title, _)
+            | ConstraintKind::LastOnSide(title, _)
+            | ConstraintKind::WithinFirst(title, _)
+            | ConstraintKind::WithinLast(title, _)
+            | ConstraintKind::PositionParity(title, _)
+            | ConstraintKind::OnSide(title, _)
+            | ConstraintKind::InPositionRange(title, ..)
+            | ConstraintKind::ClosesAlbum(title)
+            | ConstraintKind::OpensSide(title)
+            | ConstraintKind::ClosesSide(title)
+            | ConstraintKind::NearSideBoundary(title) => find(title).into_iter().collect(),
+            ConstraintKind::Adjacent(t1, t2)
+            | ConstraintKind::AdjacentEither(t1, t2)
+            | ConstraintKind::NotAdjacent(t1, t2)
+            | ConstraintKind::OnSameSide(t1, t2)
+            | ConstraintKind::NotOnSameSide(t1, t2)
+            | ConstraintKind::Before(t1, t2)
+            | ConstraintKind::Separated(t1, t2, _)
+            | ConstraintKind::OnSameSideCentered(t1, t2)
+            | ConstraintKind::BpmWithin(t1, t2, _) => {
+                find(t1).into_iter().chain(find(t2)).collect()
+            }
+            ConstraintKind::Group(titles, _) => {
+                titles.iter().filter_map(|title| find(title)).collect()
+            }
+            ConstraintKind::SeparateVersions(title) => tracklist
+                .0
+                .iter()
+                .enumerate()
+                .filter(|(_, t)| options.matches(&t.title, title))
+                .map(|(i, _)| i)
+                .collect(),
+            ConstraintKind::AtPositionIdx(id, _) => {
+                tracklist.position_of_id(*id).into_iter().collect()
+            }
+            ConstraintKind::AdjacentIdx(id1, id2) | ConstraintKind::OnSameSideIdx(id1, id2) => {
+                tracklist
+                    .position_of_id(*id1)
+                    .into_iter()
+                    .chain(tracklist.position_of_id(*id2))
+                    .collect()
+            }
+            ConstraintKind::MaxTagRun(selector, _) => tracklist
+                .0
+                .iter()
+                .enumerate()
+                .filter(|(_, t)| t.has_tag(selector))
+                .map(|(i, _)| i)
+                .collect(),
+            ConstraintKind::MaxShortPerSide(short, _) => tracklist
+                .0
+                .iter()
+                .enumerate()
+                .filter(|(_, t)| t.duration < *short)
+                .map(|(i, _)| i)
+                .collect(),
+            ConstraintKind::OpenerNotGenre(_) => (0..tracklist.0.len().min(1)).collect(),
+            ConstraintKind::TagSequence(pattern) => {
+                (0..tracklist.0.len().min(pattern.len())).collect()
+            }
+            ConstraintKind::EnergyCurve(_)
+            | ConstraintKind::SideTrackCountArc
+            | ConstraintKind::SymmetricDuration(_)
+            | ConstraintKind::MoodTrajectory(_)
+            | ConstraintKind::Custom(_) => (0..tracklist.0.len()).collect(),
+        }
+    }
+
+    /// Whether swapping positions `i` and `j` of `tracklist` could change this
+    /// constraint's satisfaction. `repacked` says whether the swap changes the
+    /// side layout, and `sides` is the layout before it. May over-report, but
+    /// never misses a change.
+    fn touched_by_swap(
+        &self,
+        tracklist: &Tracklist,
+        (i, j): (usize, usize),
+        repacked: bool,
+        sides: &[usize],
+    ) -> bool {
+        let (a, b) = (&tracklist.0[i], &tracklist.0[j]);
+        let names_swapped = || self.titles().iter().any(|t| a.title == *t || b.title == *t);
+        let has_id = |ids: &[usize]| ids.iter().any(|id| a.id == Some(*id) || b.id == Some(*id));
+        let crosses_sides = repacked || sides[i] != sides[j];
+        match self {
+            // Audible positions shift when a hidden track swaps with a visible one.
+            ConstraintKind::AtPosition(..)
+            | ConstraintKind::ClosesAlbum(_)
+            | ConstraintKind::Adjacent(..)
+            | ConstraintKind::AdjacentEither(..)
+            | ConstraintKind::NotAdjacent(..)
+            | ConstraintKind::BpmWithin(..)
+            | ConstraintKind::InPositionRange(..)
+            | ConstraintKind::WithinFirst(..)
+            | ConstraintKind::WithinLast(..)
+            | ConstraintKind::PositionParity(..) => a.hidden != b.hidden || names_swapped(),
+            ConstraintKind::Before(..)
+            | ConstraintKind::Separated(..)
+            | ConstraintKind::Group(..)
+            | ConstraintKind::SeparateVersions(_) => names_swapped(),
+            ConstraintKind::OnSameSide(..)
+            | ConstraintKind::NotOnSameSide(..)
+            | ConstraintKind::OnSide(..) => repacked || crosses_sides && names_swapped(),
+            ConstraintKind::FirstOnSide(..)
+            | ConstraintKind::LastOnSide(..)
+            | ConstraintKind::OpensSide(_)
+            | ConstraintKind::ClosesSide(_)
+            | ConstraintKind::NearSideBoundary(_) => repacked || names_swapped(),
+            ConstraintKind::AtPositionIdx(id, _) => has_id(&[*id]),
+            ConstraintKind::AdjacentIdx(id1, id2) => has_id(&[*id1, *id2]),
+            ConstraintKind::OnSameSideIdx(id1, id2) => {
+                repacked || crosses_sides && has_id(&[*id1, *id2])
+            }
+            ConstraintKind::OpenerNotGenre(_) => i.min(j) == 0,
+            ConstraintKind::TagSequence(pattern) => i.min(j) < pattern.len(),
+            ConstraintKind::SideTrackCountArc => repacked,
+            ConstraintKind::MaxShortPerSide(..) => crosses_sides,
+            // Centrality moves whenever anything on the side is reordered.
+            ConstraintKind::OnSameSideCentered(..)
+            | ConstraintKind::EnergyCurve(_)
+            | ConstraintKind::SymmetricDuration(_)
+            | ConstraintKind::MoodTrajectory(_)
+            | ConstraintKind::MaxTagRun(..)
+            | ConstraintKind::Custom(_) => true,
+        }
+    }
+
+    /// Fraction of the weight earned by `tracklist`, in `0.0..=1.0`.
+    /// Most kinds are all-or-nothing; `EnergyCurve`, `MoodTrajectory` and
+    /// `OnSameSideCentered` are proportional, and `Custom` is whatever its
+    /// evaluator says.
+    fn satisfaction(&self, tracklist: &Tracklist, medium: &Medium, options: &MatchOptions) -> f64 {
+        let is = |track: &Track, title: &str| options.matches(&track.title, title);
+        let met = match self {
+            ConstraintKind::AtPosition(title, pos) => tracklist
+                .audible()
+                .nth(*pos)
+                .is_some_and(|track| is(track, title)),
+            ConstraintKind::InPositionRange(title, min, max) => tracklist
+                .audible_position_of(title, options)
+                .is_some_and(|pos| (*min..=*max).contains(&pos)),
+            ConstraintKind::ClosesAlbum(title) => tracklist
+                .audible()
+                .last()
+                .is_some_and(|track| is(track, title)),
+            ConstraintKind::WithinFirst(title, n) => tracklist
+                .audible_position_of(title, options)
+                .is_some_and(|pos| pos < *n),
+            ConstraintKind::WithinLast(title, n) => tracklist
+                .audible_position_of(title, options)
+                .is_some_and(|pos| pos >= tracklist.audible().count().saturating_sub(*n)),
+            ConstraintKind::Separated(t1, t2, min_gap) => {
+                match (
+                    tracklist.position_of(t1, options),
+                    tracklist.position_of(t2, options),
+                ) {
+                    (Some(i1), Some(i2)) => i1.abs_diff(i2) > *min_gap,
+                    _ => false,
+                }
+            }
+            ConstraintKind::Group(titles, ordered) => {
+                let positions: Option<Vec<usize>> = titles
+                    .iter()
+                    .map(|title| tracklist.position_of(title, options))
+                    .collect();
+                positions.is_some_and(|positions| {
+                    match (positions.iter().min(), positions.iter().max()) {
+                        (Some(min), Some(max)) => {
+                            max - min + 1 == positions.len()
+                                && positions.iter().all_unique()
+                                && (!ordered || positions.windows(2).all(|w| w[0] < w[1]))
+                        }
+                        _ => false, // an empty group
+                    }
+                })
+            }
+            ConstraintKind::Before(t1, t2) => {
+                match (
+                    tracklist.position_of(t1, options),
+                    tracklist.position_of(t2, options),
+                ) {
+                    (Some(i1), Some(i2)) => i1 < i2,
+                    _ => false,
+                }
+            }
+            ConstraintKind::PositionParity(title, even) => tracklist
+                .audible_position_of(title, options)
+                .is_some_and(|pos| (pos % 2 == 0) == *even),
+            ConstraintKind::Adjacent(t1, t2) => tracklist
+                .audible()
+                .tuple_windows()
+                .any(|(a, b)| is(a, t1) && is(b, t2)),
+            ConstraintKind::AdjacentEither(t1, t2) => tracklist
+                .audible()
+                .tuple_windows()
+                .any(|(a, b)| (is(a, t1) && is(b, t2)) || (is(a, t2) && is(b, t1))),
+            ConstraintKind::OnSameSide(t1, t2) => {
+                medium.on_same_side_with(tracklist, t1, t2, options)
+            }
+            ConstraintKind::NotAdjacent(t1, t2) => {
+                tracklist.position_of(t1, options).is_some()
+                    && tracklist.position_of(t2, options).is_some()
+                    && !tracklist
+                        .audible()
+                        .tuple_windows()
+                        .any(|(a, b)| (is(a, t1) && is(b, t2)) || (is(a, t2) && is(b, t1)))
+            }
+            ConstraintKind::NotOnSameSide(t1, t2) => {
+                match (
+                    tracklist.position_of(t1, options),
+                    tracklist.position_of(t2, options),
+                ) {
+                    (Some(i1), Some(i2)) => {
+                        let sides = medium.side_indices(tracklist);
+                        sides[i1] != sides[i2]
+                    }
+                    _ => false,
+                }
+            }
+            ConstraintKind::BpmWithin(t1, t2, tolerance) => {
+                // Only scored when t1 directly precedes t2 and both have a BPM.
+                tracklist.audible().tuple_windows().any(|(x, y)| {
+                    is(x, t1)
+                        && is(y, t2)
+                        && matches!((x.bpm, y.bpm), (Some(a), Some(b)) if (a - b).abs() <= *tolerance)
+                })
+            }
+            ConstraintKind::FirstOnSide(title, side) => medium
+                .split_sides(tracklist)
+                .get(*side)
+                .and_then(|tracks| tracks.first())
+                .is_some_and(|track| is(track, title)),
+            ConstraintKind::LastOnSide(title, side) => medium
+                .split_sides(tracklist)
+                .get(*side)
+                .and_then(|tracks| tracks.last())
+                .is_some_and(|track| is(track, title)),
+            ConstraintKind::OnSide(title, side) => tracklist
+                .position_of(title, options)
+                .is_some_and(|pos| medium.side_indices(tracklist)[pos] == *side),
+            ConstraintKind::OpensSide(title) => medium
+                .split_sides(tracklist)
+                .iter()
+                .any(|tracks| tracks.first().is_some_and(|track| is(track, title))),
+            ConstraintKind::ClosesSide(title) => medium
+                .split_sides(tracklist)
+                .iter()
+                .any(|tracks| tracks.last().is_some_and(|track| is(track, title))),
+            ConstraintKind::NearSideBoundary(title) => {
+                // Only breaks between two sides count, not the album's ends.
+                let sides = medium.side_indices(tracklist);
+                tracklist.position_of(title, options).is_some_and(|pos| {
+                    (pos > 0 && sides[pos - 1] != sides[pos])
+                        || sides.get(pos + 1).is_some_and(|&next| next != sides[pos])
+                })
+            }
+            ConstraintKind::SideTrackCountArc => {
+                let counts: Vec<usize> = medium
+                    .split_sides(tracklist)
+                    .iter()
+                    .map(|side| side.len())
+                    .collect();
+                is_unimodal(&counts)
+            }
+            ConstraintKind::MaxShortPerSide(short, cap) => medium
+                .split_sides(tracklist)
+                .iter()
+                .all(|side| side.iter().filter(|t| t.duration < *short).count() <= *cap),
+            ConstraintKind::SeparateVersions(_) => {
+                // Every version of the title counts, whatever its `version` label.
+                let positions = self.referenced_positions(tracklist, options);
+                !positions.is_empty() && positions.windows(2).all(|w| w[1] - w[0] >= 2)
+            }
+            ConstraintKind::AtPositionIdx(id, pos) => {
+                tracklist.0.get(*pos).is_some_and(|t| t.id == Some(*id))
+            }
+            ConstraintKind::AdjacentIdx(id1, id2) => tracklist
+                .0
+                .windows(2)
+                .any(|w| w[0].id == Some(*id1) && w[1].id == Some(*id2)),
+            ConstraintKind::OnSameSideIdx(id1, id2) => {
+                match (
+                    tracklist.position_of_id(*id1),
+                    tracklist.position_of_id(*id2),
+                ) {
+                    (Some(i1), Some(i2)) => {
+                        let sides = medium.side_indices(tracklist);
+                        sides[i1] == sides[i2]
+                    }
+                    _ => false,
+                }
+            }
+            ConstraintKind::MaxTagRun(selector, cap) => {
+                let mut run = 0;
+                tracklist.0.iter().all(|t| {
+                    run = if t.has_tag(selector) { run + 1 } else { 0 };
+                    run <= *cap
+                })
+            }
+            ConstraintKind::SymmetricDuration(tolerance) => {
+                // An odd count's middle track is its own mirror and always passes.
+                let tracks = &tracklist.0;
+                tracks
+                    .iter()
+                    .zip(tracks.iter().rev())
+                    .take(tracks.len() / 2)
+                    .all(|(a, b)| (a.duration - b.duration).abs() <= *tolerance)
+            }
+            ConstraintKind::TagSequence(pattern) => {
+                tracklist.0.len() >= pattern.len()
+                    && pattern
+                        .iter()
+                        .zip(&tracklist.0)
+                        .all(|(tag, track)| tag == "*" || track.has_tag(tag))
+            }
+            ConstraintKind::EnergyCurve(target) => return energy_curve_fit(tracklist, target),
+            ConstraintKind::Custom(eval) => {
+                return eval.0.satisfaction(tracklist, medium).clamp(0.0, 1.0);
+            }
+            ConstraintKind::MoodTrajectory(slope) => return mood_trajectory_fit(tracklist, *slope),
+            ConstraintKind::OnSameSideCentered(t1, t2) => {
+                return side_centrality(tracklist, medium, t1, t2, options);
+            }
+            ConstraintKind::OpenerNotGenre(genre) => {
+                // A missing genre counts as "not this genre"; an empty list has no opener.
+                tracklist
+                    .0
+                    .first()
+                    .is_some_and(|opener| opener.genre.as_deref() != Some(genre.as_str()))
+            }
+        };
+        if met { 1.0 } else { 0.0 }
+    }
+}
+
+impl Constraint {
+    pub fn new(kind: ConstraintKind, weight: impl Into<f64>) -> Self {
+        Self {
+            kind,
+            weight: weight.into(),
+            priority: 0,
+            hard: false,
+            penalty: false,
+        }
+    }
+
+    /// Builder: set the priority level.
+    pub fn with_priority(mut self, priority: usize) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Builder: make this a hard constraint (see `hard`).
+    pub fn with_hard(mut self) -> Self {
+        self.hard = true;
+        self
+    }
+
+    /// Builder: make this a penalty constraint (see `penalty`).
+    pub fn with_penalty(mut self) -> Self {
+        self.penalty = true;
+        self
+    }
+
+    /// Fraction of the weight earned, in `0.0..=1.0`: the kind's
+    /// satisfaction, inverted for a penalty.
+    fn satisfaction(&self, tracklist: &Tracklist, medium: &Medium, options: &MatchOptions) -> f64 {
+        let satisfaction = self.kind.satisfaction(tracklist, medium, options);
+        if self.penalty {
+            1.0 - satisfaction
+        } else {
+            satisfaction
+        }
+    }
+
+    /// Weight earned by this constraint alone. Does not check medium fit.
+    pub fn score(&self, tracklist: &Tracklist, medium: &Medium) -> f64 {
+        self.score_with(tracklist, medium, &MatchOptions::default())
+    }
+
+    /// `score` with title matching relaxed by `options`.
+    pub fn score_with(
+        &self,
+        tracklist: &Tracklist,
+        medium: &Medium,
+        options: &MatchOptions,
+    ) -> f64 {
+        self.earned(self.satisfaction(tracklist, medium, options))
+    }
+
+    /// Weight earned at the given satisfaction.
+    fn earned(&self, satisfaction: f64) -> f64 {
+        self.weight * satisfaction
+    }
+
+    /// Whether this constraint earns its full weight (for a penalty: whether
+    /// it is fully avoided). Does not check medium fit.
+    pub fn is_satisfied(&self, tracklist: &Tracklist, medium: &Medium) -> bool {
+        self.satisfaction(tracklist, medium, &MatchOptions::default()) >= 1.0
+    }
+}
+
+/// True if `values` never decreases up to some peak and never increases after it,
+/// i.e. `v[0] <= .. <= v[p] >= .. >= v[n-1]` for some `p`. Plateaus are allowed,
+/// and sequences shorter than three are trivially unimodal.
+fn is_unimodal(values: &[usize]) -> bool {
+    let rising = values.windows(2).take_while(|w| w[0] <= w[1]).count();
+    values[rising..].windows(2).all(|w| w[0] >= w[1])
+}
+
+/// How closely the tracklist's energies follow `target`, in `0.0..=1.0`.
+///
+/// Energies are min-max normalized to `0..=1` so only the shape matters
+/// (a flat curve sits at 0.5). Tracks without an energy value are treated
+/// as the mean of the known energies. Only positions covered by both the
+/// tracklist and `target` are compared. The fit is `1 / (1 + sse)` where
+/// `sse` is the summed squared error; no known energies yields 0.0.
+fn energy_curve_fit(tracklist: &Tracklist, target: &[f64]) -> f64 {
+    let known: Vec<f64> = tracklist.0.iter().filter_map(|t| t.energy).collect();
+    if known.is_empty() {
+        return 0.0;
+    }
+    let mean = known.iter().sum::<f64>() / known.len() as f64;
+    let min = known.iter().cloned().fold(f64::INFINITY, f64::min);
+    let max = known.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    let normalize = |e: f64| {
+        if max > min {
+            (e - min) / (max - min)
+        } else {
+            0.5
+        }
+    };
+
+    let sse: f64 = tracklist
+        .0
+        .iter()
+        .zip(target)
+        .map(|(track, &t)| (normalize(track.energy.unwrap_or(mean)) - t).powi(2))
+        .sum();
+    1.0 / (1.0 + sse)
+}
+
+/// How closely consecutive mood changes follow `slope`, in `0.0..=1.0`.
+///
+/// Tracks without a mood are skipped, so deltas are taken between consecutive
+/// tracks that have one. The fit is `1 / (1 + e)` where `e` is the mean
+/// absolute difference between each delta and `slope`; a constraint earns
+/// `weight * fit`. Fewer than two moods yields 0.0.
+fn mood_trajectory_fit(tracklist: &Tracklist, slope: f64) -> f64 {
+    let moods: Vec<f64> = tracklist.0.iter().filter_map(|t| t.mood).collect();
+    if moods.len() < 2 {
+        return 0.0;
+    }
+    let error = moods
+        .windows(2)
+        .map(|w| (w[1] - w[0] - slope).abs())
+        .sum::<f64>()
+        / (moods.len() - 1) as f64;
+    1.0 / (1.0 + error)
+}
+
+/// How central two tracks sharing a side are, in `0.0..=1.0`.
+///
+/// Each track's centrality is `1 - |m - L/2| / (L/2)`, where `m` is the
+/// midpoint of the track on the side's timeline (`Tracklist::timeline`) and
+/// `L` the side's used time (`SideStat::used`). The result is the mean of the
+/// two, so 1.0 means both straddle the exact center. Tracks on different
+/// sides or missing score 0.0.
+fn side_centrality(
+    tracklist: &Tracklist,
+    medium: &Medium,
+    t1: &str,
+    t2: &str,
+    options: &MatchOptions,
+) -> f64 {
+    let (Some(i1), Some(i2)) = (
+        tracklist.position_of(t1, options),
+        tracklist.position_of(t2, options),
+    ) else {
+        return 0.0;
+    };
+    let timeline = tracklist.timeline(medium);
+    let side = timeline[i1].side;
+    if timeline[i2].side != side {
+        return 0.0;
+    }
+
+    let half = medium.side_stats(tracklist)[side].used / 2.0;
+    let centrality = |i: usize| {
+        let entry = &timeline[i];
+        let mid = entry.start_seconds + entry.duration_seconds / 2.0;
+        if half > 0.0 {
+            (1.0 - (mid - half).abs() / half).max(0.0)
+        } else {
+            1.0
+        }
+    };
+    (centrality(i1) + centrality(i2)) / 2.0
+}
+
+/// Whether the tracklist fits the medium and fully satisfies every hard
+/// constraint. Infeasible tracklists score 0.
+pub fn is_feasible(tracklist: &Tracklist, constraints: &[Constraint], medium: &Medium) -> bool {
+    is_feasible_with(tracklist, constraints, medium, &MatchOptions::default())
+}
+
+/// `is_feasible` with title matching relaxed by `options`.
+fn is_feasible_with(
+    tracklist: &Tracklist,
+    constraints: &[Constraint],
+    medium: &Medium,
+    options: &MatchOptions,
+) -> bool {
+    medium.fits(tracklist)
+        && constraints
+            .iter()
+            .filter(|c| c.hard)
+            .all(|c| c.satisfaction(tracklist, medium, options) >= 1.0)
+}
+
+/// Score the tracklist against constraints and medium.
+///
+/// Titles are matched against the first track carrying them; see
+/// `Tracklist::duplicate_titles` to validate input beforehand. Tracklists
+/// that don't fit or violate a hard constraint score 0; use `is_feasible` to
+/// tell them apart from feasible ones that earn nothing.
+pub fn score_tracklist(tracklist: &Tracklist, constraints: &[Constraint], medium: &Medium) -> f64 {
+    // No score if it doesn't fit the medium or breaks a hard constraint
+    feasible_score(tracklist, constraints, medium).unwrap_or(0.0)
+}
+
+/// `score_tracklist` of a feasible tracklist, or `None` if it isn't feasible.
+/// Evaluates each constraint once, where `is_feasible` followed by
+/// `score_tracklist` would evaluate the hard ones twice.
+pub(crate) fn feasible_score(
+    tracklist: &Tracklist,
+    constraints: &[Constraint],
+    medium: &Medium,
+) -> Option<f64> {
+    if !medium.fits(tracklist) {
+        return None;
+    }
+    let options = MatchOptions::default();
+    constraints
+        .iter()
+        .map(|c| {
+            let satisfaction = c.satisfaction(tracklist, medium, &options);
+            (!c.hard || satisfaction >= 1.0).then(|| c.earned(satisfaction))
+        })
+        .sum()
+}
+
+/// `score_tracklist` with penalties subtracted instead of credited for being
+/// avoided: rewards earned minus the weight of each penalty met (partially
+/// met proportional penalties subtract their share). Ranks orderings exactly
+/// as `score_tracklist` does. `None` when the tracklist isn't feasible.
+pub fn score_tracklist_signed(
+    tracklist: &Tracklist,
+    constraints: &[Constraint],
+    medium: &Medium,
+) -> Option<f64> {
+    if !is_feasible(tracklist, constraints, medium) {
+        return None;
+    }
+    let penalties: f64 = constraints
+        .iter()
+        .filter(|c| c.penalty)
+        .map(|c| c.weight)
+        .sum();
+    Some(score_tracklist(tracklist, constraints, medium) - penalties)
+}
+
+/// Share of the total constraint weight the tracklist earns, in `0.0..=1.0`,
+/// for showing as a percentage or comparing constraint sets of different
+/// total weight: `score_tracklist` over the sum of all weights.
+///
+/// Infeasible tracklists score 0.0; a feasible one with no weight to earn
+/// scores 1.0.
+pub fn score_normalized(tracklist: &Tracklist, constraints: &[Constraint], medium: &Medium) -> f64 {
+    if !is_feasible(tracklist, constraints, medium) {
+        return 0.0;
+    }
+    let total: f64 = constraints.iter().map(|c| c.weight).sum();
+    if total == 0.0 {
+        return 1.0;
+    }
+    score_tracklist(tracklist, constraints, medium) / total
+}
+
+/// Change in `score_tracklist` from swapping positions `i` and `j`, without
+/// rescoring every constraint.
+///
+/// Only constraints the swap could affect are re-evaluated: those naming one
+/// of the two tracks (or their ids), slot-based kinds covering either
+/// position, and side-based kinds when the swap moves a track across a side
+/// or changes the packing. Whole-album kinds like `EnergyCurve` are always
+/// re-evaluated. The result equals `score(after) - score(before)` up to
+/// floating-point rounding.
+///
+/// Panics if `i` or `j` is out of range.
+pub fn score_delta(
+    tracklist: &Tracklist,
+    i: usize,
+    j: usize,
+    constraints: &[Constraint],
+    medium: &Medium,
+) -> f64 {
+    if i == j {
+        return 0.0;
+    }
+    let mut swapped = tracklist.clone();
+    swapped.0.swap(i, j);
+
+    match (
+        is_feasible(tracklist, constraints, medium),
+        is_feasible(&swapped, constraints, medium),
+    ) {
+        (false, false) => return 0.0,
+        (true, true) => {}
+        (before, after) => {
+            let full = |feasible: bool, t: &Tracklist| {
+                if feasible {
+                    score_tracklist(t, constraints, medium)
+                } else {
+                    0.0
+                }
+            };
+            return full(after, &swapped) - full(before, tracklist);
+        }
+    }
+
+    let sides = medium.side_indices(tracklist);
+    let repacked = medium.side_indices(&swapped) != sides;
+    constraints
+        .iter()
+        .filter(|c| c.kind.touched_by_swap(tracklist, (i, j), repacked, &sides))
+        .map(|c| c.score(&swapped, medium) - c.score(tracklist, medium))
+        .sum()
+}
+
+/// Incremental scorer for swap moves, for local searches that evaluate many
+/// swaps of one tracklist.
+///
+/// Keeps the side assignment and each constraint's satisfaction cached, so
+/// `delta` re-packs the sides once and re-evaluates only the constraints the
+/// swap could affect (as `score_delta` does), without cloning the tracklist.
+/// `score` and `delta` always agree with `score_tracklist` and `score_delta`.
+pub struct SwapScorer<'a> {
+    tracklist: Tracklist,
+    constraints: &'a [Constraint],
+    medium: &'a Medium,
+    /// `Medium::fits_any_order`, which no swap changes.
+    fits_any_order: bool,
+    sides: Vec<usize>,
+    satisfaction: Vec<f64>,
+    feasible: bool,
+    score: f64,
+}
+
+/// A swap evaluated by `SwapScorer`, ready to commit.
+struct SwapOutcome {
+    sides: Vec<usize>,
+    /// Constraints whose satisfaction was re-evaluated, with the new value.
+    changed: Vec<(usize, f64)>,
+    feasible: bool,
+    score: f64,
+}
+
+impl<'a> SwapScorer<'a> {
+    pub fn new(tracklist: Tracklist, constraints: &'a [Constraint], medium: &'a Medium) -> Self {
+        let options = MatchOptions::default();
+        let satisfaction: Vec<f64> = constraints
+            .iter()
+            .map(|c| c.satisfaction(&tracklist, medium, &options))
+            .collect();
+        let fits_any_order = medium.fits_any_order(&tracklist);
+        let sides = medium.side_indices(&tracklist);
+        let mut scorer = Self {
+            tracklist,
+            constraints,
+            medium,
+            fits_any_order,
+            sides,
+            satisfaction,
+            feasible: false,
+            score: 0.0,
+        };
+        (scorer.feasible, scorer.score) = scorer.total(&scorer.sides, &scorer.satisfaction);
+        scorer
+    }
+
+    /// The tracklist with every committed swap applied.
+    pub fn tracklist(&self) -> &Tracklist {
+        &self.tracklist
+    }
+
+    pub fn into_tracklist(self) -> Tracklist {
+        self.tracklist
+    }
+
+    /// `score_tracklist` of the current tracklist.
+    pub fn score(&self) -> f64 {
+        self.score
+    }
+
+    /// Change in score from swapping positions `i` and `j`, leaving the
+    /// tracklist as it is. Panics if `i` or `j` is out of range.
+    pub fn delta(&mut self, i: usize, j: usize) -> f64 {
+        let outcome = self.evaluate(i, j);
+        self.tracklist.0.swap(i, j);
+        outcome.score - self.score
+    }
+
+    /// Swap positions `i` and `j` and update the cached state.
+    pub fn swap(&mut self, i: usize, j: usize) {
+        let outcome = self.evaluate(i, j);
+        for (k, satisfaction) in outcome.changed {
+            self.satisfaction[k] = satisfaction;
+        }
+        self.sides = outcome.sides;
+        self.feasible = outcome.feasible;
+        self.score = outcome.score;
+    }
+
+    /// Swap `i` and `j` in place and score the result against the cache.
+    fn evaluate(&mut self, i: usize, j: usize) -> SwapOutcome {
+        self.tracklist.0.swap(i, j);
+        let sides = self.medium.side_indices(&self.tracklist);
+        if i == j {
+            return SwapOutcome {
+                sides,
+                changed: Vec::new(),
+                feasible: self.feasible,
+                score: self.score,
+            };
+        }
+        // Swapping the same pair back is symmetric, so the check can look at
+        // the tracklist after the swap.
+        let repacked = sides != self.sides;
+        let options = MatchOptions::default();
+        let changed: Vec<(usize, f64)> = self
+            .constraints
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| {
+                c.kind
+                    .touched_by_swap(&self.tracklist, (i, j), repacked, &self.sides)
+            })
+            .map(|(k, c)| (k, c.satisfaction(&self.tracklist, self.medium, &options)))
+            .collect();
+        let mut satisfaction = self.satisfaction.clone();
+        for &(k, value) in &changed {
+            satisfaction[k] = value;
+        }
+        let (feasible, score) = self.total(&sides, &satisfaction);
+        SwapOutcome {
+            sides,
+            changed,
+            feasible,
+            score,
+        }
+    }
+
+    /// Feasibility and score for a side assignment and satisfactions.
+    fn total(&self, sides: &[usize], satisfaction: &[f64]) -> (bool, f64) {
+        let fits = self.fits_any_order && self.medium.fits_sides(sides, false);
+        let feasible = fits
+            && self
+                .constraints
+                .iter()
+                .zip(satisfaction)
+                .all(|(c, &s)| !c.hard || s >= 1.0);
+        let score = if feasible {
+            self.constraints
+                .iter()
+                .zip(satisfaction)
+                .map(|(c, &s)| c.earned(s))
+                .sum()
+        } else {
+            0.0
+        };
+        (feasible, score)
+    }
+}
+
+/// `score_tracklist` with title matching relaxed by `options`, e.g. to
+/// tolerate case or stray whitespace differences between constraints typed
+/// by a user and titles read from tags. Applies to every title-based kind.
+pub fn score_tracklist_with(
+    tracklist: &Tracklist,
+    constraints: &[Constraint],
+    medium: &Medium,
+    options: &MatchOptions,
+) -> f64 {
+    if !is_feasible_with(tracklist, constraints, medium, options) {
+        return 0.0;
+    }
+
+    constraints
+        .iter()
+        .map(|c| c.score_with(tracklist, medium, options))
+        .sum()
+}
+
+/// Score only the adjacency constraints (`Adjacent`, `AdjacentEither`,
+/// `NotAdjacent`, `AdjacentIdx` and `BpmWithin`), judged across the order a
+/// record changer actually plays the album (see `Tracklist::changer_tracklist`),
+/// so transitions between sides are the ones a listener hears. Other kinds are
+/// left out since their sides and positions refer to the pressed layout. A
+/// tracklist that doesn't fit scores 0.
+pub fn score_changer_adjacency(
+    tracklist: &Tracklist,
+    constraints: &[Constraint],
+    medium: &Medium,
+) -> f64 {
+    if !medium.fits(tracklist) {
+        return 0.0;
+    }
+
+    let played = tracklist.changer_tracklist(medium);
+    constraints
+        .iter()
+        .filter(|c| {
+            matches!(
+                c.kind,
+                ConstraintKind::Adjacent(..)
+                    | ConstraintKind::AdjacentEither(..)
+                    | ConstraintKind::NotAdjacent(..)
+                    | ConstraintKind::AdjacentIdx(..)
+                    | ConstraintKind::BpmWithin(..)
+            )
+        })
+        .map(|c| c.score(&played, medium))
+        .sum()
+}
+
+/// The candidate with the least `Medium::total_capacity` that `tracklist`
+/// fits on, or `None` if none does. Ties keep the earlier candidate.
+pub fn smallest_fitting<'a>(tracklist: &Tracklist, candidates: &'a [Medium]) -> Option<&'a Medium> {
+    candidates
+        .iter()
+        .filter(|medium| medium.fits(tracklist))
+        .min_by(|a, b| a.total_capacity().total_cmp(&b.total_capacity()))
+}
+
+/// Per constraint, its index and whether it is satisfied in `a` and in `b`.
+///
+/// A tracklist that isn't feasible satisfies nothing, as in `score_tracklist`.
+pub fn constraint_diff(
+    a: &Tracklist,
+    b: &Tracklist,
+    constraints: &[Constraint],
+    medium: &Medium,
+) -> Vec<(usize, bool, bool)> {
+    let a_feasible = is_feasible(a, constraints, medium);
+    let b_feasible = is_feasible(b, constraints, medium);
+    constraints
+        .iter()
+        .enumerate()
+        .map(|(i, c)| {
+            (
+                i,
+                a_feasible && c.is_satisfied(a, medium),
+                b_feasible && c.is_satisfied(b, medium),
+            )
+        })
+        .collect()
+}
+
+/// How tracklist `b` differs from `a`, see `compare`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TracklistComparison<'a> {
+    /// `score_tracklist(b) - score_tracklist(a)`.
+    pub score_delta: f64,
+    /// Constraints satisfied in `b` but not in `a`, by index.
+    pub newly_satisfied: Vec<usize>,
+    /// Constraints satisfied in `a` but not in `b`, by index.
+    pub newly_violated: Vec<usize>,
+    /// Title-keyed track changes from `a` to `b`, see `Tracklist::diff`.
+    pub diff: TracklistDiff<'a>,
+    /// `reordering_effort` from `a` to `b`: 0.0 unchanged, 1.0 fully reversed.
+    pub kendall_tau: f64,
+}
+
+/// Compare two orderings under the same constraints and medium: the score
+/// change, the constraints that flipped either way (as in `constraint_diff`),
+/// how each track moved, and the Kendall-tau distance between them.
+pub fn compare<'a>(
+    a: &'a Tracklist,
+    b: &'a Tracklist,
+    constraints: &[Constraint],
+    medium: &Medium,
+) -> TracklistComparison<'a> {
+    let mut newly_satisfied = Vec::new();
+    let mut newly_violated = Vec::new();
+    for (i, in_a, in_b) in constraint_diff(a, b, constraints, medium) {
+        match (in_a, in_b) {
+            (false, true) => newly_satisfied.push(i),
+            (true, false) => newly_violated.push(i),
+            _ => {}
+        }
+    }
+    let titles: Vec<String> = a.0.iter().map(|t| t.title.clone()).collect();
+
+    TracklistComparison {
+        score_delta: score_tracklist(b, constraints, medium)
+            - score_tracklist(a, constraints, medium),
+        newly_satisfied,
+        newly_violated,
+        diff: a.diff(b),
+        kendall_tau: reordering_effort(&titles, b),
+    }
+}
+
+/// Normalized Kendall-tau distance between `ordering` and `original_titles`, in `0.0..=1.0`.
+///
+/// Counts the track pairs whose relative order differs, divided by the number
+/// of pairs: 0.0 means unchanged, 1.0 fully reversed. Titles missing from
+/// `original_titles` are ignored; fewer than two comparable tracks yields 0.0.
+pub fn reordering_effort(original_titles: &[String], ordering: &Tracklist) -> f64 {
+    let ranks: Vec<usize> = ordering
+        .0
+        .iter()
+        .filter_map(|t| original_titles.iter().position(|o| *o == t.title))
+        .collect();
+    let n = ranks.len();
+    if n < 2 {
+        return 0.0;
+    }
+
+    let discordant = (0..n)
+        .flat_map(|i| (i + 1..n).map(move |j| (i, j)))
+        .filter(|&(i, j)| ranks[i] > ranks[j])
+        .count();
+    discordant as f64 / (n * (n - 1) / 2) as f64
+}
+
+/// What is wrong with a constraint, as found by `validate_constraints`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConstraintProblem {
+    /// The constraint names a title no track in the pool has.
+    UnknownTitle(String),
+    /// An `AtPosition` target past the end of the pool.
+    PositionOutOfRange { position: usize, len: usize },
+    /// A pair (or `Group`) names the same track twice, so it relates the track
+    /// to itself. Holds the title, or `"id N"` for the `*Idx` kinds. Not
+    /// reported when several tracks share the title.
+    SelfReference(String),
+}
+
+/// A problem with the constraint at index `constraint`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConstraintIssue {
+    pub constraint: usize,
+    pub problem: ConstraintProblem,
+}
+
+impl std::fmt::Display for ConstraintIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.problem {
+            ConstraintProblem::UnknownTitle(title) => {
+                write!(f, "constraint {}: unknown title {title:?}", self.constraint)
+            }
+            ConstraintProblem::PositionOutOfRange { position, len } => write!(
+                f,
+                "constraint {}: position {position} out of range for {len} tracks",
+                self.constraint
+            ),
+            ConstraintProblem::SelfReference(track) => {
+                write!(f, "constraint {}: names {track} twice", self.constraint)
+            }
+        }
+    }
+}
+
+/// Check `constraints` against the track pool without scoring anything.
+///
+/// Reports every title that matches no track exactly (so a typo doesn't just
+/// silently lower the score) and every `AtPosition` or `AtPositionIdx` whose
+/// position can never be filled, or `InPositionRange` whose range starts past
+/// the end, and every pair that names one track twice. Issues come in
+/// constraint order; an empty vec means the set is valid.
+pub fn validate_constraints(constraints: &[Constraint], tracks: &[Track]) -> Vec<ConstraintIssue> {
+    let mut issues = Vec::new();
+    for (i, constraint) in constraints.iter().enumerate() {
+        for title in constraint.kind.titles() {
+            if !tracks.iter().any(|t| t.title == title) {
+                issues.push(ConstraintIssue {
+                    constraint: i,
+                    problem: ConstraintProblem::UnknownTitle(title.to_string()),
+                });
+            }
+        }
+        if let ConstraintKind::AtPosition(_, position)
+        | ConstraintKind::AtPositionIdx(_, position)
+        | ConstraintKind::InPositionRange(_, position, _) = constraint.kind
+            && position >= tracks.len()
+        {
+            issues.push(ConstraintIssue {
+                constraint: i,
+                problem: ConstraintProblem::PositionOutOfRange {
+                    position,
+                    len: tracks.len(),
+                },
+            });
+        }
+
+        let repeated: Vec<String> = match &constraint.kind {
+            ConstraintKind::Adjacent(t1, t2)
+            | ConstraintKind::AdjacentEither(t1, t2)
+            | ConstraintKind::NotAdjacent(t1, t2)
+            | ConstraintKind::OnSameSide(t1, t2)
+            | ConstraintKind::NotOnSameSide(t1, t2)
+            | ConstraintKind::OnSameSideCentered(t1, t2)
+            | ConstraintKind::Before(t1, t2)
+            | ConstraintKind::BpmWithin(t1, t2, _)
+            | ConstraintKind::Separated(t1, t2, _)
+                if t1 == t2 =>
+            {
+                vec![t1.clone()]
+            }
+            ConstraintKind::Group(titles, _) => titles.iter().duplicates().cloned().collect(),
+            _ => Vec::new(),
+        };
+        // Two tracks sharing a title (e.g. a reprise) can legitimately pair up.
+        for title in repeated {
+            if tracks.iter().filter(|t| t.title == title).count() == 1 {
+                issues.push(ConstraintIssue {
+                    constraint: i,
+                    problem: ConstraintProblem::SelfReference(title),
+                });
+            }
+        }
+        if let ConstraintKind::AdjacentIdx(id1, id2) | ConstraintKind::OnSameSideIdx(id1, id2) =
+            constraint.kind
+            && id1 == id2
+        {
+            issues.push(ConstraintIssue {
+                constraint: i,
+                problem: ConstraintProblem::SelfReference(format!("id {id1}")),
+            });
+        }
+    }
+    issues
+}
+
+/// Constraints that can never all be met together, see `find_conflicts`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConstraintConflict {
+    /// Indices into the constraint list, in increasing order.
+    pub constraints: Vec<usize>,
+}
+
+impl std::fmt::Display for ConstraintConflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let list = self.constraints.iter().map(|i| i.to_string()).join(", ");
+        write!(f, "constraints {list} can't all be met")
+    }
+}
+
+/// Whether `a` and `b` can never both be met. `unique` says whether a title
+/// is carried by exactly one track.
+fn kinds_conflict(a: &ConstraintKind, b: &ConstraintKind, unique: &dyn Fn(&str) -> bool) -> bool {
+    let same_pair = |(a1, a2): (&String, &String), (b1, b2): (&String, &String)| {
+        (a1 == b1 && a2 == b2) || (a1 == b2 && a2 == b1)
+    };
+    match (a, b) {
+        (ConstraintKind::AtPosition(t1, p1), ConstraintKind::AtPosition(t2, p2)) => {
+            (p1 == p2 && t1 != t2) || (t1 == t2 && p1 != p2 && unique(t1))
+        }
+        (ConstraintKind::AtPositionIdx(id1, p1), ConstraintKind::AtPositionIdx(id2, p2)) => {
+            (p1 == p2) != (id1 == id2)
+        }
+        // A track has one successor and one predecessor.
+        (ConstraintKind::Adjacent(a1, a2), ConstraintKind::Adjacent(b1, b2)) => {
+            (a1 == b1 && a2 != b2 && unique(a1)) || (a2 == b2 && a1 != b1 && unique(a2))
+        }
+        (
+            ConstraintKind::Adjacent(a1, a2) | ConstraintKind::AdjacentEither(a1, a2),
+            ConstraintKind::NotAdjacent(b1, b2),
+        )
+        | (ConstraintKind::OnSameSide(a1, a2), ConstraintKind::NotOnSameSide(b1, b2)) => {
+            same_pair((a1, a2), (b1, b2))
+        }
+        _ => false,
+    }
+}
+
+/// Find sets of constraints that no ordering of `tracks` can satisfy
+/// together, so an unreachable maximum shows up before a long search.
+///
+/// Checks pairs that contradict each other directly (two tracks pinned to
+/// one position, one track pinned to two, two successors for one track, a
+/// pair both required and forbidden to be adjacent or on the same side), and
+/// cycles in the order implied by `Before` and `Adjacent` (e.g. `A` before
+/// `B` before `C` before `A`). Title rules assume one track per title and
+/// are skipped for titles several tracks share. Penalty constraints are
+/// ignored. Conflicts come sorted by their constraint indices; the analysis
+/// is sound but not complete, so an empty result doesn't prove every
+/// constraint can be met at once.
+pub fn find_conflicts(constraints: &[Constraint], tracks: &[Track]) -> Vec<ConstraintConflict> {
+    let unique = |title: &str| tracks.iter().filter(|t| t.title == title).count() == 1;
+    let active: Vec<usize> = (0..constraints.len())
+        .filter(|&i| !constraints[i].penalty)
+        .collect();
+    let mut conflicts: Vec<Vec<usize>> = Vec::new();
+
+    for (&i, &j) in active.iter().tuple_combinations() {
+        let (a, b) = (&constraints[i].kind, &constraints[j].kind);
+        if kinds_conflict(a, b, &unique) || kinds_conflict(b, a, &unique) {
+            conflicts.push(vec![i, j]);
+        }
+    }
+
+    // Order edges: `from` comes before `to` whenever constraint `index` is met.
+    let edges: Vec<(&str, &str, usize)> = active
+        .iter()
+        .filter_map(|&i| match &constraints[i].kind {
+            ConstraintKind::Before(t1, t2) | ConstraintKind::Adjacent(t1, t2)
+                if t1 != t2 && unique(t1) && unique(t2) =>
+            {
+                Some((t1.as_str(), t2.as_str(), i))
+            }
+            _ => None,
+        })
+        .collect();
+    for &(from, to, index) in &edges {
+        // Breadth-first search for a path back from `to` to `from`.
+        let mut via: Vec<(&str, Option<usize>)> = vec![(to, None)];
+        let mut head = 0;
+        while head < via.len() && via[head].0 != from {
+            let node = via[head].0;
+            for (k, &(a, b, _)) in edges.iter().enumerate() {
+                if a == node && !via.iter().any(|(seen, _)| *seen == b) {
+                    via.push((b, Some(k)));
+                }
+            }
+            head += 1;
+        }
+        if head == via.len() {
+            continue;
+        }
+        let mut cycle = vec![index];
+        let mut at = head;
+        while let Some(k) = via[at].1 {
+            cycle.push(edges[k].2);
+            at = via
+                .iter()
+                .position(|(node, _)| *node == edges[k].0)
+                .unwrap();
+        }
+        cycle.sort_unstable();
+        cycle.dedup();
+        if !conflicts.contains(&cycle) {
+            conflicts.push(cycle);
+        }
+    }
+
+    conflicts.sort();
+    conflicts
+        .into_iter()
+        .map(|constraints| ConstraintConflict { constraints })
+        .collect()
+}
+
+/// Indices of the constraints `tracklist` doesn't fully satisfy, heaviest first
+/// (equal weights keep constraint order). Nothing is satisfied if it isn't
+/// feasible.
+pub fn unsatisfied_constraints(
+    tracklist: &Tracklist,
+    constraints: &[Constraint],
+    medium: &Medium,
+) -> Vec<usize> {
+    let feasible = is_feasible(tracklist, constraints, medium);
+    let mut unsatisfied: Vec<usize> = (0..constraints.len())
+        .filter(|&i| !(feasible && constraints[i].is_satisfied(tracklist, medium)))
+        .collect();
+    unsatisfied.sort_by(|&a, &b| constraints[b].weight.total_cmp(&constraints[a].weight));
+    unsatisfied
+}
+
+/// Per side, the satisfied share of the constraint weight attributable to it.
+///
+/// A constraint is attributed to every side holding one of the tracks it
+/// references (its named titles, the opener, or all tracks for whole-album
+/// kinds), contributing its full weight to each such side's total and its
+/// earned score to each side's satisfied weight. Constraints whose titles are
+/// all missing are attributed nowhere. Sides with nothing attributed report
+/// 1.0. A tracklist that isn't feasible earns nothing.
+pub fn per_side_satisfaction(
+    tracklist: &Tracklist,
+    constraints: &[Constraint],
+    medium: &Medium,
+) -> Vec<f64> {
+    let side_indices = medium.side_indices(tracklist);
+    let side_count = side_indices.last().map_or(0, |&s| s + 1);
+    let feasible = is_feasible(tracklist, constraints, medium);
+    let mut satisfied = vec![0.0; side_count];
+    let mut total = vec![0.0; side_count];
+
+    for constraint in constraints {
+        let earned = if feasible {
+            constraint.score(tracklist, medium)
+        } else {
+            0.0
+        };
+        let mut sides: Vec<usize> = constraint
+            .kind
+            .referenced_positions(tracklist, &MatchOptions::default())
+            .into_iter()
+            .map(|pos| side_indices[pos])
+            .collect();
+        sides.sort_unstable();
+        sides.dedup();
+        for side in sides {
+            total[side] += constraint.weight;
+            satisfied[side] += earned;
+        }
+    }
+
+    satisfied
+        .iter()
+        .zip(&total)
+        .map(|(&s, &t)| if t == 0.0 { 1.0 } else { s / t })
+        .collect()
+}
+
+/// Per track, the score earned by the constraints that reference it.
+///
+/// Uses the same attribution as `per_side_satisfaction`: a constraint's
+/// earned score is credited in full to every track it references rather than
+/// split between them. The sum over all
+/// tracks is therefore each constraint's score times the number of tracks it
+/// references; it equals `score_tracklist` only when every constraint names
+/// exactly one present track. A tracklist that isn't feasible earns nothing.
+pub fn track_contributions(
+    tracklist: &Tracklist,
+    constraints: &[Constraint],
+    medium: &Medium,
+) -> Vec<f64> {
+    let mut contributions = vec![0.0; tracklist.0.len()];
+    if !is_feasible(tracklist, constraints, medium) {
+        return contributions;
+    }
+
+    let options = MatchOptions::default();
+    for constraint in constraints {
+        let earned = constraint.score(tracklist, medium);
+        let mut positions = constraint.kind.referenced_positions(tracklist, &options);
+        positions.sort_unstable();
+        positions.dedup();
+        for pos in positions {
+            contributions[pos] += earned;
+        }
+    }
+    contributions
+}
+
+/// One constraint's line in a `ScoreReport`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ConstraintResult {
+    /// Index into the constraint list.
+    pub index: usize,
+    /// Whether the constraint is fully met (for a penalty: fully avoided),
+    /// regardless of feasibility, so a broken hard constraint shows up.
+    pub satisfied: bool,
+    /// Fraction of the weight earned; `satisfied` when it reaches 1.0.
+    pub satisfaction: f64,
+    /// Weight counted towards the score; 0 when the tracklist is infeasible.
+    pub earned: f64,
+    /// The rest of the constraint's weight.
+    pub lost: f64,
+}
+
+/// Why a tracklist scores what it does, from `score_report`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ScoreReport {
+    /// Same as `score_tracklist`.
+    pub score: f64,
+    /// Whether the tracklist fits the medium.
+    pub fits: bool,
+    /// Whether it fits and meets every hard constraint; see `is_feasible`.
+    pub feasible: bool,
+    /// Side index of each track, as in `Medium::side_indices`.
+    pub sides: Vec<usize>,
+    /// One entry per constraint, in the given order.
+    pub constraints: Vec<ConstraintResult>,
+}
+
+/// Score `tracklist` like `score_tracklist`, but report each constraint's
+/// outcome and the side assignment alongside the total, to see why one
+/// ordering beats another.
+pub fn score_report(
+    tracklist: &Tracklist,
+    constraints: &[Constraint],
+    medium: &Medium,
+) -> ScoreReport {
+    let options = MatchOptions::default();
+    let feasible = is_feasible(tracklist, constraints, medium);
+    let results: Vec<ConstraintResult> = constraints
+        .iter()
+        .enumerate()
+        .map(|(index, c)| {
+            let satisfaction = c.satisfaction(tracklist, medium, &options);
+            let earned = if feasible {
+                c.earned(satisfaction)
+            } else {
+                0.0
+            };
+            ConstraintResult {
+                index,
+                satisfied: satisfaction >= 1.0,
+                satisfaction,
+                earned,
+                lost: c.weight - earned,
+            }
+        })
+        .collect();
+    ScoreReport {
+        score: results.iter().map(|r| r.earned).sum(),
+        fits: medium.fits(tracklist),
+        feasible,
+        sides: medium.side_indices(tracklist),
+        constraints: results,
+    }
+}
+
+/// How unevenly `tracklist` fills the sides: the longest side's used time
+/// minus the shortest's, in the same units as `Duration`.
+///
+/// Sides are packed as in `Medium::split_sides`, and sides of the medium left
+/// empty count as unused (0.0), so piling everything onto side A is
+/// penalized. Lower is better; perfectly balanced sides and single-sided
+/// media give 0.0. Combine with the constraint score as you see fit, e.g.
+/// `score as f64 - lambda * balance_penalty(medium, tracklist)`.
+pub fn balance_penalty(medium: &Medium, tracklist: &Tracklist) -> f64 {
+    let mut used: Vec<Duration> = medium
+        .side_stats(tracklist)
+        .iter()
+        .map(|s| s.used)
+        .collect();
+    used.resize(used.len().max(medium.sides), 0.0);
+    if used.len() < 2 {
+        return 0.0;
+    }
+    let max = used.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    let min = used.iter().cloned().fold(f64::INFINITY, f64::min);
+    max - min
+}
+
+/// Panic if `medium`'s packing of `tracklist` is internally inconsistent.
+///
+/// Checks that `side_indices` assigns every track a side, starting at 0 and
+/// never skipping or going back; that `split_sides`, `side_stats` and
+/// `timeline` agree with it; that when `fits` holds every side is within the
+/// effective capacity and the side count within `medium.sides`; and that
+/// `on_same_side` agrees with `split_sides`. Meant as a fuzzing oracle (see
+/// the `arbitrary` feature), not for production paths: it is quadratic in the
+/// track count.
+pub fn check_invariants(tracklist: &Tracklist, medium: &Medium) {
+    let n = tracklist.0.len();
+    let sides = medium.side_indices(tracklist);
+    assert_eq!(sides.len(), n, "side_indices length");
+    if let Some(&first) = sides.first() {
+        assert_eq!(first, 0, "first track not on side 0");
+    }
+    for w in sides.windows(2) {
+        assert!(
+            w[1] == w[0] || w[1] == w[0] + 1,
+            "side_indices jumps: {sides:?}"
+        );
+    }
+
+    let split = medium.split_sides(tracklist);
+    assert_eq!(
+        split.len(),
+        sides.last().map_or(0, |&s| s + 1),
+        "split_sides count"
+    );
+    let flattened: Vec<&Track> = split.iter().flatten().copied().collect();
+    assert!(
+        flattened
+            .iter()
+            .zip(&tracklist.0)
+            .all(|(a, b)| std::ptr::eq(*a, b)),
+        "split_sides reorders tracks"
+    );
+
+    let stats = medium.side_stats(tracklist);
+    assert_eq!(stats.len(), split.len(), "side_stats count");
+    for (stat, side) in stats.iter().zip(&split) {
+        assert_eq!(stat.track_count, side.len(), "side_stats track count");
+    }
+
+    let timeline = tracklist.timeline(medium);
+    for (entry, &side) in timeline.iter().zip(&sides) {
+        assert_eq!(entry.side, side, "timeline side");
+    }
+
+    if let Some(max) = medium.max_tracks_per_side {
+        for stat in &stats {
+            assert!(stat.track_count <= max, "side holds too many tracks");
+        }
+    }
+
+    if medium.fits(tracklist) {
+        assert!(split.len() <= medium.sides, "fits but uses too many sides");
+        let capacity = medium.effective_capacity();
+        let tolerance = 1e-9 * capacity.max(1.0);
+        for stat in &stats {
+            assert!(
+                stat.used <= capacity + tolerance,
+                "fits but a side overflows"
+            );
+        }
+    }
+
+    let options = MatchOptions::default();
+    for i in 0..n {
+        for j in i + 1..n {
+            let (t1, t2) = (&tracklist.0[i].title, &tracklist.0[j].title);
+            let (Some(p1), Some(p2)) = (
+                tracklist.position_of(t1, &options),
+                tracklist.position_of(t2, &options),
+            ) else {
+                unreachable!("titles come from the tracklist");
+            };
+            assert_eq!(
+                medium.on_same_side(tracklist, t1, t2),
+                sides[p1] == sides[p2],
+                "on_same_side disagrees with split_sides for {t1:?} and {t2:?}"
+            );
+        }
+    }
+}
+
+/// Parse a Camelot key like "8A" or "12B" into (wheel number, mode letter).
+fn parse_camelot(key: &str) -> Option<(u8, char)> {
+    let key = key.trim();
+    let mode = key.chars().last()?.to_ascii_uppercase();
+    if mode != 'A' && mode != 'B' {
+        return None;
+    }
+    let number: u8 = key[..key.len() - 1].parse().ok()?;
+    if (1..=12).contains(&number) {
+        Some((number, mode))
+    } else {
+        None
+    }
+}
+
+/// Score how harmonically a tracklist flows, using Camelot keys.
+///
+/// Each adjacent pair earns one point when the transition is compatible:
+/// same key, one step around the wheel in the same mode (12 wraps to 1),
+/// or the relative major/minor swap (same number, other letter).
+/// Pairs where either track has a missing or unparseable key earn nothing.
+pub fn harmonic_flow_score(tracklist: &Tracklist) -> usize {
+    tracklist
+        .0
+        .windows(2)
+        .filter(|w| {
+            let a = w[0].key.as_deref().and_then(parse_camelot);
+            let b = w[1].key.as_deref().and_then(parse_camelot);
+            match (a, b) {
+                (Some((n1, m1)), Some((n2, m2))) => {
+                    let step = (n1 as i32 - n2 as i32).rem_euclid(12);
+                    (m1 == m2 && matches!(step, 0 | 1 | 11)) || (n1 == n2 && m1 != m2)
+                }
+                _ => false,
+            }
+        })
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cmp::Ordering;
+
+    // For testing: lex order on track titles.
+    impl PartialOrd for Tracklist {
+        fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+    impl Ord for Tracklist {
+        fn cmp(&self, other: &Self) -> Ordering {
+            self.0
+                .iter()
+                .map(|t| &t.title)
+                .cmp(other.0.iter().map(|t| &t.title))
+        }
+    }
+
+    #[test]
+    fn test_tracklist_permutations() {
+        let tracks = vec![
+            Track::new("A", 3.5),
+            Track::new("B", 4.0),
+            Track::new("C", 2.75),
+        ];
+
+        let mut perms: Vec<Tracklist> = TracklistPermutations::new(&tracks)
+            .map(|perm| Tracklist::new(perm.into_iter().cloned().collect()))
+            .collect();
+
+        assert_eq!(perms.len(), 6);
+
+        let mut expected: Vec<Tracklist> = vec![
+            Tracklist::from(vec![("A", 3.5), ("B", 4.0), ("C", 2.75)]),
+            Tracklist::from(vec![("A", 3.5), ("C", 2.75), ("B", 4.0)]),
+            Tracklist::from(vec![("B", 4.0), ("A", 3.5), ("C", 2.75)]),
+            Tracklist::from(vec![("B", 4.0), ("C", 2.75), ("A", 3.5)]),
+            Tracklist::from(vec![("C", 2.75), ("A", 3.5), ("B", 4.0)]),
+            Tracklist::from(vec![("C", 2.75), ("B", 4.0), ("A", 3.5)]),
+        ];
+
+        perms.sort();
+        expected.sort();
+
+        assert_eq!(perms, expected);
+    }
+
+    #[test]
+    fn test_medium_fits_and_same_side() {
+        let tracks = Tracklist::from(vec![("A", 10.0), ("B", 8.0), ("C", 12.0), ("D", 7.0)]);
+        let medium = Medium::new("test_medium", 2, 20.0);
+        assert!(medium.fits(&tracks)); // (A+B=18), (C+D=19)
+        assert!(medium.on_same_side(&tracks, "A", "B"));
+        assert!(medium.on_same_side(&tracks, "C", "D"));
+        assert!(!medium.on_same_side(&tracks, "B", "C"));
+        assert!(!medium.on_same_side(&tracks, "A", "D"));
+
+        let medium2 = Medium::new("test_medium", 2, 15.0);
+        assert!(!medium2.fits(&tracks)); // no 2-side split possible with max 15
+
+        let tracks2 = Tracklist::from(vec![("A", 10.0), ("B", 5.0), ("C", 7.0), ("D", 7.0)]);
+        let medium3 = Medium::new("test_medium", 3, 12.0);
+        assert!(medium3.fits(&tracks2)); // (A=10), (B+C=12), (D=7)
+        assert!(medium3.on_same_side(&tracks2, "B", "C"));
+        assert!(!medium3.on_same_side(&tracks2, "A", "B"));
+
+        let tracks3 = Tracklist::from(vec![("A", 21.0), ("B", 5.0)]);
+        let medium4 = Medium::new("test_medium", 2, 20.0);
+        assert!(!medium4.fits(&tracks3));
+    }
+
+    #[test]
+    fn test_score_tracklist() {
+        let medium = Medium::new("test_medium", 2, 10.0);
+
+        let constraints = vec![
+            Constraint::new(ConstraintKind::AtPosition("Intro".into(), 0), 7),
+            Constraint::new(ConstraintKind::Adjacent("First".into(), "Second".into()), 5),
+            Constraint::new(
+                ConstraintKind::OnSameSide("Second".into(), "Third".into()),
+                2,
+            ),
+        ];
+
+        let max_score: f64 = constraints.iter().map(|c| c.weight).sum();
+
+        let tracks = Tracklist::from(vec![
+            ("Intro", 5.0),
+            ("First", 5.0),
+            ("Second", 2.0),
+            ("Third", 2.0),
+        ]);
+        // Tracklist respects all constraints
+        assert_eq!(score_tracklist(&tracks, &constraints, &medium), max_score);
+
+        // Third is alone on side 2
+        let tracks = Tracklist::from(vec![
+            ("Intro", 5.0),
+            ("First", 3.0),
+            ("Second", 2.0),
+            ("Third", 2.0),
+        ]);
+        assert_eq!(
+            score_tracklist(&tracks, &constraints, &medium),
+            max_score - constraints[2].weight
+        );
+
+        // Third is alone on side 2 and first and second dont folow
+        let tracks = Tracklist::from(vec![
+            ("Intro", 5.0),
+            ("Second", 2.0),
+            ("First", 3.0),
+            ("Third", 2.0),
+        ]);
+        assert_eq!(
+            score_tracklist(&tracks, &constraints, &medium),
+            max_score - constraints[2].weight - constraints[1].weight
+        );
+
+        // Third is alone on side 2, first and second dont folow and intro is not at the beginning
+        let tracks = Tracklist::from(vec![
+            ("Second", 2.0),
+            ("Intro", 5.0),
+            ("First", 3.0),
+            ("Third", 2.0),
+        ]);
+        assert_eq!(
+            score_tracklist(&tracks, &constraints, &medium),
+            max_score - constraints[2].weight - constraints[1].weight - constraints[0].weight
+        );
+    }
+
+    #[test]
+    fn test_opener_not_genre() {
+        let medium = Medium::new("test_medium", 1, 20.0);
+        let constraints = vec![Constraint::new(
+            ConstraintKind::OpenerNotGenre("ballad".into()),
+            3,
+        )];
+
+        let rock_opener = Tracklist::new(vec![
+            Track::new("Anthem", 4.0).with_genre("rock"),
+            Track::new("Slow", 5.0).with_genre("ballad"),
+        ]);
+        assert_eq!(score_tracklist(&rock_opener, &constraints, &medium), 3.0);
+
+        // No genre at all is not a ballad
+        let untagged_opener = Tracklist::new(vec![
+            Track::new("Untagged", 4.0),
+            Track::new("Slow", 5.0).with_genre("ballad"),
+        ]);
+        assert_eq!(
+            score_tracklist(&untagged_opener, &constraints, &medium),
+            3.0
+        );
+
+        let ballad_opener = Tracklist::new(vec![
+            Track::new("Slow", 5.0).with_genre("ballad"),
+            Track::new("Anthem", 4.0).with_genre("rock"),
+        ]);
+        assert_eq!(score_tracklist(&ballad_opener, &constraints, &medium), 0.0);
+    }
+
+    #[test]
+    fn test_bpm_within() {
+        let medium = Medium::new("test_medium", 1, 20.0);
+        let constraints = vec![Constraint::new(
+            ConstraintKind::BpmWithin("A".into(), "B".into(), 5.0),
+            4,
+        )];
+
+        // Present and within tolerance
+        let tracks = Tracklist::new(vec![
+            Track::new("A", 4.0).with_bpm(120.0),
+            Track::new("B", 4.0).with_bpm(124.0),
+        ]);
+        assert_eq!(score_tracklist(&tracks, &constraints, &medium), 4.0);
+
+        // Present but exceeds tolerance
+        let tracks = Tracklist::new(vec![
+            Track::new("A", 4.0).with_bpm(120.0),
+            Track::new("B", 4.0).with_bpm(128.0),
+        ]);
+        assert_eq!(score_tracklist(&tracks, &constraints, &medium), 0.0);
+
+        // Within tolerance but not adjacent
+        let tracks = Tracklist::new(vec![
+            Track::new("A", 4.0).with_bpm(120.0),
+            Track::new("C", 4.0),
+            Track::new("B", 4.0).with_bpm(124.0),
+        ]);
+        assert_eq!(score_tracklist(&tracks, &constraints, &medium), 0.0);
+
+        // Missing metadata is ignored rather than scored
+        let tracks = Tracklist::new(vec![
+            Track::new("A", 4.0).with_bpm(120.0),
+            Track::new("B", 4.0),
+        ]);
+        assert_eq!(score_tracklist(&tracks, &constraints, &medium), 0.0);
+    }
+
+    #[test]
+    fn test_harmonic_flow_score() {
+        let keyed = |keys: &[&str]| {
+            Tracklist::new(
+                keys.iter()
+                    .enumerate()
+                    .map(|(i, k)| Track::new(format!("T{i}"), 4.0).with_key(*k))
+                    .collect(),
+            )
+        };
+
+        // 8A -> 9A (+1), 9A -> 9B (relative), 9B -> 9B (same), 9B -> 10B (+1), 12B -> 1B (wrap)
+        let mixed = keyed(&["8A", "9A", "9B", "9B", "10B"]);
+        assert_eq!(harmonic_flow_score(&mixed), 4);
+        assert_eq!(harmonic_flow_score(&keyed(&["12B", "1B"])), 1);
+
+        // 9A -> 3B is a jarring jump
+        let jarring = keyed(&["8A", "9A", "3B", "4B"]);
+        assert_eq!(harmonic_flow_score(&jarring), 2);
+
+        // Missing or unparseable keys contribute nothing
+        let mut partial = keyed(&["8A", "nonsense", "8A"]);
+        partial.0.push(Track::new("NoKey", 4.0));
+        assert_eq!(harmonic_flow_score(&partial), 0);
+    }
+
+    #[test]
+    fn test_coherence() {
+        let bpm_similarity = |a: &Track, b: &Track| match (a.bpm, b.bpm) {
+            (Some(x), Some(y)) => 1.0 / (1.0 + (x - y).abs()),
+            _ => 0.0,
+        };
+        let with_bpms = |bpms: &[f64]| {
+            Tracklist::new(
+                bpms.iter()
+                    .map(|b| Track::new(format!("{b}"), 4.0).with_bpm(*b))
+                    .collect(),
+            )
+        };
+
+        let sorted = with_bpms(&[90.0, 100.0, 110.0, 120.0]);
+        let shuffled = with_bpms(&[110.0, 90.0, 120.0, 100.0]);
+        assert!(sorted.coherence(bpm_similarity) > shuffled.coherence(bpm_similarity));
+
+        // A single track has no adjacent pairs
+        assert_eq!(with_bpms(&[90.0]).coherence(bpm_similarity), 0.0);
+    }
+
+    #[test]
+    fn test_energy_curve() {
+        let medium = Medium::new("test_medium", 1, 40.0);
+        let constraints = vec![Constraint::new(
+            ConstraintKind::EnergyCurve(vec![0.0, 1.0 / 3.0, 2.0 / 3.0, 1.0]),
+            10,
+        )];
+        let with_energies = |energies: &[f64]| {
+            Tracklist::new(
+                energies
+                    .iter()
+                    .map(|e| Track::new(format!("{e}"), 4.0).with_energy(*e))
+                    .collect(),
+            )
+        };
+
+        let rising = with_energies(&[0.2, 0.4, 0.6, 0.8]);
+        let shuffled = with_energies(&[0.6, 0.2, 0.8, 0.4]);
+        let rising_score = score_tracklist(&rising, &constraints, &medium);
+        assert_eq!(rising_score, 10.0);
+        assert!(rising_score > score_tracklist(&shuffled, &constraints, &medium));
+
+        // Missing energy counts as the mean, which still leaves a partial fit
+        let mut partial = with_energies(&[0.2, 0.8]);
+        partial.0.insert(1, Track::new("unknown", 4.0));
+        let partial_score = score_tracklist(&partial, &constraints, &medium);
+        assert!(partial_score > 0.0 && partial_score < 10.0);
+
+        // No energies at all earns nothing
+        let untagged = Tracklist::from(vec![("A", 4.0), ("B", 4.0)]);
+        assert_eq!(score_tracklist(&untagged, &constraints, &medium), 0.0);
+    }
+
+    #[test]
+    fn test_tracklist_editing() {
+        let mut tracks = Tracklist::from(vec![("A", 1.0), ("B", 1.0), ("C", 1.0), ("D", 1.0)]);
+
+        tracks.push(Track::new("E", 1.0));
+        assert_eq!(tracks.titles(), vec!["A", "B", "C", "D", "E"]);
+
+        // Moving shifts the intervening tracks in both directions
+        tracks.move_track(3, 0).unwrap();
+        assert_eq!(tracks.titles(), vec!["D", "A", "B", "C", "E"]);
+        tracks.move_track(0, 4).unwrap();
+        assert_eq!(tracks.titles(), vec!["A", "B", "C", "E", "D"]);
+        tracks.move_track(2, 2).unwrap();
+        assert_eq!(tracks.titles(), vec!["A", "B", "C", "E", "D"]);
+        assert_eq!(
+            tracks.move_track(5, 0),
+            Err(IndexError { index: 5, len: 5 })
+        );
+        assert_eq!(
+            tracks.move_track(0, 5),
+            Err(IndexError { index: 5, len: 5 })
+        );
+
+        let removed = tracks.remove_by_title("E").unwrap();
+        assert_eq!(removed.title, "E");
+        assert!(tracks.remove_by_title("E").is_none());
+        assert_eq!(tracks.titles(), vec!["A", "B", "C", "D"]);
+
+        // Inserting at the length appends; past it is an error
+        tracks.insert_at(0, Track::new("Intro", 1.0)).unwrap();
+        tracks.insert_at(5, Track::new("Outro", 1.0)).unwrap();
+        assert_eq!(tracks.titles(), vec!["Intro", "A", "B", "C", "D", "Outro"]);
+        assert_eq!(
+            tracks.insert_at(7, Track::new("Lost", 1.0)),
+            Err(IndexError { index: 7, len: 6 })
+        );
+    }
+
+    #[test]
+    fn test_constraint_diff() {
+        let medium = Medium::new("test_medium", 1, 20.0);
+        let constraints = vec![
+            Constraint::new(ConstraintKind::AtPosition("A".into(), 0), 1),
+            Constraint::new(ConstraintKind::Adjacent("B".into(), "C".into()), 1),
+        ];
+
+        let before = Tracklist::from(vec![("A", 1.0), ("C", 1.0), ("B", 1.0)]);
+        let after = Tracklist::from(vec![("A", 1.0), ("B", 1.0), ("C", 1.0)]);
+        assert_eq!(
+            constraint_diff(&before, &after, &constraints, &medium),
+            vec![(0, true, true), (1, false, true)]
+        );
+    }
+
+    #[test]
+    fn test_duplicate_titles() {
+        let tracks = Tracklist::from(vec![
+            ("A", 1.0),
+            ("B", 2.0),
+            ("A", 3.0),
+            ("C", 1.0),
+            ("A", 1.0),
+        ]);
+        assert_eq!(tracks.duplicate_titles(), vec!["A".to_string()]);
+
+        let unique = Tracklist::from(vec![("A", 1.0), ("B", 2.0)]);
+        assert!(unique.duplicate_titles().is_empty());
+    }
+
+    #[test]
+    fn test_first_and_last_on_side() {
+        let medium = Medium::new("test_medium", 2, 10.0);
+        // Side 0: A, B; side 1: C, D
+        let tracks = Tracklist::from(vec![("A", 5.0), ("B", 5.0), ("C", 4.0), ("D", 4.0)]);
+        let score =
+            |kind: ConstraintKind| score_tracklist(&tracks, &[Constraint::new(kind, 3)], &medium);
+
+        assert_eq!(score(ConstraintKind::FirstOnSide("A".into(), 0)), 3.0);
+        assert_eq!(score(ConstraintKind::FirstOnSide("C".into(), 1)), 3.0);
+        assert_eq!(score(ConstraintKind::LastOnSide("B".into(), 0)), 3.0);
+        assert_eq!(score(ConstraintKind::LastOnSide("D".into(), 1)), 3.0);
+
+        assert_eq!(score(ConstraintKind::FirstOnSide("D".into(), 1)), 0.0);
+        assert_eq!(score(ConstraintKind::LastOnSide("A".into(), 0)), 0.0);
+        assert_eq!(score(ConstraintKind::LastOnSide("C".into(), 0)), 0.0);
+
+        // Side that doesn't exist in the packing, and a missing title
+        assert_eq!(score(ConstraintKind::FirstOnSide("A".into(), 2)), 0.0);
+        assert_eq!(score(ConstraintKind::FirstOnSide("Z".into(), 0)), 0.0);
+    }
+
+    #[test]
+    fn test_side_track_count_arc() {
+        let constraints = vec![Constraint::new(ConstraintKind::SideTrackCountArc, 2)];
+        let tracks_of = |durations: &[f64]| {
+            Tracklist::new(
+                durations
+                    .iter()
+                    .enumerate()
+                    .map(|(i, d)| Track::new(format!("T{i}"), *d))
+                    .collect(),
+            )
+        };
+
+        // Counts 4, 6, 6, 3 rise, plateau, then fall
+        let medium = Medium::new("box_set", 4, 10.0);
+        let mut durations = vec![2.5; 4];
+        durations.extend([1.5; 12]);
+        durations.extend([2.5; 3]);
+        let arc = tracks_of(&durations);
+        let counts: Vec<usize> = medium.split_sides(&arc).iter().map(|s| s.len()).collect();
+        assert_eq!(counts, vec![4, 6, 6, 3]);
+        assert_eq!(score_tracklist(&arc, &constraints, &medium), 2.0);
+
+        // Counts 6, 3, 6 dip in the middle
+        let mut durations = vec![1.5; 6];
+        durations.extend([3.0; 3]);
+        durations.extend([1.5; 6]);
+        let valley = tracks_of(&durations);
+        let counts: Vec<usize> = medium
+            .split_sides(&valley)
+            .iter()
+            .map(|s| s.len())
+            .collect();
+        assert_eq!(counts, vec![6, 3, 6]);
+        assert_eq!(score_tracklist(&valley, &constraints, &medium), 0.0);
+    }
+
+    #[test]
+    fn test_side_stats() {
+        let medium = Medium::new("test_medium", 2, 20.0);
+        let tracks = Tracklist::from(vec![("A", 10.0), ("B", 8.0), ("C", 5.0), ("D", 3.0)]);
+
+        let stats = medium.side_stats(&tracks);
+        assert_eq!(
+            stats,
+            vec![
+                SideStat {
+                    used: 18.0,
+                    capacity: 20.0,
+                    fill_ratio: 0.9,
+                    track_count: 2,
+                },
+                SideStat {
+                    used: 8.0,
+                    capacity: 20.0,
+                    fill_ratio: 0.4,
+                    track_count: 2,
+                },
+            ]
+        );
+
+        // Gaps between tracks count as used time
+        let gapped = Medium::new("gapped", 2, 20.0).with_gap(1.0);
+        assert_eq!(gapped.side_stats(&tracks)[0].used, 19.0);
+    }
+
+    #[test]
+    fn test_reordering_effort() {
+        let original: Vec<String> = ["A", "B", "C", "D"].iter().map(|t| t.to_string()).collect();
+        let ordering =
+            |titles: &[&str]| Tracklist::new(titles.iter().map(|t| Track::new(*t, 1.0)).collect());
+
+        assert_eq!(
+            reordering_effort(&original, &ordering(&["A", "B", "C", "D"])),
+            0.0
+        );
+        assert_eq!(
+            reordering_effort(&original, &ordering(&["D", "C", "B", "A"])),
+            1.0
+        );
+
+        // One adjacent swap flips one of six pairs
+        let swapped = reordering_effort(&original, &ordering(&["A", "C", "B", "D"]));
+        assert!(swapped > 0.0);
+        assert!((swapped - 1.0 / 6.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_overfill_ratio() {
+        let tracks = Tracklist::from(vec![("A", 10.0), ("B", 10.5), ("C", 10.0), ("D", 10.0)]);
+
+        let strict = Medium::new("strict", 2, 20.0);
+        assert!(!strict.fits(&tracks));
+
+        let lenient = Medium::new("lenient", 2, 20.0).with_overfill(0.05);
+        assert!(lenient.fits(&tracks));
+        assert!(lenient.on_same_side(&tracks, "A", "B"));
+        assert_eq!(lenient.side_indices(&tracks), vec![0, 0, 1, 1]);
+        assert!(lenient.side_stats(&tracks)[0].fill_ratio > 1.0);
+    }
+
+    #[test]
+    fn test_within_first_and_last() {
+        let medium = Medium::new("test_medium", 1, 20.0);
+        let tracks = Tracklist::from(vec![
+            ("A", 1.0),
+            ("B", 1.0),
+            ("C", 1.0),
+            ("D", 1.0),
+            ("E", 1.0),
+        ]);
+        let score =
+            |kind: ConstraintKind| score_tracklist(&tracks, &[Constraint::new(kind, 2)], &medium);
+
+        // Index 2 is the last of the first three slots, index 3 just outside
+        assert_eq!(score(ConstraintKind::WithinFirst("C".into(), 3)), 2.0);
+        assert_eq!(score(ConstraintKind::WithinFirst("D".into(), 3)), 0.0);
+
+        // Index 3 (len - 2) is the first of the last two slots, index 2 just outside
+        assert_eq!(score(ConstraintKind::WithinLast("D".into(), 2)), 2.0);
+        assert_eq!(score(ConstraintKind::WithinLast("C".into(), 2)), 0.0);
+
+        assert_eq!(score(ConstraintKind::WithinFirst("Z".into(), 5)), 0.0);
+        assert_eq!(score(ConstraintKind::WithinLast("Z".into(), 5)), 0.0);
+    }
+
+    #[test]
+    fn test_per_side_satisfaction() {
+        let medium = Medium::new("test_medium", 2, 10.0);
+        // Side 0: A, B; side 1: C, D
+        let tracks = Tracklist::from(vec![("A", 5.0), ("B", 5.0), ("C", 5.0), ("D", 5.0)]);
+        let constraints = vec![
+            Constraint::new(ConstraintKind::Adjacent("A".into(), "B".into()), 2),
+            Constraint::new(ConstraintKind::AtPosition("C".into(), 3), 2),
+            Constraint::new(ConstraintKind::Adjacent("C".into(), "D".into()), 1),
+        ];
+
+        let ratios = per_side_satisfaction(&tracks, &constraints, &medium);
+        assert_eq!(ratios, vec![1.0, 1.0 / 3.0]);
+        assert!(ratios[0] > ratios[1]);
+
+        // A constraint spanning the break counts toward both sides
+        let spanning = vec![Constraint::new(
+            ConstraintKind::Adjacent("B".into(), "C".into()),
+            4,
+        )];
+        assert_eq!(
+            per_side_satisfaction(&tracks, &spanning, &medium),
+            vec![1.0, 1.0]
+        );
+    }
+
+    #[test]
+    fn test_separate_versions() {
+        let medium = Medium::new("test_medium", 1, 20.0);
+        let constraints = vec![Constraint::new(
+            ConstraintKind::SeparateVersions("Song".into()),
+            3,
+        )];
+
+        let separated = Tracklist::new(vec![
+            Track::new("Song", 4.0),
+            Track::new("Other", 4.0),
+            Track::new("Song", 5.0).with_version("live"),
+        ]);
+        assert_eq!(score_tracklist(&separated, &constraints, &medium), 3.0);
+
+        let adjacent = Tracklist::new(vec![
+            Track::new("Other", 4.0),
+            Track::new("Song", 4.0),
+            Track::new("Song", 5.0).with_version("live"),
+        ]);
+        assert_eq!(score_tracklist(&adjacent, &constraints, &medium), 0.0);
+    }
+
+    #[test]
+    fn test_score_tracklist_with_match_options() {
+        let medium = Medium::new("test_medium", 2, 10.0);
+        let tracks = Tracklist::from(vec![("Intro ", 5.0), ("first", 5.0), ("Second", 5.0)]);
+        let constraints = vec![
+            Constraint::new(ConstraintKind::AtPosition("intro".into(), 0), 4),
+            Constraint::new(ConstraintKind::Adjacent("First".into(), "Second".into()), 2),
+            Constraint::new(
+                ConstraintKind::OnSameSide("INTRO".into(), "First".into()),
+                1,
+            ),
+        ];
+
+        assert_eq!(score_tracklist(&tracks, &constraints, &medium), 0.0);
+
+        let case_only = MatchOptions {
+            case_insensitive: true,
+            trim: false,
+        };
+        // "Intro " still misses without trimming
+        assert_eq!(
+            score_tracklist_with(&tracks, &constraints, &medium, &case_only),
+            2.0
+        );
+
+        let relaxed = MatchOptions {
+            case_insensitive: true,
+            trim: true,
+        };
+        assert_eq!(
+            score_tracklist_with(&tracks, &constraints, &medium, &relaxed),
+            7.0
+        );
+        assert!(medium.on_same_side_with(&tracks, " intro", "FIRST", &relaxed));
+
+        let strict = MatchOptions::default();
+        assert_eq!(
+            score_tracklist_with(&tracks, &constraints, &medium, &strict),
+            score_tracklist(&tracks, &constraints, &medium)
+        );
+    }
+
+    #[test]
+    fn test_index_constraints_match_title_constraints() {
+        let medium = Medium::new("test_medium", 2, 10.0);
+        let mut pool = Tracklist::from(vec![("A", 4.0), ("B", 5.0), ("C", 3.0), ("D", 6.0)]);
+        pool.assign_ids();
+
+        let by_title = vec![
+            Constraint::new(ConstraintKind::AtPosition("C".into(), 0), 3),
+            Constraint::new(ConstraintKind::Adjacent("A".into(), "D".into()), 2),
+            Constraint::new(ConstraintKind::OnSameSide("B".into(), "C".into()), 1),
+        ];
+        let by_index = vec![
+            Constraint::new(ConstraintKind::AtPositionIdx(2, 0), 3),
+            Constraint::new(ConstraintKind::AdjacentIdx(0, 3), 2),
+            Constraint::new(ConstraintKind::OnSameSideIdx(1, 2), 1),
+        ];
+
+        for perm in TracklistPermutations::new(&pool.0) {
+            let tracks = Tracklist::new(perm.into_iter().cloned().collect());
+            assert_eq!(
+                score_tracklist(&tracks, &by_index, &medium),
+                score_tracklist(&tracks, &by_title, &medium)
+            );
+        }
+
+        // Without ids the index kinds never match
+        let anonymous = Tracklist::from(vec![("C", 3.0), ("B", 5.0), ("A", 4.0), ("D", 6.0)]);
+        assert_eq!(score_tracklist(&anonymous, &by_index, &medium), 0.0);
+    }
+
+    #[test]
+    fn test_track_hash_and_eq_by_title() {
+        use std::collections::HashSet;
+
+        let studio = Track::new("Song", 4.0);
+        let radio_edit = Track::new("Song", 3.2).with_bpm(120.0);
+        assert_eq!(studio, radio_edit);
+        assert_ne!(studio, Track::new("Other", 4.0));
+
+        let set: HashSet<Track> = [studio, radio_edit].into_iter().collect();
+        assert_eq!(set.len(), 1);
+    }
+
+    #[test]
+    fn test_max_tag_run() {
+        let medium = Medium::new("test_medium", 1, 40.0);
+        let tagged = |n: usize| Track::new(format!("Ballad{n}"), 4.0).with_tag("ballad");
+        let constraints = vec![Constraint::new(
+            ConstraintKind::MaxTagRun("ballad".into(), 3),
+            2,
+        )];
+
+        let four_in_a_row = Tracklist::new(vec![
+            Track::new("Opener", 4.0),
+            tagged(1),
+            tagged(2),
+            tagged(3),
+            tagged(4),
+        ]);
+        assert_eq!(score_tracklist(&four_in_a_row, &constraints, &medium), 0.0);
+
+        let broken_up = Tracklist::new(vec![
+            tagged(1),
+            tagged(2),
+            tagged(3),
+            Track::new("Rocker", 4.0),
+            tagged(4),
+        ]);
+        assert_eq!(score_tracklist(&broken_up, &constraints, &medium), 2.0);
+
+        // Field selectors look at track metadata
+        let rock = |t: &str| Track::new(t, 4.0).with_genre("rock");
+        let genre_run = Tracklist::new(vec![rock("A"), rock("B"), Track::new("C", 4.0)]);
+        let by_genre = vec![Constraint::new(
+            ConstraintKind::MaxTagRun("genre=rock".into(), 1),
+            2,
+        )];
+        assert_eq!(score_tracklist(&genre_run, &by_genre, &medium), 0.0);
+        assert!(!genre_run.0[0].has_tag("mood=rock"));
+    }
+
+    #[test]
+    fn test_tracklist_diff() {
+        let old = Tracklist::from(vec![("A", 1.0), ("B", 1.0), ("C", 1.0)]);
+
+        // Add only: appending keeps every existing position
+        let appended = Tracklist::from(vec![("A", 1.0), ("B", 1.0), ("C", 1.0), ("D", 1.0)]);
+        let diff = old.diff(&appended);
+        assert_eq!(diff.added, vec![&appended.0[3]]);
+        assert!(diff.removed.is_empty());
+        assert!(diff.moved.is_empty());
+
+        // Remove only: dropping the last track
+        let trimmed = Tracklist::from(vec![("A", 1.0), ("B", 1.0)]);
+        let diff = old.diff(&trimmed);
+        assert!(diff.added.is_empty());
+        assert_eq!(diff.removed.len(), 1);
+        assert_eq!(diff.removed[0].title, "C");
+        assert!(diff.moved.is_empty());
+
+        // Pure reorder
+        let reordered = Tracklist::from(vec![("C", 1.0), ("A", 1.0), ("B", 1.0)]);
+        let diff = old.diff(&reordered);
+        assert!(diff.added.is_empty());
+        assert!(diff.removed.is_empty());
+        assert_eq!(
+            diff.moved,
+            vec![
+                TrackMove {
+                    title: "A",
+                    from: 0,
+                    to: 1
+                },
+                TrackMove {
+                    title: "B",
+                    from: 1,
+                    to: 2
+                },
+                TrackMove {
+                    title: "C",
+                    from: 2,
+                    to: 0
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_best_insertion() {
+        let medium = Medium::new("test_medium", 1, 20.0);
+        let tracks = Tracklist::from(vec![("A", 2.0), ("B", 2.0), ("C", 2.0), ("D", 2.0)]);
+        let constraints = vec![Constraint::new(
+            ConstraintKind::Adjacent("C".into(), "New".into()),
+            5,
+        )];
+
+        assert_eq!(
+            tracks.best_insertion(Track::new("New", 2.0), &constraints, &medium),
+            (3, 5.0)
+        );
+
+        // Nothing to gain anywhere: earliest index wins
+        assert_eq!(
+            tracks.best_insertion(Track::new("Other", 2.0), &constraints, &medium),
+            (0, 0.0)
+        );
+    }
+
+    #[test]
+    fn test_unsatisfied_constraints() {
+        let medium = Medium::new("test_medium", 1, 20.0);
+        let tracks = Tracklist::from(vec![("A", 2.0), ("B", 2.0), ("C", 2.0)]);
+        let constraints = vec![
+            Constraint::new(ConstraintKind::AtPosition("A".into(), 0), 1),
+            Constraint::new(ConstraintKind::AtPosition("C".into(), 0), 2),
+            Constraint::new(ConstraintKind::Adjacent("B".into(), "C".into()), 4),
+            Constraint::new(ConstraintKind::Adjacent("C".into(), "A".into()), 5),
+            Constraint::new(ConstraintKind::WithinLast("A".into(), 1), 2),
+        ];
+
+        let unsatisfied = unsatisfied_constraints(&tracks, &constraints, &medium);
+        assert_eq!(unsatisfied, vec![3, 1, 4]);
+
+        let satisfied: Vec<usize> = constraint_diff(&tracks, &tracks, &constraints, &medium)
+            .into_iter()
+            .filter(|&(_, sat, _)| sat)
+            .map(|(i, _, _)| i)
+            .collect();
+        let mut all: Vec<usize> = unsatisfied.iter().chain(&satisfied).copied().collect();
+        all.sort_unstable();
+        assert_eq!(all, (0..constraints.len()).collect::<Vec<_>>());
+        assert!(satisfied.iter().all(|i| !unsatisfied.contains(i)));
+    }
+
+    #[test]
+    fn test_symmetric_duration() {
+        let medium = Medium::new("test_medium", 1, 40.0);
+        let constraints = vec![Constraint::new(ConstraintKind::SymmetricDuration(0.5), 3)];
+
+        let symmetric = Tracklist::from(vec![("A", 3.0), ("B", 5.0), ("C", 4.8), ("D", 3.4)]);
+        assert_eq!(score_tracklist(&symmetric, &constraints, &medium), 3.0);
+
+        let asymmetric = Tracklist::from(vec![("A", 3.0), ("B", 5.0), ("C", 4.2), ("D", 6.0)]);
+        assert_eq!(score_tracklist(&asymmetric, &constraints, &medium), 0.0);
+
+        // The middle of an odd count is unpaired, however long it is
+        let odd = Tracklist::from(vec![
+            ("A", 3.0),
+            ("B", 5.0),
+            ("Mid", 9.0),
+            ("C", 5.2),
+            ("D", 3.0),
+        ]);
+        assert_eq!(score_tracklist(&odd, &constraints, &medium), 3.0);
+
+        let odd_asymmetric = Tracklist::from(vec![
+            ("A", 3.0),
+            ("B", 5.0),
+            ("Mid", 9.0),
+            ("C", 2.0),
+            ("D", 3.0),
+        ]);
+        assert_eq!(score_tracklist(&odd_asymmetric, &constraints, &medium), 0.0);
+    }
+
+    #[test]
+    fn test_track_contributions() {
+        let medium = Medium::new("test_medium", 1, 20.0);
+        let tracks = Tracklist::from(vec![("A", 2.0), ("B", 2.0), ("C", 2.0)]);
+        let single = vec![
+            Constraint::new(ConstraintKind::AtPosition("A".into(), 0), 3),
+            Constraint::new(ConstraintKind::WithinLast("C".into(), 1), 2),
+            Constraint::new(ConstraintKind::AtPosition("B".into(), 0), 7),
+        ];
+
+        // One track per constraint: contributions add up to the score
+        let contributions = track_contributions(&tracks, &single, &medium);
+        assert_eq!(contributions, vec![3.0, 0.0, 2.0]);
+        assert_eq!(
+            contributions.iter().sum::<f64>(),
+            score_tracklist(&tracks, &single, &medium)
+        );
+
+        // Pair constraints credit both tracks in full
+        let paired = vec![
+            Constraint::new(ConstraintKind::Adjacent("A".into(), "B".into()), 4),
+            Constraint::new(ConstraintKind::AtPosition("C".into(), 2), 1),
+        ];
+        let contributions = track_contributions(&tracks, &paired, &medium);
+        assert_eq!(contributions, vec![4.0, 4.0, 1.0]);
+        assert_eq!(contributions.iter().sum::<f64>(), 2.0 * 4.0 + 1.0);
+
+        let too_small = Medium::new("too_small", 1, 5.0);
+        assert_eq!(
+            track_contributions(&tracks, &paired, &too_small),
+            vec![0.0, 0.0, 0.0]
+        );
+    }
+
+    #[test]
+    fn test_validate_constraints() {
+        let tracks = vec![
+            Track::new("A", 2.0),
+            Track::new("B", 2.0),
+            Track::new("C", 2.0),
+        ];
+
+        let self_pairs = vec![
+            Constraint::new(ConstraintKind::Adjacent("A".into(), "A".into()), 1),
+            Constraint::new(
+                ConstraintKind::Group(vec!["A".into(), "B".into(), "B".into()], false),
+                1,
+            ),
+            Constraint::new(ConstraintKind::AdjacentIdx(4, 4), 1),
+        ];
+        let issues = validate_constraints(&self_pairs, &tracks);
+        assert_eq!(
+            issues
+                .iter()
+                .map(|issue| issue.to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                "constraint 0: names A twice",
+                "constraint 1: names B twice",
+                "constraint 2: names id 4 twice",
+            ]
+        );
+        // Fine when two tracks share the title
+        let reprised = [tracks.clone(), vec![Track::new("A", 1.0)]].concat();
+        assert_eq!(validate_constraints(&self_pairs[..1], &reprised), vec![]);
+
+        let valid = vec![
+            Constraint::new(ConstraintKind::AtPosition("A".into(), 2), 1),
+            Constraint::new(ConstraintKind::Adjacent("B".into(), "C".into()), 1),
+            Constraint::new(ConstraintKind::SymmetricDuration(0.5), 1),
+        ];
+        assert!(validate_constraints(&valid, &tracks).is_empty());
+
+        let typo = vec![
+            Constraint::new(ConstraintKind::AtPosition("A".into(), 0), 1),
+            Constraint::new(ConstraintKind::Adjacent("B".into(), "c".into()), 1),
+        ];
+        let issues = validate_constraints(&typo, &tracks);
+        assert_eq!(
+            issues,
+            vec![ConstraintIssue {
+                constraint: 1,
+                problem: ConstraintProblem::UnknownTitle("c".into()),
+            }]
+        );
+        assert_eq!(issues[0].to_string(), "constraint 1: unknown title \"c\"");
+
+        let out_of_range = vec![Constraint::new(
+            ConstraintKind::AtPosition("A".into(), 3),
+            1,
+        )];
+        assert_eq!(
+            validate_constraints(&out_of_range, &tracks),
+            vec![ConstraintIssue {
+                constraint: 0,
+                problem: ConstraintProblem::PositionOutOfRange {
+                    position: 3,
+                    len: 3
+                },
+            }]
+        );
+    }
+
+    #[test]
+    fn test_balance_penalty() {
+        let medium = Medium::new("test_medium", 2, 10.0);
+
+        let balanced = Tracklist::from(vec![("A", 5.0), ("B", 4.0), ("C", 6.0), ("D", 3.0)]);
+        assert!(balance_penalty(&medium, &balanced).abs() < 1e-9);
+
+        let lopsided = Tracklist::from(vec![("A", 9.0), ("B", 2.0), ("C", 1.0)]);
+        assert!((balance_penalty(&medium, &lopsided) - 6.0).abs() < 1e-9);
+
+        // Everything on side A leaves side B empty
+        let one_side = Tracklist::from(vec![("A", 3.0), ("B", 3.0)]);
+        assert!((balance_penalty(&medium, &one_side) - 6.0).abs() < 1e-9);
+
+        let single = Medium::new("single", 1, 20.0);
+        assert_eq!(balance_penalty(&single, &lopsided), 0.0);
+    }
+
+    #[test]
+    fn test_tag_sequence() {
+        let medium = Medium::new("test_medium", 1, 40.0);
+        let constraints = vec![Constraint::new(
+            ConstraintKind::TagSequence(vec!["single".into(), "*".into(), "ballad".into()]),
+            4,
+        )];
+
+        let matching = Tracklist::new(vec![
+            Track::new("Hit", 3.0).with_tag("single"),
+            Track::new("Deep Cut", 5.0),
+            Track::new("Slow One", 6.0).with_tag("ballad"),
+            Track::new("Closer", 4.0),
+        ]);
+        assert_eq!(score_tracklist(&matching, &constraints, &medium), 4.0);
+
+        let ballad_second = Tracklist::new(vec![
+            Track::new("Hit", 3.0).with_tag("single"),
+            Track::new("Slow One", 6.0).with_tag("ballad"),
+            Track::new("Deep Cut", 5.0),
+        ]);
+        assert_eq!(score_tracklist(&ballad_second, &constraints, &medium), 0.0);
+
+        // Too short to fill the pattern
+        let short = Tracklist::new(vec![Track::new("Hit", 3.0).with_tag("single")]);
+        assert_eq!(score_tracklist(&short, &constraints, &medium), 0.0);
+    }
+
+    #[test]
+    fn test_hidden_tracks() {
+        let medium = Medium::new("test_medium", 2, 10.0);
+        let tracklist = Tracklist::new(vec![
+            Track::new("Pregap", 1.0).with_hidden(),
+            Track::new("Intro", 4.0).with_bpm(120.0),
+            Track::new("Secret", 2.0).with_hidden(),
+            Track::new("Song", 3.0).with_bpm(122.0),
+            Track::new("Closer", 5.0),
+        ]);
+        let constraints = vec![
+            Constraint::new(ConstraintKind::AtPosition("Intro".into(), 0), 1),
+            Constraint::new(ConstraintKind::Adjacent("Intro".into(), "Song".into()), 2),
+            Constraint::new(ConstraintKind::AtPosition("Pregap".into(), 0), 4),
+        ];
+        assert_eq!(score_tracklist(&tracklist, &constraints, &medium), 3.0);
+
+        // Every position and neighbor kind skips them
+        let positional = vec![
+            Constraint::new(ConstraintKind::WithinFirst("Song".into(), 2), 1),
+            Constraint::new(ConstraintKind::WithinLast("Intro".into(), 3), 2),
+            Constraint::new(ConstraintKind::PositionParity("Intro".into(), true), 4),
+            Constraint::new(
+                ConstraintKind::BpmWithin("Intro".into(), "Song".into(), 5.0),
+                8,
+            ),
+            Constraint::new(ConstraintKind::WithinFirst("Pregap".into(), 5), 16),
+        ];
+        assert_eq!(score_tracklist(&tracklist, &positional, &medium), 15.0);
+        assert_eq!(
+            crate::optimize::best_tracklist_bb(&tracklist.0, &positional, &medium),
+            crate::optimize::best_arrangement(&tracklist.0, &positional, &medium)
+        );
+
+        // Hidden tracks still take up side time
+        assert_eq!(medium.side_indices(&tracklist), vec![0, 0, 0, 0, 1]);
+        assert!(!Medium::new("tight", 1, 14.0).fits(&tracklist));
+    }
+
+    #[test]
+    fn test_position_parity() {
+        let medium = Medium::new("test_medium", 1, 20.0);
+        let tracks = Tracklist::from(vec![("A", 2.0), ("B", 2.0), ("C", 2.0)]);
+        let even = |title: &str| {
+            vec![Constraint::new(
+                ConstraintKind::PositionParity(title.into(), true),
+                2,
+            )]
+        };
+        let odd = |title: &str| {
+            vec![Constraint::new(
+                ConstraintKind::PositionParity(title.into(), false),
+                2,
+            )]
+        };
+
+        assert_eq!(score_tracklist(&tracks, &even("C"), &medium), 2.0);
+        assert_eq!(score_tracklist(&tracks, &odd("C"), &medium), 0.0);
+        assert_eq!(score_tracklist(&tracks, &odd("B"), &medium), 2.0);
+        assert_eq!(score_tracklist(&tracks, &even("B"), &medium), 0.0);
+        assert_eq!(score_tracklist(&tracks, &even("Missing"), &medium), 0.0);
+        assert_eq!(score_tracklist(&tracks, &odd("Missing"), &medium), 0.0);
+    }
+
+    #[test]
+    fn test_changer_play_order() {
+        let medium = Medium::new("double_lp", 4, 10.0);
+        let tracklist = Tracklist::from(vec![("A1", 8.0), ("B1", 8.0), ("C1", 8.0), ("D1", 8.0)]);
+        assert_eq!(tracklist.changer_play_order(&medium), vec![0, 3, 1, 2]);
+        assert_eq!(
+            tracklist.changer_tracklist(&medium).titles(),
+            vec!["A1", "D1", "B1", "C1"]
+        );
+
+        // Side A runs straight into side D on a changer
+        let constraints = vec![
+            Constraint::new(ConstraintKind::Adjacent("A1".into(), "D1".into()), 2),
+            Constraint::new(ConstraintKind::Adjacent("A1".into(), "B1".into()), 5),
+        ];
+        assert_eq!(
+            score_changer_adjacency(&tracklist, &constraints, &medium),
+            2.0
+        );
+        assert_eq!(score_tracklist(&tracklist, &constraints, &medium), 5.0);
+
+        // Empty sides are skipped; odd side counts keep the middle side once
+        let half = Tracklist::from(vec![("A1", 8.0), ("B1", 8.0)]);
+        assert_eq!(half.changer_play_order(&medium), vec![0, 1]);
+        let three_sides = Medium::new("odd", 3, 10.0);
+        let three = Tracklist::from(vec![("A1", 8.0), ("B1", 8.0), ("C1", 8.0)]);
+        assert_eq!(three.changer_play_order(&three_sides), vec![0, 2, 1]);
+    }
+
+    #[test]
+    fn test_crossfade() {
+        let tracklist = Tracklist::from(vec![("A", 4.0), ("B", 4.0), ("C", 3.0), ("D", 5.0)]);
+        let medium = Medium::new("test_medium", 2, 7.5);
+        assert!(!medium.fits(&tracklist));
+
+        let crossfaded = medium.clone().with_crossfade(0.5);
+        assert!(crossfaded.fits(&tracklist));
+        assert_eq!(crossfaded.side_indices(&tracklist), vec![0, 0, 1, 1]);
+        // One overlap per side; none across the side break
+        let used: Vec<Duration> = crossfaded
+            .side_stats(&tracklist)
+            .iter()
+            .map(|s| s.used)
+            .collect();
+        assert_eq!(used, vec![7.5, 7.5]);
+
+        // The overlap never exceeds the shorter track
+        let blip = Tracklist::from(vec![("A", 6.0), ("Blip", 1.0)]);
+        let long_fade = Medium::new("long_fade", 1, 6.5).with_crossfade(3.0);
+        assert_eq!(long_fade.side_stats(&blip)[0].used, 6.0);
+        assert!(!Medium::new("tight", 1, 5.5).with_crossfade(3.0).fits(&blip));
+    }
+
+    #[test]
+    fn test_mood_trajectory() {
+        let medium = Medium::new("test_medium", 1, 40.0);
+        let moody = |title: &str, mood: f64| Track::new(title, 4.0).with_mood(mood);
+        let constraints = vec![Constraint::new(ConstraintKind::MoodTrajectory(0.2), 10)];
+
+        // Rising steadily by 0.2, with an unrated track skipped
+        let rising = Tracklist::new(vec![
+            moody("A", 0.1),
+            moody("B", 0.3),
+            Track::new("Unrated", 4.0),
+            moody("C", 0.5),
+            moody("D", 0.7),
+        ]);
+        assert_eq!(score_tracklist(&rising, &constraints, &medium), 10.0);
+
+        // Falling by 0.2 misses the slope by 0.4 each step: 10 / 1.4
+        let falling = Tracklist::new(vec![moody("D", 0.7), moody("C", 0.5), moody("B", 0.3)]);
+        assert_eq!(score_tracklist(&falling, &constraints, &medium), 10.0 / 1.4);
+
+        let unrated = Tracklist::new(vec![moody("A", 0.1), Track::new("B", 4.0)]);
+        assert_eq!(score_tracklist(&unrated, &constraints, &medium), 0.0);
+    }
+
+    #[test]
+    fn test_append_cost() {
+        let medium = Medium::new("test_medium", 2, 10.0)
+            .with_gap(0.5)
+            .with_lead_in(1.0);
+        let empty = Tracklist::new(Vec::new());
+        let track = Track::new("A", 4.0);
+
+        // No gap before the first track of a side
+        assert_eq!(
+            empty.append_cost(medium.lead_in, &track, &medium),
+            Some(5.0)
+        );
+
+        let side = Tracklist::from(vec![("A", 4.0)]);
+        assert_eq!(
+            side.append_cost(5.0, &Track::new("B", 4.0), &medium),
+            Some(9.5)
+        );
+        assert_eq!(side.append_cost(5.0, &Track::new("B", 4.6), &medium), None);
+    }
+
+    #[test]
+    fn test_side_overrun() {
+        let medium = Medium::new("test_medium", 2, 22.0).with_overfill(2.0 / 22.0);
+        let tracklist = Tracklist::from(vec![
+            ("A", 8.0),
+            ("B", 8.0),
+            ("C", 7.5),
+            ("D", 9.0),
+            ("E", 9.0),
+        ]);
+        assert_eq!(medium.side_overrun(&tracklist), vec![1.5, 0.0]);
+
+        // Spilling onto a third side is reported too
+        let spilled = Tracklist::from(vec![("A", 20.0), ("B", 20.0), ("C", 25.0)]);
+        assert_eq!(medium.side_overrun(&spilled), vec![0.0, 0.0, 3.0]);
+    }
+
+    #[test]
+    fn test_smallest_fitting() {
+        // Fifty minutes, in seconds
+        let tracklist = Tracklist::from(vec![
+            ("A", 600.0),
+            ("B", 600.0),
+            ("C", 600.0),
+            ("D", 600.0),
+            ("E", 600.0),
+        ]);
+        let candidates = vec![
+            Medium::new("7-inch", 2, 300.0),
+            Medium::double_lp(),
+            Medium::lp(),
+        ];
+        assert!(!Medium::lp().fits(&tracklist));
+        assert_eq!(
+            smallest_fitting(&tracklist, &candidates).map(|m| m.name.as_str()),
+            Some("Double LP")
+        );
+
+        // A CD holds it in less total capacity than a double LP
+        let with_cd = vec![Medium::double_lp(), Medium::cd()];
+        assert_eq!(
+            smallest_fitting(&tracklist, &with_cd).map(|m| m.name.as_str()),
+            Some("CD")
+        );
+        assert!(smallest_fitting(&tracklist, &candidates[..1]).is_none());
+    }
+
+    #[test]
+    fn test_max_short_per_side() {
+        let medium = Medium::new("test_medium", 2, 600.0);
+        let constraints = vec![Constraint::new(
+            ConstraintKind::MaxShortPerSide(120.0, 2),
+            3,
+        )];
+
+        let crammed = Tracklist::from(vec![
+            ("Interlude", 60.0),
+            ("Sketch", 90.0),
+            ("Song", 300.0),
+            ("Skit", 45.0),
+            ("Epic", 500.0),
+        ]);
+        assert_eq!(medium.side_indices(&crammed), vec![0, 0, 0, 0, 1]);
+        assert_eq!(score_tracklist(&crammed, &constraints, &medium), 0.0);
+
+        let spread = Tracklist::from(vec![
+            ("Interlude", 60.0),
+            ("Sketch", 90.0),
+            ("Song", 300.0),
+            ("Epic", 500.0),
+            ("Skit", 45.0),
+        ]);
+        assert_eq!(score_tracklist(&spread, &constraints, &medium), 3.0);
+    }
+
+    #[test]
+    fn test_order_hash() {
+        let a = Tracklist::from(vec![("A", 1.0), ("B", 2.0), ("C", 3.0)]);
+        let same_titles = Tracklist::from(vec![("A", 9.0), ("B", 8.0), ("C", 7.0)]);
+        assert_eq!(a.order_hash(), same_titles.order_hash());
+
+        let reordered = Tracklist::from(vec![("B", 2.0), ("A", 1.0), ("C", 3.0)]);
+        assert_ne!(a.order_hash(), reordered.order_hash());
+
+        let resplit = Tracklist::from(vec![("AB", 1.0), ("C", 3.0)]);
+        assert_ne!(a.order_hash(), resplit.order_hash());
+    }
+
+    #[test]
+    fn test_score_delta_matches_full_rescore() {
+        let medium = Medium::new("test_medium", 3, 10.0).with_gap(0.5);
+        let tracks: Vec<Track> = (0..8)
+            .map(|i| {
+                let track = Track::new(format!("T{i}"), 1.5 + (i % 4) as f64)
+                    .with_id(i)
+                    .with_energy(i as f64)
+                    .with_bpm(100.0 + (i * 3) as f64);
+                match i {
+                    2 => track.with_hidden(),
+                    5 => track.with_genre("rock").with_tag("single"),
+                    _ => track.with_tag("album"),
+                }
+            })
+            .collect();
+        let constraints = vec![
+            Constraint::new(ConstraintKind::AtPosition("T1".into(), 0), 3),
+            Constraint::new(ConstraintKind::Adjacent("T3".into(), "T4".into()), 2),
+            Constraint::new(ConstraintKind::OnSameSide("T0".into(), "T7".into()), 4),
+            Constraint::new(ConstraintKind::FirstOnSide("T6".into(), 1), 2),
+            Constraint::new(ConstraintKind::LastOnSide("T2".into(), 0), 1),
+            Constraint::new(ConstraintKind::OpenerNotGenre("rock".into()), 1),
+            Constraint::new(ConstraintKind::BpmWithin("T4".into(), "T5".into(), 5.0), 2),
+            Constraint::new(ConstraintKind::EnergyCurve(vec![0.0, 0.3, 0.6, 1.0]), 5),
+            Constraint::new(ConstraintKind::SideTrackCountArc, 1),
+            Constraint::new(ConstraintKind::WithinFirst("T5".into(), 3), 2),
+            Constraint::new(ConstraintKind::WithinLast("T0".into(), 2), 1),
+            Constraint::new(ConstraintKind::AtPositionIdx(4, 1), 2),
+            Constraint::new(ConstraintKind::AdjacentIdx(6, 7), 3),
+            Constraint::new(ConstraintKind::OnSameSideIdx(1, 3), 2),
+            Constraint::new(ConstraintKind::MaxTagRun("album".into(), 2), 2),
+            Constraint::new(
+                ConstraintKind::TagSequence(vec!["*".into(), "single".into()]),
+                3,
+            ),
+            Constraint::new(ConstraintKind::PositionParity("T3".into(), true), 1),
+            Constraint::new(ConstraintKind::MaxShortPerSide(3.0, 1), 2),
+            Constraint::new(ConstraintKind::SymmetricDuration(1.0), 1),
+        ];
+
+        let mut rng = crate::rng::Rng::new(7);
+        let mut tracklist = Tracklist::new(tracks);
+        for _ in 0..2000 {
+            let (i, j) = (rng.below(8), rng.below(8));
+            let before = score_tracklist(&tracklist, &constraints, &medium);
+            let delta = score_delta(&tracklist, i, j, &constraints, &medium);
+            tracklist.0.swap(i, j);
+            let after = score_tracklist(&tracklist, &constraints, &medium);
+            assert!((delta - (after - before)).abs() < 1e-9, "swap ({i}, {j})");
+        }
+    }
+
+    #[test]
+    fn test_adjacent_either() {
+        let medium = Medium::new("test_medium", 1, 20.0);
+        let constraints = vec![Constraint::new(
+            ConstraintKind::AdjacentEither("A".into(), "B".into()),
+            3,
+        )];
+
+        let forward = Tracklist::from(vec![("A", 2.0), ("B", 2.0), ("C", 2.0)]);
+        assert_eq!(score_tracklist(&forward, &constraints, &medium), 3.0);
+
+        let backward = Tracklist::from(vec![("C", 2.0), ("B", 2.0), ("A", 2.0)]);
+        assert_eq!(score_tracklist(&backward, &constraints, &medium), 3.0);
+
+        let separated = Tracklist::from(vec![("A", 2.0), ("C", 2.0), ("B", 2.0)]);
+        assert_eq!(score_tracklist(&separated, &constraints, &medium), 0.0);
+
+        // Unlike the directional kind
+        let directional = vec![Constraint::new(
+            ConstraintKind::Adjacent("A".into(), "B".into()),
+            3,
+        )];
+        assert_eq!(score_tracklist(&backward, &directional, &medium), 0.0);
+    }
+
+    #[test]
+    fn test_on_same_side_centered() {
+        let medium = Medium::new("test_medium", 1, 20.0);
+        let constraints = vec![Constraint::new(
+            ConstraintKind::OnSameSideCentered("X".into(), "Y".into()),
+            10,
+        )];
+
+        // Midpoints at 7 and 9 on a 16-minute side: centralities 7/8 and 7/8
+        let central = Tracklist::from(vec![("A", 6.0), ("X", 2.0), ("Y", 2.0), ("B", 6.0)]);
+        assert_eq!(score_tracklist(&central, &constraints, &medium), 8.75);
+
+        // Midpoints at 1 and 15: centralities 1/8 and 1/8
+        let edges = Tracklist::from(vec![("X", 2.0), ("A", 6.0), ("B", 6.0), ("Y", 2.0)]);
+        assert_eq!(score_tracklist(&edges, &constraints, &medium), 1.25);
+
+        let two_sides = Medium::new("two_sides", 2, 10.0);
+        assert_eq!(score_tracklist(&edges, &constraints, &two_sides), 0.0);
+    }
+
+    #[test]
+    fn test_check_invariants_on_fixtures() {
+        let tracklists = [
+            Tracklist::new(Vec::new()),
+            Tracklist::from(vec![("A", 4.0), ("B", 5.0), ("C", 3.0), ("D", 6.0)]),
+            Tracklist::from(vec![
+                ("A", 8.0),
+                ("B", 8.0),
+                ("C", 7.5),
+                ("D", 9.0),
+                ("E", 9.0),
+            ]),
+            Tracklist::from(vec![("A", 25.0), ("A", 1.0), ("B", 0.0)]),
+            Tracklist::new(vec![
+                Track::new("Pregap", 1.0).with_hidden(),
+                Track::new("Intro", 4.0),
+                Track::new("Song", 3.0),
+            ]),
+        ];
+        let media = [
+            Medium::new("test_medium", 2, 10.0),
+            Medium::new("single", 1, 40.0),
+            Medium::new("padded", 2, 10.0)
+                .with_gap(0.5)
+                .with_lead_in(1.0),
+            Medium::new("overfill", 2, 22.0).with_overfill(0.1),
+            Medium::new("crossfaded", 2, 7.5).with_crossfade(0.5),
+            Medium::new("none", 0, 10.0),
+            Medium::lp(),
+        ];
+        for tracklist in &tracklists {
+            for medium in &media {
+                check_invariants(tracklist, medium);
+            }
+        }
+    }
+
+    #[test]
+    fn test_optimal_side_splits() {
+        let tracklist = Tracklist::from(vec![
+            ("A", 7.0),
+            ("B", 2.0),
+            ("C", 5.0),
+            ("D", 10.0),
+            ("E", 8.0),
+            ("F", 1.0),
+            ("G", 4.0),
+        ]);
+        let longest_side = |breaks: &[usize]| {
+            let mut bounds = vec![0];
+            bounds.extend_from_slice(breaks);
+            bounds.push(tracklist.0.len());
+            bounds
+                .windows(2)
+                .map(|w| {
+                    tracklist.0[w[0]..w[1]]
+                        .iter()
+                        .map(|t| t.duration)
+                        .sum::<f64>()
+                })
+                .fold(0.0, f64::max)
+        };
+
+        for sides in 1..=tracklist.0.len() {
+            let breaks = tracklist.optimal_side_splits(sides).unwrap();
+            assert_eq!(breaks.len(), sides - 1);
+            assert!(breaks.windows(2).all(|w| w[0] < w[1]));
+            let brute_force = (1..tracklist.0.len())
+                .combinations(sides - 1)
+                .map(|breaks| longest_side(&breaks))
+                .fold(f64::INFINITY, f64::min);
+            assert_eq!(longest_side(&breaks), brute_force, "{sides} sides");
+        }
+        assert_eq!(tracklist.optimal_side_splits(3), Some(vec![3, 4]));
+
+        assert_eq!(tracklist.optimal_side_splits(0), None);
+        assert_eq!(tracklist.optimal_side_splits(8), None);
+    }
+
+    #[test]
+    fn test_near_side_boundary() {
+        let medium = Medium::new("test_medium", 2, 10.0);
+        let tracklist = Tracklist::from(vec![
+            ("A", 3.0),
+            ("B", 3.0),
+            ("C", 3.0),
+            ("D", 3.0),
+            ("E", 3.0),
+            ("F", 3.0),
+        ]);
+        let near = |title: &str| {
+            vec![Constraint::new(
+                ConstraintKind::NearSideBoundary(title.into()),
+                2,
+            )]
+        };
+
+        // C ends side A and D opens side B
+        assert_eq!(score_tracklist(&tracklist, &near("C"), &medium), 2.0);
+        assert_eq!(score_tracklist(&tracklist, &near("D"), &medium), 2.0);
+        assert_eq!(score_tracklist(&tracklist, &near("B"), &medium), 0.0);
+        // The album's own opener and closer aren't at a side break
+        assert_eq!(score_tracklist(&tracklist, &near("A"), &medium), 0.0);
+        assert_eq!(score_tracklist(&tracklist, &near("F"), &medium), 0.0);
+    }
+
+    #[test]
+    fn test_split_at_breaks() {
+        let medium = Medium::new("test_medium", 2, 10.0);
+        let tracklist = Tracklist::from(vec![
+            ("A", 2.0),
+            ("B", 2.0),
+            ("C", 2.0),
+            ("D", 2.0),
+            ("E", 2.0),
+            ("F", 5.0),
+        ]);
+
+        // Greedy packing would put D and E on side A too
+        let sides = medium.split_at_breaks(&tracklist, &[3]).unwrap();
+        let titles: Vec<Vec<&str>> = sides
+            .iter()
+            .map(|side| side.iter().map(|t| t.title.as_str()).collect())
+            .collect();
+        assert_eq!(titles, vec![vec!["A", "B", "C"], vec!["D", "E", "F"]]);
+
+        assert_eq!(
+            medium.split_at_breaks(&tracklist, &[1]),
+            Err(FitError::SideOverflow {
+                side: 1,
+                used: 13.0,
+                capacity: 10.0
+            })
+        );
+        assert_eq!(
+            medium.split_at_breaks(&tracklist, &[2, 4]),
+            Err(FitError::TooManySides {
+                requested: 3,
+                available: 2
+            })
+        );
+        assert_eq!(
+            medium.split_at_breaks(&tracklist, &[0]),
+            Err(FitError::InvalidBreak { index: 0 })
+        );
+    }
+
+    #[test]
+    fn test_permutation_rank_unrank() {
+        let tracks: Vec<Track> = ["A", "B", "C", "D"]
+            .iter()
+            .map(|t| Track::new(*t, 1.0))
+            .collect();
+        for (index, perm) in TracklistPermutations::new(&tracks).enumerate() {
+            let expected = Tracklist::new(perm.into_iter().cloned().collect());
+            assert_eq!(
+                nth_permutation(&tracks, index as u128),
+                Some(expected.clone())
+            );
+            assert_eq!(permutation_index(&tracks, &expected), Some(index as u128));
+        }
+        assert_eq!(nth_permutation(&tracks, 24), None);
+
+        let stranger = Tracklist::from(vec![("A", 1.0), ("B", 1.0), ("C", 1.0), ("E", 1.0)]);
+        assert_eq!(permutation_index(&tracks, &stranger), None);
+
+        // Beyond 34 tracks n! overflows u128, but small indices still work
+        let many: Vec<Track> = (0..40).map(|i| Track::new(format!("T{i}"), 1.0)).collect();
+        let third = nth_permutation(&many, 2).unwrap();
+        assert_eq!(third.titles()[37..], ["T38", "T37", "T39"]);
+        assert_eq!(permutation_index(&many, &third), Some(2));
+        let reversed = Tracklist::new(many.iter().rev().cloned().collect());
+        assert_eq!(permutation_index(&many, &reversed), None);
+    }
+
+    #[test]
+    fn test_hard_constraints() {
+        let tracks = Tracklist::new(vec![
+            Track::new("Intro", 3.0),
+            Track::new("Single", 3.0),
+            Track::new("Outro", 3.0),
+        ]);
+        let medium = Medium::new("LP", 1, 20.0);
+        let intro_first = Constraint::new(ConstraintKind::AtPosition("Intro".into(), 0), 1);
         let constraints = vec![
-            Constraint {
-                kind: ConstraintKind::AtPosition("Intro".into(), 0),
-                weight: 7,
-            },
-            Constraint {
-                kind: ConstraintKind::Adjacent("First".into(), "Second".into()),
-                weight: 5,
-            },
-            Constraint {
-                kind: ConstraintKind::OnSameSide("Second".into(), "Third".into()),
-                weight: 2,
-            },
-        ];
-
-        let max_score = constraints.iter().map(|c| c.weight).sum();
+            intro_first.clone().with_hard(),
+            Constraint::new(ConstraintKind::AtPosition("Single".into(), 0), 10),
+            Constraint::new(ConstraintKind::AtPosition("Outro".into(), 2), 3),
+        ];
 
-        let tracks = Tracklist::from(vec![
-            ("Intro", 5.0),
-            ("First", 5.0),
-            ("Second", 2.0),
-            ("Third", 2.0),
+        // Intro first: feasible, earns the hard weight and the Outro slot
+        assert!(is_feasible(&tracks, &constraints, &medium));
+        assert_eq!(score_tracklist(&tracks, &constraints, &medium), 4.0);
+
+        // The heavy soft constraint can't buy its way past the hard one
+        let single_first = Tracklist::new(vec![
+            tracks.0[1].clone(),
+            tracks.0[0].clone(),
+            tracks.0[2].clone(),
         ]);
-        // Tracklist respects all constraints
-        assert_eq!(score_tracklist(&tracks, &constraints, &medium), max_score);
+        assert!(!is_feasible(&single_first, &constraints, &medium));
+        assert_eq!(score_tracklist(&single_first, &constraints, &medium), 0.0);
+        assert_eq!(score_delta(&tracks, 0, 1, &constraints, &medium), -4.0);
 
-        // Third is alone on side 2
-        let tracks = Tracklist::from(vec![
-            ("Intro", 5.0),
-            ("First", 3.0),
-            ("Second", 2.0),
-            ("Third", 2.0),
+        // As a soft constraint the heavy one wins
+        let soft = [intro_first, constraints[1].clone(), constraints[2].clone()];
+        assert_eq!(score_tracklist(&single_first, &soft, &medium), 13.0);
+    }
+
+    #[test]
+    fn test_pinned_permutations() {
+        let tracks = vec![
+            Track::new("Intro", 1.0),
+            Track::new("A", 1.0),
+            Track::new("Secret", 1.0).with_hidden(),
+            Track::new("B", 1.0).with_id(7),
+        ];
+        let medium = Medium::new("LP", 1, 10.0);
+        let pins = [
+            ConstraintKind::AtPosition("Intro".into(), 0),
+            ConstraintKind::AtPositionIdx(7, 3),
+            ConstraintKind::Adjacent("A".into(), "B".into()), // not a pin
+        ];
+        let consistent = |perm: &Vec<&Track>| {
+            let tracklist = Tracklist::new(perm.iter().map(|t| (*t).clone()).collect());
+            pins[..2]
+                .iter()
+                .all(|kind| kind.satisfaction(&tracklist, &medium, &MatchOptions::default()) >= 1.0)
+        };
+        let expected: Vec<Vec<&Track>> = TracklistPermutations::new(&tracks)
+            .filter(consistent)
+            .collect();
+        let pinned: Vec<Vec<&Track>> = PinnedPermutations::new(&tracks, &pins).collect();
+        // Secret may come before Intro, which is still the first audible track
+        assert_eq!(
+            pinned
+                .iter()
+                .map(|p| p.iter().map(|t| t.title.as_str()).collect::<Vec<_>>())
+                .collect::<Vec<_>>(),
+            vec![
+                vec!["Intro", "A", "Secret", "B"],
+                vec!["Intro", "Secret", "A", "B"],
+                vec!["Secret", "Intro", "A", "B"],
+            ]
+        );
+        assert_eq!(pinned, expected);
+
+        assert_eq!(PinnedPermutations::new(&tracks, &[]).count(), 24);
+        let unreachable = [ConstraintKind::AtPosition("A".into(), 3)];
+        assert_eq!(PinnedPermutations::new(&tracks, &unreachable).count(), 0);
+    }
+
+    #[test]
+    fn test_swap_scorer_matches_full_scoring() {
+        let tracks = Tracklist::new(vec![
+            Track::new("A", 4.0).with_energy(1.0),
+            Track::new("B", 6.0).with_energy(5.0),
+            Track::new("C", 3.0).with_energy(3.0).with_hidden(),
+            Track::new("D", 5.0).with_energy(2.0),
+            Track::new("E", 2.0).with_energy(4.0),
         ]);
+        let medium = Medium::new("LP", 2, 12.5).with_gap(0.5);
+        let constraints = vec![
+            Constraint::new(ConstraintKind::WithinFirst("A".into(), 3), 3).with_hard(),
+            Constraint::new(ConstraintKind::Adjacent("B".into(), "D".into()), 2),
+            Constraint::new(ConstraintKind::OnSameSide("A".into(), "E".into()), 4),
+            Constraint::new(ConstraintKind::LastOnSide("D".into(), 0), 1),
+            Constraint::new(
+                ConstraintKind::EnergyCurve(vec![1.0, 3.0, 5.0, 4.0, 2.0]),
+                5,
+            ),
+        ];
+
+        let mut scorer = SwapScorer::new(tracks.clone(), &constraints, &medium);
+        let mut rng = crate::rng::Rng::new(3);
+        for _ in 0..200 {
+            let (i, j) = (rng.below(5), rng.below(5));
+            let current = scorer.tracklist().clone();
+            assert_eq!(
+                scorer.score(),
+                score_tracklist(&current, &constraints, &medium)
+            );
+            let delta = score_delta(&current, i, j, &constraints, &medium);
+            assert!((scorer.delta(i, j) - delta).abs() < 1e-9);
+            assert_eq!(scorer.tracklist(), &current);
+            scorer.swap(i, j);
+        }
+    }
+
+    #[test]
+    fn test_index_permutations() {
+        let tracks: Vec<Track> = ["A", "B", "C", "D"]
+            .iter()
+            .map(|t| Track::new(*t, 1.0))
+            .collect();
+        let mut perms = IndexPermutations::new(&tracks);
+        let mut seen = 0;
+        for expected in TracklistPermutations::new(&tracks) {
+            let (order, tracklist) = perms.next_permutation().unwrap();
+            assert!(
+                order
+                    .iter()
+                    .map(|&i| &tracks[i])
+                    .eq(expected.iter().copied())
+            );
+            assert!(tracklist.0.iter().eq(expected.iter().copied()));
+            seen += 1;
+        }
+        assert_eq!(seen, 24);
+        assert!(perms.next_permutation().is_none());
+        assert!(perms.next_permutation().is_none());
+
+        let mut resumed = IndexPermutations::starting_at(&tracks, 22).unwrap();
         assert_eq!(
-            score_tracklist(&tracks, &constraints, &medium),
-            max_score - constraints[2].weight
+            resumed.next_permutation().map(|(_, t)| t.clone()),
+            nth_permutation(&tracks, 22)
         );
+        assert_eq!(resumed.next_permutation().unwrap().0, &[3, 2, 1, 0]);
+        assert!(resumed.next_permutation().is_none());
+        assert!(IndexPermutations::starting_at(&tracks, 24).is_none());
 
-        // Third is alone on side 2 and first and second dont folow
-        let tracks = Tracklist::from(vec![
-            ("Intro", 5.0),
-            ("Second", 2.0),
-            ("First", 3.0),
-            ("Third", 2.0),
+        // The empty pool has one (empty) permutation, as in `TracklistPermutations`
+        let mut empty = IndexPermutations::new(&[]);
+        assert_eq!(empty.next_permutation().unwrap().0, &[] as &[usize]);
+        assert!(empty.next_permutation().is_none());
+    }
+
+    #[test]
+    fn test_custom_constraint() {
+        let tracks = vec![
+            Track::new("A", 3.0).with_key("8A"),
+            Track::new("B", 3.0).with_key("8A"),
+            Track::new("C", 3.0).with_key("5B"),
+        ];
+        let medium = Medium::new("LP", 1, 20.0);
+        let no_repeated_key = |tracklist: &Tracklist, _: &Medium| {
+            tracklist.0.windows(2).all(|w| w[0].key != w[1].key)
+        };
+        let constraints = vec![
+            Constraint::new(ConstraintKind::custom(no_repeated_key), 5),
+            Constraint::new(ConstraintKind::AtPosition("A".into(), 0), 1),
+        ];
+
+        let input = Tracklist::new(tracks.clone());
+        assert_eq!(score_tracklist(&input, &constraints, &medium), 1.0);
+        let (best, score) =
+            crate::optimize::best_arrangement(&tracks, &constraints, &medium).unwrap();
+        assert_eq!(best.titles(), vec!["A", "C", "B"]);
+        assert_eq!(score, 6.0);
+        assert_eq!(
+            format!("{:?}", constraints[0].kind),
+            "Custom(CustomConstraint(..))"
+        );
+    }
+
+    #[test]
+    fn test_negative_constraints() {
+        let tracks = Tracklist::new(vec![
+            Track::new("Ballad 1", 5.0),
+            Track::new("Rocker", 4.0),
+            Track::new("Ballad 2", 5.0),
+            Track::new("Epic 1", 10.0),
+            Track::new("Epic 2", 10.0),
+        ]);
+        let medium = Medium::new("LP", 2, 20.0);
+        let not_adjacent = Constraint::new(
+            ConstraintKind::NotAdjacent("Ballad 2".into(), "Ballad 1".into()),
+            1,
+        );
+        let not_same_side = Constraint::new(
+            ConstraintKind::NotOnSameSide("Epic 1".into(), "Epic 2".into()),
+            1,
+        );
+        // Sides: [Ballad 1, Rocker, Ballad 2] [Epic 1, Epic 2]
+        assert!(not_adjacent.is_satisfied(&tracks, &medium));
+        assert!(!not_same_side.is_satisfied(&tracks, &medium));
+
+        // Hiding the track between them makes the ballads neighbors
+        let mut hidden = tracks.clone();
+        hidden.0[1].hidden = true;
+        assert!(!not_adjacent.is_satisfied(&hidden, &medium));
+
+        // Sides: [Ballad 1, Epic 1, Ballad 2] [Rocker, Epic 2]
+        let split = Tracklist::new(vec![
+            tracks.0[0].clone(),
+            tracks.0[3].clone(),
+            tracks.0[2].clone(),
+            tracks.0[1].clone(),
+            tracks.0[4].clone(),
+        ]);
+        assert!(not_same_side.is_satisfied(&split, &medium));
+
+        // Both tracks must be present
+        let missing = Constraint::new(
+            ConstraintKind::NotAdjacent("Ballad 1".into(), "Ghost".into()),
+            1,
+        );
+        assert!(!missing.is_satisfied(&tracks, &medium));
+    }
+
+    #[test]
+    fn test_before_constraint() {
+        let medium = Medium::new("LP", 1, 60.0);
+        let tracklist =
+            |titles: &[&str]| Tracklist::new(titles.iter().map(|t| Track::new(*t, 3.0)).collect());
+        let reprise = Constraint::new(ConstraintKind::Before("Theme".into(), "Reprise".into()), 2);
+        assert!(reprise.is_satisfied(&tracklist(&["Theme", "Other", "Reprise"]), &medium));
+        assert!(reprise.is_satisfied(&tracklist(&["Theme", "Reprise"]), &medium));
+        assert!(!reprise.is_satisfied(&tracklist(&["Reprise", "Theme"]), &medium));
+        assert!(!reprise.is_satisfied(&tracklist(&["Theme", "Other"]), &medium));
+
+        let tracks = tracklist(&["Reprise", "Other", "Theme"]).0;
+        let constraints = [reprise];
+        let (best, score) =
+            crate::optimize::best_tracklist_bb(&tracks, &constraints, &medium).unwrap();
+        assert_eq!(score, 2.0);
+        assert!(constraints[0].is_satisfied(&best, &medium));
+    }
+
+    #[test]
+    fn test_opens_and_closes_side() {
+        // Sides: [A, B] [C, D]
+        let tracks = Tracklist::new(vec![
+            Track::new("A", 10.0),
+            Track::new("B", 8.0),
+            Track::new("C", 12.0),
+            Track::new("D", 7.0),
+        ]);
+        let medium = Medium::new("LP", 2, 20.0);
+        let met = |kind: ConstraintKind| Constraint::new(kind, 1).is_satisfied(&tracks, &medium);
+        assert!(met(ConstraintKind::OpensSide("A".into())));
+        assert!(met(ConstraintKind::OpensSide("C".into())));
+        assert!(!met(ConstraintKind::OpensSide("B".into())));
+        assert!(met(ConstraintKind::ClosesSide("B".into())));
+        assert!(met(ConstraintKind::ClosesSide("D".into())));
+        assert!(!met(ConstraintKind::ClosesSide("C".into())));
+        assert!(!met(ConstraintKind::OpensSide("Z".into())));
+    }
+
+    #[test]
+    fn test_on_side() {
+        // Sides: [A, B] [C, D]
+        let tracks = Tracklist::new(vec![
+            Track::new("A", 10.0),
+            Track::new("B", 8.0),
+            Track::new("C", 12.0),
+            Track::new("D", 7.0),
         ]);
+        let medium = Medium::new("LP", 2, 20.0);
+        let met = |kind: ConstraintKind| Constraint::new(kind, 1).is_satisfied(&tracks, &medium);
+        assert!(met(ConstraintKind::OnSide("B".into(), 0)));
+        assert!(met(ConstraintKind::OnSide("D".into(), 1)));
+        assert!(!met(ConstraintKind::OnSide("D".into(), 0)));
+        assert!(!met(ConstraintKind::OnSide("A".into(), 2)));
+        assert!(!met(ConstraintKind::OnSide("Z".into(), 0)));
+
+        let constraints = [Constraint::new(ConstraintKind::OnSide("A".into(), 1), 1)];
+        let (best, score) =
+            crate::optimize::best_tracklist_bb(&tracks.0, &constraints, &medium).unwrap();
+        assert_eq!(score, 1.0);
         assert_eq!(
-            score_tracklist(&tracks, &constraints, &medium),
-            max_score - constraints[2].weight - constraints[1].weight
+            medium.side_indices(&best)[best.0.iter().position(|t| t.title == "A").unwrap()],
+            1
         );
+    }
 
-        // Third is alone on side 2, first and second dont folow and intro is not at the beginning
-        let tracks = Tracklist::from(vec![
-            ("Second", 2.0),
-            ("Intro", 5.0),
-            ("First", 3.0),
-            ("Third", 2.0),
+    #[test]
+    fn test_group_constraint() {
+        let medium = Medium::new("LP", 1, 60.0);
+        let tracklist =
+            |titles: &[&str]| Tracklist::new(titles.iter().map(|t| Track::new(*t, 3.0)).collect());
+        let suite = |ordered| {
+            Constraint::new(
+                ConstraintKind::Group(vec!["I".into(), "II".into(), "III".into()], ordered),
+                3,
+            )
+        };
+        let together = tracklist(&["Opener", "II", "I", "III", "Closer"]);
+        assert!(suite(false).is_satisfied(&together, &medium));
+        assert!(!suite(true).is_satisfied(&together, &medium));
+        let in_order = tracklist(&["I", "II", "III", "Closer"]);
+        assert!(suite(true).is_satisfied(&in_order, &medium));
+        let split = tracklist(&["I", "II", "Interlude", "III"]);
+        assert!(!suite(false).is_satisfied(&split, &medium));
+        let missing = tracklist(&["I", "II", "Closer"]);
+        assert!(!suite(false).is_satisfied(&missing, &medium));
+
+        let tracks = tracklist(&["I", "Opener", "III", "Closer", "II"]).0;
+        let constraints = [suite(true)];
+        let (best, score) =
+            crate::optimize::best_tracklist_bb(&tracks, &constraints, &medium).unwrap();
+        assert_eq!(score, 3.0);
+        assert!(constraints[0].is_satisfied(&best, &medium));
+    }
+
+    #[test]
+    fn test_position_range_and_closer() {
+        let tracks = Tracklist::new(vec![
+            Track::new("Intro", 2.0),
+            Track::new("Single", 3.0),
+            Track::new("Deep Cut", 4.0),
+            Track::new("Epic", 9.0),
+            Track::new("Bonus", 2.0).with_hidden(),
         ]);
+        let medium = Medium::new("CD", 1, 60.0);
+        let met = |kind: ConstraintKind| Constraint::new(kind, 1).is_satisfied(&tracks, &medium);
+        assert!(met(ConstraintKind::InPositionRange("Single".into(), 0, 2)));
+        assert!(met(ConstraintKind::InPositionRange("Single".into(), 1, 1)));
+        assert!(!met(ConstraintKind::InPositionRange(
+            "Deep Cut".into(),
+            0,
+            1
+        )));
+        assert!(!met(ConstraintKind::InPositionRange("Single".into(), 2, 0)));
+        // The hidden bonus track doesn't count as the closer
+        assert!(met(ConstraintKind::ClosesAlbum("Epic".into())));
+        assert!(!met(ConstraintKind::ClosesAlbum("Bonus".into())));
+
+        // Nor does a hidden pregap shift the range
+        let pregap = Tracklist::new(
+            std::iter::once(Track::new("Pregap", 1.0).with_hidden())
+                .chain(tracks.0.iter().cloned())
+                .collect(),
+        );
+        let range = [Constraint::new(
+            ConstraintKind::InPositionRange("Single".into(), 1, 1),
+            1,
+        )];
+        assert!(range[0].is_satisfied(&pregap, &medium));
+        assert!(
+            !Constraint::new(ConstraintKind::InPositionRange("Pregap".into(), 0, 5), 1)
+                .is_satisfied(&pregap, &medium)
+        );
         assert_eq!(
-            score_tracklist(&tracks, &constraints, &medium),
-            max_score - constraints[2].weight - constraints[1].weight - constraints[0].weight
+            crate::optimize::best_tracklist_bb(&pregap.0, &range, &medium),
+            crate::optimize::best_arrangement(&pregap.0, &range, &medium)
+        );
+
+        let late = [Constraint::new(
+            ConstraintKind::InPositionRange("Single".into(), 5, 9),
+            1,
+        )];
+        assert_eq!(
+            validate_constraints(&late, &tracks.0)[0].problem,
+            ConstraintProblem::PositionOutOfRange {
+                position: 5,
+                len: 5
+            }
+        );
+    }
+
+    #[test]
+    fn test_tracks_per_side_limits() {
+        let tracks = Tracklist::new((0..6).map(|i| Track::new(format!("T{i}"), 1.0)).collect());
+        let single = Medium::new("7\"", 2, 60.0).with_max_tracks_per_side(3);
+        assert_eq!(single.side_indices(&tracks), vec![0, 0, 0, 1, 1, 1]);
+        assert!(single.fits(&tracks));
+        assert!(single.on_same_side(&tracks, "T0", "T2"));
+        assert!(!single.on_same_side(&tracks, "T2", "T3"));
+        let crowded = Medium::new("7\"", 2, 60.0).with_max_tracks_per_side(2);
+        assert!(!crowded.fits(&tracks));
+
+        let min = Medium::new("LP", 2, 4.0).with_min_tracks_per_side(3);
+        assert_eq!(min.side_indices(&tracks), vec![0, 0, 0, 0, 1, 1]);
+        assert!(!min.fits(&tracks));
+        assert!(min.fits_prefix(&Tracklist::new(tracks.0[..5].to_vec())));
+        assert_eq!(
+            min.split_at_breaks(&tracks, &[4]),
+            Err(FitError::SideTrackCount { side: 1, count: 2 })
+        );
+        assert!(min.split_at_breaks(&tracks, &[3]).is_ok());
+
+        let side = Tracklist::new(tracks.0[..3].to_vec());
+        assert_eq!(side.append_cost(3.0, &tracks.0[3], &single), None);
+        assert_eq!(
+            Tracklist::new(tracks.0[..2].to_vec()).append_cost(2.0, &tracks.0[3], &single),
+            Some(3.0)
+        );
+    }
+
+    #[test]
+    fn test_separated_constraint() {
+        let medium = Medium::new("LP", 1, 60.0);
+        let tracks = Tracklist::new(
+            ["Twin A", "X", "Y", "Twin B", "Z"]
+                .iter()
+                .map(|t| Track::new(*t, 3.0))
+                .collect(),
+        );
+        let apart = |min_gap| {
+            Constraint::new(
+                ConstraintKind::Separated("Twin B".into(), "Twin A".into(), min_gap),
+                1,
+            )
+            .is_satisfied(&tracks, &medium)
+        };
+        assert!(apart(0));
+        assert!(apart(2));
+        assert!(!apart(3));
+
+        let constraints = [Constraint::new(
+            ConstraintKind::Separated("Twin A".into(), "Twin B".into(), 3),
+            1,
+        )];
+        let (best, score) =
+            crate::optimize::best_tracklist_bb(&tracks.0, &constraints, &medium).unwrap();
+        assert_eq!(score, 1.0);
+        assert!(constraints[0].is_satisfied(&best, &medium));
+    }
+
+    #[test]
+    fn test_penalty_constraints() {
+        let tracks = Tracklist::new(vec![
+            Track::new("Ballad 1", 3.0),
+            Track::new("Ballad 2", 3.0),
+            Track::new("Rocker", 3.0),
+        ]);
+        let medium = Medium::new("LP", 1, 20.0);
+        let constraints = vec![
+            Constraint::new(ConstraintKind::AtPosition("Rocker".into(), 2), 1),
+            Constraint::new(
+                ConstraintKind::Adjacent("Ballad 1".into(), "Ballad 2".into()),
+                4,
+            )
+            .with_penalty(),
+        ];
+        // Ballads back to back: the reward, minus the penalty
+        assert!(!constraints[1].is_satisfied(&tracks, &medium));
+        assert_eq!(score_tracklist(&tracks, &constraints, &medium), 1.0);
+        assert_eq!(
+            score_tracklist_signed(&tracks, &constraints, &medium),
+            Some(-3.0)
+        );
+
+        let (best, score) =
+            crate::optimize::best_arrangement(&tracks.0, &constraints, &medium).unwrap();
+        assert_eq!(best.titles(), vec!["Ballad 2", "Ballad 1", "Rocker"]);
+        assert_eq!(score, 5.0);
+        assert_eq!(
+            score_tracklist_signed(&best, &constraints, &medium),
+            Some(1.0)
+        );
+
+        let too_short = Medium::new("Single", 1, 5.0);
+        assert_eq!(
+            score_tracklist_signed(&tracks, &constraints, &too_short),
+            None
         );
     }
+
+    #[test]
+    fn test_score_normalized() {
+        let tracks = Tracklist::new(vec![
+            Track::new("A", 3.0).with_energy(0.0),
+            Track::new("B", 3.0).with_energy(1.0),
+        ]);
+        let medium = Medium::new("LP", 1, 20.0);
+        let constraints = vec![
+            Constraint::new(ConstraintKind::AtPosition("A".into(), 0), 3),
+            Constraint::new(ConstraintKind::AtPosition("B".into(), 0), 1),
+        ];
+        assert_eq!(score_normalized(&tracks, &constraints, &medium), 0.75);
+        assert_eq!(score_normalized(&tracks, &[], &medium), 1.0);
+        assert_eq!(
+            score_normalized(&tracks, &constraints, &Medium::new("Short", 1, 5.0)),
+            0.0
+        );
+
+        // A proportional kind earns its exact share of the weight
+        let curve = [Constraint::new(
+            ConstraintKind::EnergyCurve(vec![0.0, 0.6]),
+            1,
+        )];
+        let satisfaction = curve[0]
+            .kind
+            .satisfaction(&tracks, &medium, &MatchOptions::default());
+        assert!(satisfaction > 0.0 && satisfaction < 1.0);
+        assert_eq!(score_normalized(&tracks, &curve, &medium), satisfaction);
+    }
+
+    #[test]
+    fn test_fractional_weights() {
+        let tracks = Tracklist::from(vec![("A", 3.0), ("B", 3.0), ("C", 3.0)]);
+        let medium = Medium::new("LP", 1, 20.0);
+        let constraints = vec![
+            Constraint::new(ConstraintKind::AtPosition("A".into(), 0), 2.5),
+            Constraint::new(ConstraintKind::Adjacent("C".into(), "B".into()), 0.75),
+        ];
+        assert_eq!(score_tracklist(&tracks, &constraints, &medium), 2.5);
+        assert_eq!(score_normalized(&tracks, &constraints, &medium), 2.5 / 3.25);
+
+        let report = score_report(&tracks, &constraints, &medium);
+        assert_eq!(report.constraints[1].lost, 0.75);
+
+        // The fractional weight still tips the search
+        let (best, score) =
+            crate::optimize::best_arrangement(&tracks.0, &constraints, &medium).unwrap();
+        assert_eq!(best.titles(), vec!["A", "C", "B"]);
+        assert_eq!(score, 3.25);
+    }
+
+    #[test]
+    fn test_find_conflicts() {
+        let tracks: Vec<Track> = ["A", "B", "C", "D"]
+            .iter()
+            .map(|t| Track::new(*t, 2.0))
+            .collect();
+        let c = |kind| Constraint::new(kind, 1);
+        let constraints = vec![
+            c(ConstraintKind::AtPosition("A".into(), 0)),
+            c(ConstraintKind::AtPosition("B".into(), 0)),
+            c(ConstraintKind::Before("B".into(), "C".into())),
+            c(ConstraintKind::Adjacent("C".into(), "D".into())),
+            c(ConstraintKind::Before("D".into(), "B".into())),
+            c(ConstraintKind::NotAdjacent("D".into(), "C".into())),
+            c(ConstraintKind::AtPosition("C".into(), 3)),
+            c(ConstraintKind::Before("A".into(), "C".into())).with_penalty(),
+        ];
+        let conflicts = find_conflicts(&constraints, &tracks);
+        assert_eq!(
+            conflicts
+                .iter()
+                .map(|c| c.constraints.clone())
+                .collect::<Vec<_>>(),
+            vec![vec![0, 1], vec![2, 3, 4], vec![3, 5]]
+        );
+        assert_eq!(
+            conflicts[1].to_string(),
+            "constraints 2, 3, 4 can't all be met"
+        );
+
+        // Two tracks titled "A" may fill both slots
+        let doubled = [tracks.clone(), vec![Track::new("A", 2.0)]].concat();
+        let pinned = vec![
+            c(ConstraintKind::AtPosition("A".into(), 0)),
+            c(ConstraintKind::AtPosition("A".into(), 1)),
+        ];
+        assert_eq!(find_conflicts(&pinned, &tracks).len(), 1);
+        assert!(find_conflicts(&pinned, &doubled).is_empty());
+    }
+
+    #[test]
+    fn test_score_report() {
+        let medium = Medium::new("test_medium", 2, 10.0);
+        let tracklist = Tracklist::from(vec![("A", 4.0), ("B", 5.0), ("C", 6.0)]);
+        let constraints = vec![
+            Constraint::new(ConstraintKind::AtPosition("A".into(), 0), 3),
+            Constraint::new(ConstraintKind::OnSameSide("A".into(), "C".into()), 2),
+            Constraint::new(ConstraintKind::Before("C".into(), "A".into()), 1).with_penalty(),
+        ];
+
+        let report = score_report(&tracklist, &constraints, &medium);
+        assert_eq!(
+            report.score,
+            score_tracklist(&tracklist, &constraints, &medium)
+        );
+        assert_eq!(report.score, 4.0);
+        assert!(report.fits && report.feasible);
+        assert_eq!(report.sides, vec![0, 0, 1]);
+        let outcome: Vec<(bool, f64, f64)> = report
+            .constraints
+            .iter()
+            .map(|r| (r.satisfied, r.earned, r.lost))
+            .collect();
+        assert_eq!(
+            outcome,
+            vec![(true, 3.0, 0.0), (false, 0.0, 2.0), (true, 1.0, 0.0)]
+        );
+
+        // A broken hard constraint is still pointed out
+        let hard = vec![constraints[1].clone().with_hard()];
+        let report = score_report(&tracklist, &hard, &medium);
+        assert!(report.fits && !report.feasible);
+        assert_eq!((report.score, report.constraints[0].lost), (0.0, 2.0));
+    }
+
+    #[test]
+    fn test_compare() {
+        let medium = Medium::new("test_medium", 1, 100.0);
+        let a = Tracklist::from(vec![("A", 1.0), ("B", 1.0), ("C", 1.0)]);
+        let b = Tracklist::from(vec![("C", 1.0), ("B", 1.0), ("A", 1.0)]);
+        let constraints = vec![
+            Constraint::new(ConstraintKind::AtPosition("A".into(), 0), 3),
+            Constraint::new(ConstraintKind::AtPosition("C".into(), 0), 5),
+            Constraint::new(ConstraintKind::AtPosition("B".into(), 1), 1),
+        ];
+
+        let comparison = compare(&a, &b, &constraints, &medium);
+        assert_eq!(comparison.score_delta, 2.0);
+        assert_eq!(comparison.newly_satisfied, vec![1]);
+        assert_eq!(comparison.newly_violated, vec![0]);
+        assert_eq!(
+            comparison.diff.moved,
+            vec![
+                TrackMove {
+                    title: "A",
+                    from: 0,
+                    to: 2
+                },
+                TrackMove {
+                    title: "C",
+                    from: 2,
+                    to: 0
+                },
+            ]
+        );
+        assert_eq!(comparison.kendall_tau, 1.0);
+        assert_eq!(compare(&a, &a, &constraints, &medium).kendall_tau, 0.0);
+
+        // Once A's opening is hard, b is infeasible: every helper agrees it
+        // earns nothing
+        let mut hard = constraints.clone();
+        hard[0].hard = true;
+        let comparison = compare(&a, &b, &hard, &medium);
+        assert_eq!(comparison.score_delta, -4.0);
+        assert!(comparison.newly_satisfied.is_empty());
+        assert_eq!(comparison.newly_violated, vec![0, 2]);
+        assert_eq!(unsatisfied_constraints(&b, &hard, &medium), vec![1, 0, 2]);
+        assert_eq!(per_side_satisfaction(&b, &hard, &medium), vec![0.0]);
+        assert_eq!(track_contributions(&b, &hard, &medium), vec![0.0, 0.0, 0.0]);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        let tracklist = Tracklist::new(vec![
+            Track::new("Intro", 90.0).with_tag("opener").with_id(0),
+            Track::new("Song", 200.0).with_bpm(120.0).with_hidden(),
+        ]);
+        let medium = Medium::lp().with_gap(2.0).with_max_tracks_per_side(4);
+        let constraints = vec![
+            Constraint::new(ConstraintKind::AtPosition("Intro".into(), 0), 3).with_hard(),
+            Constraint::new(
+                ConstraintKind::Group(vec!["Intro".into(), "Song".into()], true),
+                2,
+            )
+            .with_priority(1)
+            .with_penalty(),
+        ];
+
+        let json = serde_json::to_string(&(&tracklist, &medium, &constraints)).unwrap();
+        let (tracklist2, medium2, constraints2): (Tracklist, Medium, Vec<Constraint>) =
+            serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            serde_json::to_string(&(&tracklist2, &medium2, &constraints2)).unwrap(),
+            json
+        );
+        assert_eq!(tracklist2, tracklist);
+        assert!(tracklist2.0[1].hidden);
+        assert_eq!(
+            score_tracklist(&tracklist2, &constraints2, &medium2),
+            score_tracklist(&tracklist, &constraints, &medium)
+        );
+        assert!(json.starts_with(r#"[[{"title":"Intro""#));
+
+        let custom = ConstraintKind::custom(|_: &Tracklist, _: &Medium| true);
+        assert!(serde_json::to_string(&custom).is_err());
+    }
 }
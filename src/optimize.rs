@@ -0,0 +1,2434 @@
+//! Searches over track orderings.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use itertools::Itertools;
+
+use crate::rng::Rng;
+use crate::{
+    Constraint, ConstraintKind, IndexError, IndexPermutations, MatchOptions, Medium, SwapScorer,
+    Track, Tracklist, TracklistPermutations, factorial, feasible_score, is_feasible,
+    nth_permutation, score_delta, score_tracklist,
+};
+
+/// How many permutations the timed search scores between clock checks.
+const CLOCK_CHECK_INTERVAL: usize = 1024;
+
+/// Find the highest-scoring feasible ordering (see `is_feasible`) by scoring
+/// every permutation, or `None` if no ordering fits the medium and satisfies
+/// every hard constraint.
+///
+/// Ties keep the first feasible ordering in permutation order, so the input
+/// order wins when it is feasible and nothing scores. Brute force, same
+/// track-count ceiling as `best_coherent`.
+pub fn best_arrangement(
+    tracks: &[Track],
+    constraints: &[Constraint],
+    medium: &Medium,
+) -> Option<(Tracklist, f64)> {
+    best_arrangement_timed(tracks, constraints, medium, std::time::Duration::MAX).0
+}
+
+/// `best_arrangement` without the score.
+pub fn find_best(
+    tracks: &[Track],
+    constraints: &[Constraint],
+    medium: &Medium,
+) -> Option<Tracklist> {
+    best_arrangement(tracks, constraints, medium).map(|(tracklist, _)| tracklist)
+}
+
+/// `best_arrangement` spread over threads with rayon.
+///
+/// The permutations are partitioned by their leading track and each partition
+/// is searched on its own, then the per-partition bests are merged. Returns
+/// exactly what `best_arrangement` does, tie-breaking included.
+#[cfg(feature = "rayon")]
+pub fn best_arrangement_par(
+    tracks: &[Track],
+    constraints: &[Constraint],
+    medium: &Medium,
+) -> Option<(Tracklist, f64)> {
+    use rayon::prelude::*;
+
+    if tracks.is_empty() {
+        return best_arrangement(tracks, constraints, medium);
+    }
+    (0..tracks.len())
+        .into_par_iter()
+        .map(|lead| {
+            let rest: Vec<&Track> = tracks
+                .iter()
+                .enumerate()
+                .filter(|&(i, _)| i != lead)
+                .map(|(_, t)| t)
+                .collect();
+            let mut best: Option<(Tracklist, f64)> = None;
+            // Same order as `TracklistPermutations` within this partition.
+            for perm in rest.iter().permutations(rest.len()) {
+                let tracklist = Tracklist::new(
+                    std::iter::once(&tracks[lead])
+                        .chain(perm.into_iter().copied())
+                        .cloned()
+                        .collect(),
+                );
+                let Some(score) = feasible_score(&tracklist, constraints, medium) else {
+                    continue;
+                };
+                if best.as_ref().is_none_or(|(_, b)| score > *b) {
+                    best = Some((tracklist, score));
+                }
+            }
+            best.map(|(tracklist, score)| (lead, tracklist, score))
+        })
+        .flatten()
+        // Higher score wins; ties keep the earlier partition.
+        .reduce_with(|a, b| {
+            if b.2 > a.2 || (b.2 == a.2 && b.0 < a.0) {
+                b
+            } else {
+                a
+            }
+        })
+        .map(|(_, tracklist, score)| (tracklist, score))
+}
+
+/// Like `best_arrangement`, but stops once `budget` has elapsed.
+///
+/// Returns the best feasible ordering found so far with its score (`None`
+/// if none was), and whether the search finished exhaustively (in which case
+/// the result matches `best_arrangement`). The clock is only checked every
+/// `CLOCK_CHECK_INTERVAL` permutations, so the budget may be overrun slightly.
+pub fn best_arrangement_timed(
+    tracks: &[Track],
+    constraints: &[Constraint],
+    medium: &Medium,
+    budget: std::time::Duration,
+) -> (Option<(Tracklist, f64)>, bool) {
+    let start = std::time::Instant::now();
+    let mut best: Option<(Tracklist, f64)> = None;
+
+    let mut perms = IndexPermutations::new(tracks);
+    let mut examined = 0;
+    while let Some((_, tracklist)) = perms.next_permutation() {
+        record_if_better(&mut best, tracklist, constraints, medium);
+        examined += 1;
+        if examined % CLOCK_CHECK_INTERVAL == 0 && start.elapsed() >= budget {
+            return (best, false);
+        }
+    }
+
+    (best, true)
+}
+
+/// Keep `tracklist` as the new best if it is feasible and strictly outscores
+/// the current best (or there is none yet).
+fn record_if_better(
+    best: &mut Option<(Tracklist, f64)>,
+    tracklist: &Tracklist,
+    constraints: &[Constraint],
+    medium: &Medium,
+) {
+    let Some(score) = feasible_score(tracklist, constraints, medium) else {
+        return;
+    };
+    if best.as_ref().is_none_or(|(_, b)| score > *b) {
+        *best = Some((tracklist.clone(), score));
+    }
+}
+
+/// Progress reporting and cancellation for long searches.
+///
+/// Searches taking a control poll it every `CLOCK_CHECK_INTERVAL` steps:
+/// the progress callback gets `(examined, best_score_so_far)`, and once the
+/// cancel flag is set the search stops and returns its best so far.
+#[derive(Default)]
+pub struct SearchControl<'a> {
+    progress: Option<Box<dyn FnMut(usize, f64) + 'a>>,
+    cancel: Option<&'a AtomicBool>,
+}
+
+impl<'a> SearchControl<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_progress(mut self, progress: impl FnMut(usize, f64) + 'a) -> Self {
+        self.progress = Some(Box::new(progress));
+        self
+    }
+
+    /// Stop as soon as `cancel` is seen set; another thread may set it.
+    pub fn with_cancel(mut self, cancel: &'a AtomicBool) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+
+    /// Report progress every `CLOCK_CHECK_INTERVAL` steps; `false` once cancelled.
+    fn poll(&mut self, examined: usize, best_score: f64) -> bool {
+        if !examined.is_multiple_of(CLOCK_CHECK_INTERVAL) {
+            return true;
+        }
+        if let Some(progress) = &mut self.progress {
+            progress(examined, best_score);
+        }
+        self.cancel.is_none_or(|c| !c.load(Ordering::Relaxed))
+    }
+}
+
+/// `best_arrangement` with progress reporting and cancellation.
+///
+/// Returns the best feasible ordering found with its score (`None` if none
+/// was), and whether every permutation was examined (`false` when
+/// cancelled).
+pub fn best_arrangement_controlled(
+    tracks: &[Track],
+    constraints: &[Constraint],
+    medium: &Medium,
+    control: &mut SearchControl,
+) -> (Option<(Tracklist, f64)>, bool) {
+    let mut best: Option<(Tracklist, f64)> = None;
+
+    let mut perms = IndexPermutations::new(tracks);
+    let mut examined = 0;
+    while let Some((_, tracklist)) = perms.next_permutation() {
+        record_if_better(&mut best, tracklist, constraints, medium);
+        examined += 1;
+        if !control.poll(examined, best.as_ref().map_or(0.0, |(_, b)| *b)) {
+            return (best, false);
+        }
+    }
+
+    (best, true)
+}
+
+/// Saved progress of an exhaustive search, see `resume_arrangement`.
+///
+/// Orderings are identified by their index in `TracklistPermutations` order
+/// (see `nth_permutation`), so the state is two numbers and a score and stays
+/// valid as long as the track pool and its order are unchanged. With the
+/// `serde` feature it can be written to disk between runs.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SearchCheckpoint {
+    /// Index of the next ordering to score.
+    pub next_index: u128,
+    /// Index and score of the best feasible ordering so far.
+    pub best: Option<(u128, f64)>,
+    /// Whether every ordering has been scored.
+    pub finished: bool,
+}
+
+impl SearchCheckpoint {
+    /// The best ordering so far and its score, for the same `tracks`.
+    pub fn best_tracklist(&self, tracks: &[Track]) -> Option<(Tracklist, f64)> {
+        let (index, score) = self.best?;
+        Some((nth_permutation(tracks, index)?, score))
+    }
+}
+
+/// Continue an exhaustive search from `checkpoint`, scoring at most `limit`
+/// more orderings, and return the updated checkpoint.
+///
+/// Start from `SearchCheckpoint::default()` and call repeatedly (saving the
+/// checkpoint in between) until `finished`; the best ordering is then exactly
+/// what `best_arrangement` returns, tie-breaking included.
+pub fn resume_arrangement(
+    tracks: &[Track],
+    constraints: &[Constraint],
+    medium: &Medium,
+    mut checkpoint: SearchCheckpoint,
+    limit: usize,
+) -> SearchCheckpoint {
+    if checkpoint.finished {
+        return checkpoint;
+    }
+    let Some(mut perms) = IndexPermutations::starting_at(tracks, checkpoint.next_index) else {
+        checkpoint.finished = true;
+        return checkpoint;
+    };
+    for _ in 0..limit {
+        let Some((_, tracklist)) = perms.next_permutation() else {
+            checkpoint.finished = true;
+            break;
+        };
+        if let Some(score) = feasible_score(tracklist, constraints, medium)
+            && checkpoint.best.is_none_or(|(_, b)| score > b)
+        {
+            checkpoint.best = Some((checkpoint.next_index, score));
+        }
+        checkpoint.next_index += 1;
+    }
+    if factorial(tracks.len()) == Some(checkpoint.next_index) {
+        checkpoint.finished = true;
+    }
+    checkpoint
+}
+
+/// Every ordering that keeps `tracks[block]` in place, permuting only the rest.
+fn fixed_block_candidates<'a>(
+    tracks: &'a [Track],
+    block: std::ops::Range<usize>,
+) -> impl Iterator<Item = Tracklist> + 'a {
+    let free: Vec<&Track> = tracks
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| !block.contains(i))
+        .map(|(_, t)| t)
+        .collect();
+    let len = free.len();
+
+    free.into_iter().permutations(len).map(move |perm| {
+        let mut perm = perm.into_iter();
+        Tracklist::new(
+            (0..tracks.len())
+                .map(|i| {
+                    if block.contains(&i) {
+                        tracks[i].clone()
+                    } else {
+                        perm.next().unwrap().clone()
+                    }
+                })
+                .collect(),
+        )
+    })
+}
+
+/// Like `best_arrangement`, but `tracks[block]` stay at their positions and in
+/// their order; only the remaining tracks are permuted among the other slots.
+///
+/// `Ok(None)` if no such ordering is feasible. Errors if `block` extends
+/// past the end of `tracks`.
+pub fn best_tracklist_with_fixed_block(
+    tracks: &[Track],
+    block: std::ops::Range<usize>,
+    constraints: &[Constraint],
+    medium: &Medium,
+) -> Result<Option<(Tracklist, f64)>, IndexError> {
+    if block.end > tracks.len() {
+        return Err(IndexError {
+            index: block.end,
+            len: tracks.len(),
+        });
+    }
+
+    let mut best: Option<(Tracklist, f64)> = None;
+    for tracklist in fixed_block_candidates(tracks, block) {
+        record_if_better(&mut best, &tracklist, constraints, medium);
+    }
+    Ok(best)
+}
+
+/// Heap entry for `top_arrangements`, ordered by score and then by
+/// permutation index (earlier ranks higher).
+struct Ranked {
+    score: f64,
+    index: usize,
+    tracklist: Tracklist,
+}
+
+impl PartialEq for Ranked {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for Ranked {}
+
+impl PartialOrd for Ranked {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Ranked {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.score
+            .total_cmp(&other.score)
+            .then(other.index.cmp(&self.index))
+    }
+}
+
+/// The `k` highest-scoring feasible orderings (see `is_feasible`), best
+/// first; fewer than `k` come back if fewer are feasible.
+///
+/// Streams permutations through a min-heap of size `k`, so memory stays O(k).
+/// Equal scores keep the ordering seen first in permutation order, matching
+/// `best_arrangement`, so `top_arrangements(.., 1)` agrees with it.
+pub fn top_arrangements(
+    tracks: &[Track],
+    constraints: &[Constraint],
+    medium: &Medium,
+    k: usize,
+) -> Vec<(Tracklist, f64)> {
+    use std::cmp::Reverse;
+    use std::collections::BinaryHeap;
+
+    if k == 0 {
+        return Vec::new();
+    }
+
+    let mut heap: BinaryHeap<Reverse<Ranked>> = BinaryHeap::with_capacity(k + 1);
+    for (index, perm) in TracklistPermutations::new(tracks).enumerate() {
+        let tracklist = Tracklist::new(perm.into_iter().cloned().collect());
+        let Some(score) = feasible_score(&tracklist, constraints, medium) else {
+            continue;
+        };
+        // Later permutations only get in by strictly beating the current worst.
+        if heap.len() == k
+            && heap
+                .peek()
+                .is_some_and(|Reverse(worst)| score <= worst.score)
+        {
+            continue;
+        }
+        heap.push(Reverse(Ranked {
+            score,
+            index,
+            tracklist,
+        }));
+        if heap.len() > k {
+            heap.pop();
+        }
+    }
+
+    heap.into_sorted_vec()
+        .into_iter()
+        .map(|Reverse(ranked)| (ranked.tracklist, ranked.score))
+        .collect()
+}
+
+/// Former name of `top_arrangements`.
+#[deprecated(note = "use `top_arrangements`")]
+pub fn find_top_k(
+    tracks: &[Track],
+    constraints: &[Constraint],
+    medium: &Medium,
+    k: usize,
+) -> Vec<(Tracklist, f64)> {
+    top_arrangements(tracks, constraints, medium, k)
+}
+
+/// The best-scoring feasible ordering (see `is_feasible`) of any `k` tracks
+/// chosen from `pool`, with its score, or `None` if no such selection is
+/// feasible (or `k` exceeds the pool).
+///
+/// Selection and ordering are searched together over all n!/(n-k)! ordered
+/// selections, so this is heavier than `best_arrangement` on `k` tracks:
+/// picking 6 of 12 is about 665k orderings and still quick, but much past
+/// a few million it isn't practical. Ties keep the first selection in pool
+/// order.
+pub fn best_subset_tracklist(
+    pool: &[Track],
+    k: usize,
+    constraints: &[Constraint],
+    medium: &Medium,
+) -> Option<(Tracklist, f64)> {
+    let mut best: Option<(Tracklist, f64)> = None;
+    for perm in pool.iter().permutations(k) {
+        let tracklist = Tracklist::new(perm.into_iter().cloned().collect());
+        record_if_better(&mut best, &tracklist, constraints, medium);
+    }
+    best
+}
+
+/// Tuning for `optimize_genetic`.
+#[derive(Debug, Clone, Copy)]
+pub struct GaParams {
+    pub population_size: usize,
+    pub generations: usize,
+    /// Probability in `0.0..=1.0` that a child gets a random swap.
+    pub mutation_rate: f64,
+}
+
+impl Default for GaParams {
+    fn default() -> Self {
+        Self {
+            population_size: 50,
+            generations: 200,
+            mutation_rate: 0.2,
+        }
+    }
+}
+
+/// Build the tracklist for an index permutation of `tracks`.
+fn tracklist_from_order(tracks: &[Track], order: &[usize]) -> Tracklist {
+    Tracklist::new(order.iter().map(|&i| tracks[i].clone()).collect())
+}
+
+/// Order crossover (OX): keep a slice of `a`, fill the rest in `b`'s order.
+fn order_crossover(a: &[usize], b: &[usize], rng: &mut Rng) -> Vec<usize> {
+    let len = a.len();
+    let (mut start, mut end) = (rng.below(len), rng.below(len));
+    if start > end {
+        std::mem::swap(&mut start, &mut end);
+    }
+
+    let mut child = vec![usize::MAX; len];
+    child[start..=end].copy_from_slice(&a[start..=end]);
+    let mut fill = b.iter().filter(|g| !a[start..=end].contains(g));
+    for slot in child.iter_mut().filter(|g| **g == usize::MAX) {
+        *slot = *fill.next().unwrap();
+    }
+    child
+}
+
+/// How far `tracklist` is from fitting: each side's overrun of the nominal
+/// capacity plus the used time of every side spilling past `medium.sides`.
+/// Zero when it fits.
+fn fit_violation(tracklist: &Tracklist, medium: &Medium) -> f64 {
+    if medium.fits(tracklist) {
+        return 0.0;
+    }
+    let spilled: f64 = medium
+        .side_stats(tracklist)
+        .iter()
+        .skip(medium.sides)
+        .map(|stat| stat.used)
+        .sum();
+    let overrun: f64 = medium.side_overrun(tracklist).iter().sum();
+    // Never 0, so a non-fitting order ranks below every fitting one.
+    (spilled + overrun).max(f64::MIN_POSITIVE)
+}
+
+/// Genetic fitness: the score of a feasible order, or minus how far it is
+/// from feasible (its fit violation plus the unmet share of each hard
+/// constraint), so infeasible orders are still ranked by how close they come.
+fn genetic_fitness(tracklist: &Tracklist, constraints: &[Constraint], medium: &Medium) -> f64 {
+    if let Some(score) = feasible_score(tracklist, constraints, medium) {
+        return score;
+    }
+    let shortfall: f64 = constraints
+        .iter()
+        .filter(|c| c.hard)
+        .map(|c| 1.0 - c.satisfaction(tracklist, medium, &MatchOptions::default()))
+        .sum();
+    // Never 0, so an infeasible order ranks below every feasible one.
+    -(fit_violation(tracklist, medium) + shortfall).max(f64::MIN_POSITIVE)
+}
+
+/// Run the genetic search, reporting each generation's best order to `on_generation`.
+fn run_genetic(
+    tracks: &[Track],
+    constraints: &[Constraint],
+    medium: &Medium,
+    params: GaParams,
+    seed: u64,
+    mut on_generation: impl FnMut(&[usize]),
+) -> Vec<usize> {
+    let len = tracks.len();
+    if len < 2 {
+        return (0..len).collect();
+    }
+    let mut rng = Rng::new(seed);
+    let fitness = |order: &[usize]| {
+        genetic_fitness(&tracklist_from_order(tracks, order), constraints, medium)
+    };
+
+    // The input order plus random shuffles
+    let mut population: Vec<(Vec<usize>, f64)> = (0..params.population_size.max(2))
+        .map(|i| {
+            let mut order: Vec<usize> = (0..len).collect();
+            if i > 0 {
+                rng.shuffle(&mut order);
+            }
+            let score = fitness(&order);
+            (order, score)
+        })
+        .collect();
+
+    let mut best = population[0].clone();
+    for _ in 0..params.generations {
+        // Ties prefer the earlier individual so the run is fully determined by the seed
+        let generation_best = population
+            .iter()
+            .rev()
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .unwrap()
+            .clone();
+        if generation_best.1 > best.1 {
+            best = generation_best.clone();
+        }
+        on_generation(&generation_best.0);
+
+        let tournament = |rng: &mut Rng| {
+            let a = &population[rng.below(population.len())];
+            let b = &population[rng.below(population.len())];
+            if b.1 > a.1 { b.0.clone() } else { a.0.clone() }
+        };
+        let mut next = vec![generation_best];
+        while next.len() < population.len() {
+            let (a, b) = (tournament(&mut rng), tournament(&mut rng));
+            let mut child = order_crossover(&a, &b, &mut rng);
+            if rng.next_f64() < params.mutation_rate {
+                let (i, j) = (rng.below(len), rng.below(len));
+                child.swap(i, j);
+            }
+            let score = fitness(&child);
+            next.push((child, score));
+        }
+        population = next;
+    }
+
+    best.0
+}
+
+/// Population-based search for large tracklists, where exhaustive search is hopeless.
+///
+/// Individuals are orderings; children come from order crossover (OX) of two
+/// tournament-selected parents followed by a random swap with probability
+/// `mutation_rate`, and the best individual always survives. Fitness is
+/// `score_tracklist` for feasible orderings (see `is_feasible`), and minus
+/// the distance from feasible (side overruns, time spilled past the last
+/// side, and the unmet share of each hard constraint) for the rest, so the
+/// search is drawn toward feasible layouts. The result is deterministic for a
+/// given `seed` and never worse than the input order.
+///
+/// Returns `None` when the search ends without a feasible ordering, e.g. when
+/// no ordering fits the medium: the fittest infeasible one is not returned.
+pub fn optimize_genetic(
+    tracks: &[Track],
+    constraints: &[Constraint],
+    medium: &Medium,
+    params: GaParams,
+    seed: u64,
+) -> Option<Tracklist> {
+    let order = run_genetic(tracks, constraints, medium, params, seed, |_| {});
+    Some(tracklist_from_order(tracks, &order))
+        .filter(|tracklist| is_feasible(tracklist, constraints, medium))
+}
+
+/// How `anneal` lowers the temperature over its iterations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CoolingSchedule {
+    /// Straight line from the start to the end temperature.
+    Linear,
+    /// Constant ratio per step; falls back to `Linear` unless both
+    /// temperatures are positive.
+    Geometric,
+}
+
+/// Tuning for `anneal`.
+#[derive(Debug, Clone, Copy)]
+pub struct AnnealParams {
+    pub iterations: usize,
+    /// Temperature at the first iteration, in score points.
+    pub start_temperature: f64,
+    /// Temperature reached at the last iteration.
+    pub end_temperature: f64,
+    pub schedule: CoolingSchedule,
+    /// Probability in `0.0..=1.0` that a move re-inserts one track elsewhere
+    /// instead of swapping two.
+    pub insert_rate: f64,
+}
+
+impl Default for AnnealParams {
+    fn default() -> Self {
+        Self {
+            iterations: 10_000,
+            start_temperature: 5.0,
+            end_temperature: 0.01,
+            schedule: CoolingSchedule::Geometric,
+            insert_rate: 0.5,
+        }
+    }
+}
+
+impl AnnealParams {
+    /// Temperature after `step` of `iterations` steps.
+    fn temperature(&self, step: usize) -> f64 {
+        let progress = step as f64 / self.iterations as f64;
+        let (start, end) = (self.start_temperature, self.end_temperature);
+        match self.schedule {
+            CoolingSchedule::Geometric if start > 0.0 && end > 0.0 => {
+                start * (end / start).powf(progress)
+            }
+            _ => start + (end - start) * progress,
+        }
+    }
+}
+
+/// Run the annealer, returning the best feasible order seen and its score
+/// (`None` if it saw none), and whether it ran every iteration.
+fn run_annealing(
+    tracks: &[Track],
+    constraints: &[Constraint],
+    medium: &Medium,
+    params: AnnealParams,
+    seed: u64,
+    control: &mut SearchControl,
+) -> (Option<(Vec<usize>, f64)>, bool) {
+    let len = tracks.len();
+    let mut rng = Rng::new(seed);
+    let score = |order: &[usize]| {
+        score_tracklist(&tracklist_from_order(tracks, order), constraints, medium)
+    };
+    // Infeasible orders score 0, so only a score of 0 needs the full check.
+    let record = |best: &mut Option<(Vec<usize>, f64)>, order: &[usize], score: f64| {
+        if best.as_ref().is_none_or(|(_, b)| score > *b)
+            && (score > 0.0
+                || is_feasible(&tracklist_from_order(tracks, order), constraints, medium))
+        {
+            *best = Some((order.to_vec(), score));
+        }
+    };
+
+    let mut order: Vec<usize> = (0..len).collect();
+    rng.shuffle(&mut order);
+    let mut current = score(&order);
+    let mut best = None;
+    record(&mut best, &order, current);
+    if len < 2 {
+        return (best, true);
+    }
+
+    for step in 0..params.iterations {
+        if !control.poll(step, best.as_ref().map_or(0.0, |(_, b)| *b)) {
+            return (best, false);
+        }
+        let t = params.temperature(step);
+        // The move type is only drawn when inserts are enabled, so swap-only
+        // runs draw one fewer random number per step.
+        let insert = params.insert_rate > 0.0 && rng.next_f64() < params.insert_rate;
+        let (i, j) = (rng.below(len), rng.below(len));
+        if insert {
+            let track = order.remove(i);
+            order.insert(j, track);
+        } else {
+            order.swap(i, j);
+        }
+        let candidate = score(&order);
+        let accept = candidate >= current || rng.next_f64() < (-(current - candidate) / t).exp();
+        if accept {
+            current = candidate;
+            record(&mut best, &order, current);
+        } else if insert {
+            let track = order.remove(j);
+            order.insert(i, track);
+        } else {
+            order.swap(i, j);
+        }
+    }
+    (best, true)
+}
+
+/// Simulated annealing with a configurable schedule and move mix.
+///
+/// Starts from a seeded shuffle of `tracks`; each iteration either swaps two
+/// random positions or moves one track to another position (with
+/// probability `insert_rate`). Improvements are always taken; a move losing
+/// `d` points is taken with probability `exp(-d / t)` at the current
+/// temperature `t`. Returns the best feasible ordering seen (see
+/// `is_feasible`), or `None` if it saw none; deterministic for a given `seed`.
+pub fn anneal(
+    tracks: &[Track],
+    constraints: &[Constraint],
+    medium: &Medium,
+    params: AnnealParams,
+    seed: u64,
+) -> Option<Tracklist> {
+    let (best, _) = run_annealing(
+        tracks,
+        constraints,
+        medium,
+        params,
+        seed,
+        &mut SearchControl::new(),
+    );
+    best.map(|(order, _)| tracklist_from_order(tracks, &order))
+}
+
+/// `anneal` with progress reporting and cancellation, counting iterations.
+///
+/// Returns the best feasible ordering seen with its score (`None` if none
+/// was), and whether every iteration ran (`false` when cancelled). Until
+/// cancelled it follows the same path as `anneal` with the same `seed`.
+pub fn anneal_controlled(
+    tracks: &[Track],
+    constraints: &[Constraint],
+    medium: &Medium,
+    params: AnnealParams,
+    seed: u64,
+    control: &mut SearchControl,
+) -> (Option<(Tracklist, f64)>, bool) {
+    let (best, complete) = run_annealing(tracks, constraints, medium, params, seed, control);
+    (
+        best.map(|(order, score)| (tracklist_from_order(tracks, &order), score)),
+        complete,
+    )
+}
+
+/// Simulated annealing over orderings.
+///
+/// Starts from a seeded shuffle of `tracks` and proposes swaps of two random
+/// positions for `iterations` steps. Improvements are always taken; a move
+/// losing `d` points is taken with probability `exp(-d / t)`, where the
+/// temperature `t` cools linearly from the heaviest constraint weight to zero.
+/// Returns the best feasible ordering seen and its `score_tracklist` score;
+/// deterministic for a given `seed`. See `anneal` for other schedules.
+///
+/// Returns `None` when no ordering it visited was feasible (see
+/// `is_feasible`), rather than the best-scoring infeasible one.
+pub fn optimize_annealing(
+    tracks: &[Track],
+    constraints: &[Constraint],
+    medium: &Medium,
+    iterations: usize,
+    seed: u64,
+) -> Option<(Tracklist, f64)> {
+    let t_start = constraints.iter().map(|c| c.weight).fold(1.0, f64::max);
+    let params = AnnealParams {
+        iterations,
+        start_temperature: t_start,
+        end_temperature: 0.0,
+        schedule: CoolingSchedule::Linear,
+        insert_rate: 0.0,
+    };
+    let (best, _) = run_annealing(
+        tracks,
+        constraints,
+        medium,
+        params,
+        seed,
+        &mut SearchControl::new(),
+    );
+    best.map(|(order, score)| (tracklist_from_order(tracks, &order), score))
+}
+
+/// Run `optimize_annealing` `restarts` times and keep the best result.
+///
+/// Each run gets its own seed drawn from a generator seeded with `seed`, so
+/// the whole search is reproducible. Ties keep the earliest run; zero
+/// restarts returns the input order and its score.
+///
+/// Returns `None` when no run found a feasible ordering, or with zero
+/// restarts when the input order isn't feasible.
+pub fn optimize_annealing_restarts(
+    tracks: &[Track],
+    constraints: &[Constraint],
+    medium: &Medium,
+    restarts: usize,
+    iterations: usize,
+    seed: u64,
+) -> Option<(Tracklist, f64)> {
+    if restarts == 0 {
+        let mut input = None;
+        record_if_better(
+            &mut input,
+            &Tracklist::new(tracks.to_vec()),
+            constraints,
+            medium,
+        );
+        return input;
+    }
+    let mut seeds = Rng::new(seed);
+    let mut best: Option<(Tracklist, f64)> = None;
+    for _ in 0..restarts {
+        let run = optimize_annealing(tracks, constraints, medium, iterations, seeds.next_u64());
+        if let Some(run) = run
+            && best.as_ref().is_none_or(|(_, b)| run.1 > *b)
+        {
+            best = Some(run);
+        }
+    }
+    best
+}
+
+/// Anytime search: randomized hill-climbing with restarts until `budget` has
+/// elapsed, returning the best ordering found and its score.
+///
+/// Starts from the input order, then repeatedly applies the first improving
+/// swap (scanning pairs from a random offset, scored with `score_delta`)
+/// until no swap improves, and restarts from a random shuffle. Only feasible
+/// orderings (see `is_feasible`) are kept. The clock is checked every
+/// `CLOCK_CHECK_INTERVAL` swaps, so the budget may be overrun slightly.
+///
+/// The deadline only applies once something feasible has turned up: if the
+/// budget runs out first (e.g. a zero budget and an infeasible input order),
+/// the search carries on with `best_tracklist_bb`. Returns `None` only when no
+/// ordering is feasible at all.
+pub fn best_tracklist_timed(
+    tracks: &[Track],
+    constraints: &[Constraint],
+    medium: &Medium,
+    budget: std::time::Duration,
+) -> Option<(Tracklist, f64)> {
+    let start = std::time::Instant::now();
+    let mut rng = Rng::new(0);
+    let mut current = SwapScorer::new(Tracklist::new(tracks.to_vec()), constraints, medium);
+    let mut best = None;
+    record_if_better(&mut best, current.tracklist(), constraints, medium);
+    let len = tracks.len();
+    let pairs: Vec<(usize, usize)> = (0..len).tuple_combinations().collect();
+    let mut examined = 0;
+
+    while start.elapsed() < budget && !pairs.is_empty() {
+        // Climb until no swap improves
+        let mut improved = true;
+        while improved {
+            improved = false;
+            let offset = rng.below(pairs.len());
+            for k in 0..pairs.len() {
+                let (i, j) = pairs[(offset + k) % pairs.len()];
+                let delta = current.delta(i, j);
+                examined += 1;
+                if delta > 0.0 {
+                    current.swap(i, j);
+                    improved = true;
+                    break;
+                }
+                if examined % CLOCK_CHECK_INTERVAL == 0 && start.elapsed() >= budget {
+                    break;
+                }
+            }
+            if start.elapsed() >= budget {
+                break;
+            }
+        }
+        record_if_better(&mut best, current.tracklist(), constraints, medium);
+        let mut shuffled = current.into_tracklist();
+        rng.shuffle(&mut shuffled.0);
+        current = SwapScorer::new(shuffled, constraints, medium);
+    }
+
+    if best.is_none() {
+        // Out of time with nothing feasible: the exact search either finds an
+        // ordering or proves there is none
+        return best_tracklist_bb(tracks, constraints, medium);
+    }
+    best
+}
+
+/// Polish a (typically hand-edited) ordering by local search.
+///
+/// Each round applies the single best improving move among all swaps of two
+/// tracks and all moves of one track to another position, and stops once no
+/// move improves the score, i.e. at a local optimum. Ties between moves keep
+/// the first found (swaps before moves, lower indices first). The result
+/// never scores lower than `tracklist`.
+pub fn refine(tracklist: &Tracklist, constraints: &[Constraint], medium: &Medium) -> Tracklist {
+    let mut current = tracklist.clone();
+    let mut score = score_tracklist(&current, constraints, medium);
+    let len = current.0.len();
+
+    loop {
+        let mut best_move: Option<(f64, bool, usize, usize)> = None;
+        let mut consider = |gain: f64, insert: bool, i: usize, j: usize| {
+            if gain > 0.0 && best_move.is_none_or(|(g, ..)| gain > g) {
+                best_move = Some((gain, insert, i, j));
+            }
+        };
+        for (i, j) in (0..len).tuple_combinations() {
+            consider(
+                score_delta(&current, i, j, constraints, medium),
+                false,
+                i,
+                j,
+            );
+        }
+        for from in 0..len {
+            for to in (0..len).filter(|&to| to != from && to + 1 != from) {
+                let mut moved = current.clone();
+                let track = moved.0.remove(from);
+                moved.0.insert(to, track);
+                let gain = score_tracklist(&moved, constraints, medium) - score;
+                consider(gain, true, from, to);
+            }
+        }
+
+        let Some((_, insert, i, j)) = best_move else {
+            return current;
+        };
+        if insert {
+            let track = current.0.remove(i);
+            current.0.insert(j, track);
+        } else {
+            current.0.swap(i, j);
+        }
+        // Rescored rather than summing gains, which could drift from it
+        score = score_tracklist(&current, constraints, medium);
+    }
+}
+
+/// Result of `sample`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SampleSummary {
+    /// Highest score among the feasible samples (0 when there were none).
+    pub best_score: f64,
+    /// The distinct feasible sampled orderings reaching `best_score`, in
+    /// sample order.
+    pub best: Vec<Tracklist>,
+    /// Each distinct score with how many samples got it, lowest first,
+    /// infeasible ones included (at 0).
+    pub histogram: Vec<(f64, usize)>,
+}
+
+/// Score `n_samples` uniformly random orderings, to gauge how hard a
+/// constraint set is before committing to an exact search.
+///
+/// Orderings are drawn independently (so repeats are possible) from a
+/// generator seeded with `seed`, making the summary reproducible.
+pub fn sample(
+    tracks: &[Track],
+    constraints: &[Constraint],
+    medium: &Medium,
+    n_samples: usize,
+    seed: u64,
+) -> SampleSummary {
+    let mut rng = Rng::new(seed);
+    let mut summary = SampleSummary {
+        best_score: 0.0,
+        best: Vec::new(),
+        histogram: Vec::new(),
+    };
+    let mut order: Vec<usize> = (0..tracks.len()).collect();
+    for _ in 0..n_samples {
+        rng.shuffle(&mut order);
+        let tracklist = tracklist_from_order(tracks, &order);
+        let score = score_tracklist(&tracklist, constraints, medium);
+        match summary
+            .histogram
+            .binary_search_by(|(s, _)| s.total_cmp(&score))
+        {
+            Ok(i) => summary.histogram[i].1 += 1,
+            Err(i) => summary.histogram.insert(i, (score, 1)),
+        }
+        // Infeasible orders score 0, so only a score of 0 needs the full check.
+        if score == 0.0 && !is_feasible(&tracklist, constraints, medium) {
+            continue;
+        }
+        if score > summary.best_score || summary.best.is_empty() {
+            summary.best_score = score;
+            summary.best = vec![tracklist];
+        } else if score == summary.best_score && !summary.best.contains(&tracklist) {
+            summary.best.push(tracklist);
+        }
+    }
+    summary
+}
+
+/// Counters from a branch-and-bound search.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SearchStats {
+    /// Prefixes visited, including complete orderings.
+    pub nodes_explored: usize,
+    /// Prefixes whose subtree was skipped because the bound couldn't beat the best.
+    pub nodes_pruned: usize,
+    /// Value of `nodes_explored` when the final best ordering was found.
+    pub best_found_at_node: usize,
+}
+
+/// Upper bound on what `constraint` can earn in any completion of `prefix` by `pool`.
+///
+/// Kinds whose outcome the prefix can already decide or rule out are checked;
+/// everything else optimistically keeps its full weight, so the bound is admissible.
+fn constraint_upper_bound(
+    constraint: &Constraint,
+    prefix: &Tracklist,
+    pool: &[&Track],
+    medium: &Medium,
+) -> f64 {
+    let len = prefix.0.len() + pool.len();
+    let in_prefix = |title: &str| prefix.0.iter().position(|t| t.title == title);
+    let pool_count = |title: &str| pool.iter().filter(|t| t.title == title).count();
+    let audible_len = prefix.audible().count() + pool.iter().filter(|t| !t.hidden).count();
+    let audible_in_prefix = |title: &str| prefix.audible().position(|t| t.title == title);
+    let audible_in_pool = |title: &str| pool.iter().any(|t| !t.hidden && t.title == title);
+
+    // The bounds below are for meeting a kind, not avoiding it.
+    if constraint.penalty {
+        return constraint.weight;
+    }
+    let possible = match &constraint.kind {
+        // Hidden tracks don't count towards positions or adjacency.
+        ConstraintKind::AtPosition(title, pos) => match prefix.audible().nth(*pos) {
+            Some(track) => track.title == *title,
+            None => *pos < len && pool_count(title) > 0,
+        },
+        ConstraintKind::WithinFirst(title, n) => match audible_in_prefix(title) {
+            Some(pos) => pos < *n,
+            None => prefix.audible().count() < *n && audible_in_pool(title),
+        },
+        ConstraintKind::WithinLast(title, n) => match audible_in_prefix(title) {
+            Some(pos) => pos >= audible_len.saturating_sub(*n),
+            None => *n > 0 && audible_in_pool(title),
+        },
+        ConstraintKind::Adjacent(t1, t2) | ConstraintKind::BpmWithin(t1, t2, _) => {
+            let pool_pair = if t1 == t2 {
+                pool_count(t1) >= 2
+            } else {
+                pool_count(t1) > 0 && pool_count(t2) > 0
+            };
+            constraint.is_satisfied(prefix, medium)
+                || pool_pair
+                || ((prefix.0.last().is_some_and(|t| t.title == *t1)
+                    || prefix.audible().last().is_some_and(|t| t.title == *t1))
+                    && pool_count(t2) > 0)
+        }
+        ConstraintKind::AdjacentEither(t1, t2) => {
+            let pool_pair = if t1 == t2 {
+                pool_count(t1) >= 2
+            } else {
+                pool_count(t1) > 0 && pool_count(t2) > 0
+            };
+            let last_is = |title: &str| {
+                prefix.0.last().is_some_and(|t| t.title == title)
+                    || prefix.audible().last().is_some_and(|t| t.title == title)
+            };
+            constraint.is_satisfied(prefix, medium)
+                || pool_pair
+                || (last_is(t1) && pool_count(t2) > 0)
+                || (last_is(t2) && pool_count(t1) > 0)
+        }
+        ConstraintKind::AtPositionIdx(id, pos) => match prefix.0.get(*pos) {
+            Some(track) => track.id == Some(*id),
+            None => *pos < len && pool.iter().any(|t| t.id == Some(*id)),
+        },
+        // The prefix packs the same way whatever follows it.
+        ConstraintKind::OnSide(title, side) => {
+            let sides = medium.side_indices(prefix);
+            match in_prefix(title) {
+                Some(pos) => sides[pos] == *side,
+                None => {
+                    pool_count(title) > 0
+                        && *side < medium.sides
+                        && sides.last().is_none_or(|&last| last <= *side)
+                }
+            }
+        }
+        ConstraintKind::Group(titles, ordered) => {
+            let placed: Vec<usize> = titles.iter().filter_map(|t| in_prefix(t)).collect();
+            if placed.len() == titles.len() {
+                constraint.is_satisfied(prefix, medium)
+            } else {
+                // Members placed so far must be a run at the end of the
+                // prefix (the first ones, if ordered), the rest still to come.
+                let mut sorted = placed.clone();
+                sorted.sort_unstable();
+                let run = sorted.windows(2).all(|w| w[1] == w[0] + 1)
+                    && sorted.last().is_none_or(|&last| last + 1 == prefix.0.len());
+                let order_kept = !ordered
+                    || (titles[..placed.len()]
+                        .iter()
+                        .all(|t| in_prefix(t).is_some())
+                        && placed.windows(2).all(|w| w[0] < w[1]));
+                run && order_kept
+                    && titles
+                        .iter()
+                        .filter(|t| in_prefix(t).is_none())
+                        .all(|t| pool_count(t) > 0)
+            }
+        }
+        ConstraintKind::InPositionRange(title, min, max) => match audible_in_prefix(title) {
+            Some(pos) => (*min..=*max).contains(&pos),
+            None => {
+                audible_in_pool(title)
+                    && *min <= *max
+                    && *max >= prefix.audible().count()
+                    && *min < audible_len
+            }
+        },
+        // Only hidden tracks may follow a closer that is already placed.
+        ConstraintKind::ClosesAlbum(title) => {
+            pool_count(title) > 0 || pool.iter().all(|t| t.hidden)
+        }
+        // An unplaced track can go as late as the last position.
+        ConstraintKind::Separated(t1, t2, min_gap) => match (in_prefix(t1), in_prefix(t2)) {
+            (Some(i1), Some(i2)) => i1.abs_diff(i2) > *min_gap,
+            (Some(i), None) => pool_count(t2) > 0 && len - 1 - i > *min_gap,
+            (None, Some(i)) => pool_count(t1) > 0 && len - 1 - i > *min_gap,
+            (None, None) => {
+                pool_count(t1) > 0 && pool_count(t2) > 0 && len - 1 - prefix.0.len() > *min_gap
+            }
+        },
+        ConstraintKind::Before(t1, t2) => match (in_prefix(t1), in_prefix(t2)) {
+            (Some(i1), Some(i2)) => i1 < i2,
+            (None, Some(_)) => false,
+            (Some(_), None) => pool_count(t2) > 0,
+            (None, None) => pool_count(t1) > 0 && pool_count(t2) > 0,
+        },
+        ConstraintKind::PositionParity(title, even) => match audible_in_prefix(title) {
+            Some(pos) => (pos % 2 == 0) == *even,
+            None => {
+                audible_in_pool(title)
+                    && (prefix.audible().count()..audible_len).any(|pos| (pos % 2 == 0) == *even)
+            }
+        },
+        ConstraintKind::TagSequence(pattern) => prefix
+            .0
+            .iter()
+            .zip(pattern)
+            .all(|(track, tag)| tag == "*" || track.has_tag(tag)),
+        // Runs and per-side counts only grow as tracks are appended.
+        ConstraintKind::MaxTagRun(..) | ConstraintKind::MaxShortPerSide(..) => {
+            constraint.is_satisfied(prefix, medium)
+        }
+        ConstraintKind::OpenerNotGenre(_) if !prefix.0.is_empty() => {
+            constraint.is_satisfied(prefix, medium)
+        }
+        _ => true,
+    };
+    if possible { constraint.weight } else { 0.0 }
+}
+
+/// Admissible upper bound on the score of any completion of `prefix` by `pool`.
+fn prefix_upper_bound(
+    prefix: &Tracklist,
+    pool: &[&Track],
+    constraints: &[Constraint],
+    medium: &Medium,
+) -> f64 {
+    if pool.is_empty() {
+        return score_tracklist(prefix, constraints, medium);
+    }
+    constraints
+        .iter()
+        .map(|c| constraint_upper_bound(c, prefix, pool, medium))
+        .sum()
+}
+
+/// Trivial ceiling on any ordering's score: the sum of all weights.
+pub fn max_achievable_score(constraints: &[Constraint]) -> f64 {
+    constraints.iter().map(|c| c.weight).sum()
+}
+
+/// Ceiling on the score of any ordering that starts with `prefix` and
+/// continues with `pool` (the tracks not yet placed) in some order.
+///
+/// Tighter than `max_achievable_score`: it drops constraints the prefix has
+/// already ruled out (e.g. an `AtPosition` slot filled by another track) and
+/// returns 0 when the prefix alone can't fit the medium. It never
+/// underestimates, which is what lets `best_tracklist_bb` prune with it.
+pub fn max_achievable_given_prefix(
+    prefix: &[Track],
+    pool: &[Track],
+    constraints: &[Constraint],
+    medium: &Medium,
+) -> f64 {
+    let prefix = Tracklist::new(prefix.to_vec());
+    if !medium.fits_prefix(&prefix) {
+        return 0.0;
+    }
+    let pool: Vec<&Track> = pool.iter().collect();
+    prefix_upper_bound(&prefix, &pool, constraints, medium)
+}
+
+struct BranchAndBound<'a> {
+    tracks: &'a [Track],
+    constraints: &'a [Constraint],
+    medium: &'a Medium,
+    stats: SearchStats,
+    best: Option<(Tracklist, f64)>,
+}
+
+impl BranchAndBound<'_> {
+    fn search(&mut self, prefix: &mut Tracklist, used: &mut [bool]) {
+        self.stats.nodes_explored += 1;
+
+        // Greedy packing only depends on the prefix, so a spilling prefix never fits.
+        if !self.medium.fits_prefix(prefix) {
+            self.stats.nodes_pruned += 1;
+            return;
+        }
+        let pool: Vec<&Track> = self
+            .tracks
+            .iter()
+            .zip(used.iter())
+            .filter(|(_, u)| !**u)
+            .map(|(t, _)| t)
+            .collect();
+        if pool.is_empty() {
+            // The prefix check leaves the last side open, and hard constraints
+            // are only bounded, so a complete ordering must be feasible outright.
+            let Some(score) = feasible_score(prefix, self.constraints, self.medium) else {
+                self.stats.nodes_pruned += 1;
+                return;
+            };
+            if self.best.as_ref().is_none_or(|(_, b)| score > *b) {
+                self.best = Some((prefix.clone(), score));
+                self.stats.best_found_at_node = self.stats.nodes_explored;
+            }
+            return;
+        }
+        // Ties keep the earlier ordering, so only a strictly better bound is worth exploring.
+        if let Some((_, best)) = &self.best
+            && prefix_upper_bound(prefix, &pool, self.constraints, self.medium) <= *best
+        {
+            self.stats.nodes_pruned += 1;
+            return;
+        }
+
+        for i in 0..self.tracks.len() {
+            if used[i] {
+                continue;
+            }
+            used[i] = true;
+            prefix.push(self.tracks[i].clone());
+            self.search(prefix, used);
+            prefix.0.pop();
+            used[i] = false;
+        }
+    }
+}
+
+/// Exact search that builds orderings prefix by prefix and skips prefixes
+/// whose admissible upper bound can't beat the best ordering found so far.
+///
+/// Finds the same optimal score as `best_arrangement` (and the same ordering
+/// when that score is non-zero), or `None` if no ordering is feasible.
+/// Practical well beyond the brute-force ceiling when position and adjacency
+/// constraints dominate the weight.
+pub fn best_tracklist_bb(
+    tracks: &[Track],
+    constraints: &[Constraint],
+    medium: &Medium,
+) -> Option<(Tracklist, f64)> {
+    best_tracklist_bb_stats(tracks, constraints, medium).0
+}
+
+/// `best_tracklist_bb` that also reports search-tree counters, for tuning the bound.
+pub fn best_tracklist_bb_stats(
+    tracks: &[Track],
+    constraints: &[Constraint],
+    medium: &Medium,
+) -> (Option<(Tracklist, f64)>, SearchStats) {
+    let mut search = BranchAndBound {
+        tracks,
+        constraints,
+        medium,
+        stats: SearchStats::default(),
+        best: None,
+    };
+    search.search(
+        &mut Tracklist::new(Vec::with_capacity(tracks.len())),
+        &mut vec![false; tracks.len()],
+    );
+    (search.best, search.stats)
+}
+
+/// Length of the longest strictly increasing subsequence (patience sorting).
+fn longest_increasing_subsequence(values: &[usize]) -> usize {
+    let mut tails: Vec<usize> = Vec::new();
+    for &v in values {
+        match tails.binary_search(&v) {
+            Ok(_) => {}
+            Err(i) if i == tails.len() => tails.push(v),
+            Err(i) => tails[i] = v,
+        }
+    }
+    tails.len()
+}
+
+/// Fewest single-track moves (take a track out, reinsert it elsewhere) that
+/// turn `tracklist` into an ordering that fits `medium`.
+///
+/// Tracks that don't move keep their relative order, so the answer is the
+/// track count minus the longest such run kept by any fitting ordering.
+/// Returns `Some(0)` if it already fits and `None` if no ordering fits.
+/// Brute force, same track-count ceiling as `best_coherent`.
+pub fn min_moves_to_fit(tracklist: &Tracklist, medium: &Medium) -> Option<usize> {
+    if medium.fits(tracklist) {
+        return Some(0);
+    }
+    let len = tracklist.0.len();
+    (0..len)
+        .permutations(len)
+        .filter(|order| medium.fits(&tracklist_from_order(&tracklist.0, order)))
+        .map(|order| len - longest_increasing_subsequence(&order))
+        .min()
+}
+
+/// Find the fitting ordering with the highest adjacent-pair coherence.
+///
+/// Brute force over every permutation, so keep `tracks` to about 10 entries
+/// (10! is already ~3.6M orderings). Ties keep the first ordering found.
+/// Returns `None` if no ordering fits the medium.
+pub fn best_coherent(
+    tracks: &[Track],
+    medium: &Medium,
+    similarity: impl Fn(&Track, &Track) -> f64,
+) -> Option<(Tracklist, f64)> {
+    let mut best: Option<(Tracklist, f64)> = None;
+
+    for perm in TracklistPermutations::new(tracks) {
+        let tracklist = Tracklist::new(perm.into_iter().cloned().collect());
+        if !medium.fits(&tracklist) {
+            continue;
+        }
+        let coherence = tracklist.coherence(&similarity);
+        if best.as_ref().is_none_or(|(_, c)| coherence > *c) {
+            best = Some((tracklist, coherence));
+        }
+    }
+
+    best
+}
+
+/// Find the feasible ordering that optimizes constraint priorities strictly in order.
+///
+/// Weights are summed per priority level (lowest `priority` value first) and
+/// the levels are compared lexicographically, so no amount of lower-priority
+/// weight can make up for losing higher-priority weight. Returns the ordering
+/// with its per-level scores, or `None` if no ordering is feasible (see
+/// `is_feasible`). Brute force, same track-count ceiling as `best_coherent`.
+pub fn best_tracklist_lexicographic(
+    tracks: &[Track],
+    constraints: &[Constraint],
+    medium: &Medium,
+) -> Option<(Tracklist, Vec<f64>)> {
+    let mut levels: Vec<usize> = constraints.iter().map(|c| c.priority).collect();
+    levels.sort_unstable();
+    levels.dedup();
+
+    let mut best: Option<(Tracklist, Vec<f64>)> = None;
+
+    for perm in TracklistPermutations::new(tracks) {
+        let tracklist = Tracklist::new(perm.into_iter().cloned().collect());
+        if !is_feasible(&tracklist, constraints, medium) {
+            continue;
+        }
+        let mut scores = vec![0.0; levels.len()];
+        for constraint in constraints {
+            let level = levels.binary_search(&constraint.priority).unwrap();
+            scores[level] += constraint.score(&tracklist, medium);
+        }
+        if best.as_ref().is_none_or(|(_, s)| scores > *s) {
+            best = Some((tracklist, scores));
+        }
+    }
+
+    best
+}
+
+/// Score a precomputed satisfaction vector under the given weights, as
+/// `Constraint::score` does.
+fn weighted_score(satisfaction: &[f64], weights: &[f64]) -> f64 {
+    satisfaction.iter().zip(weights).map(|(&s, &w)| w * s).sum()
+}
+
+/// Per-constraint weight adjustments that make `target` tie for the best score.
+///
+/// Greedy heuristic: repeatedly take the current best ordering, then raise
+/// the weight of the constraint where `target` most out-satisfies it by just
+/// enough to close the gap. If no such constraint exists, lower the weight of
+/// the constraint the competitor most out-satisfies `target` on instead
+/// (never below zero). The result is a valid adjustment but not necessarily
+/// the smallest one. Only feasible orderings compete (see `is_feasible`).
+/// Returns `None` if `target` isn't feasible or the heuristic gets stuck.
+/// Brute force, same track-count ceiling as `best_coherent`.
+pub fn min_weight_delta_for_optimal(
+    tracks: &[Track],
+    constraints: &[Constraint],
+    medium: &Medium,
+    target: &Tracklist,
+) -> Option<Vec<f64>> {
+    if !is_feasible(target, constraints, medium) {
+        return None;
+    }
+    let satisfaction_of = |tracklist: &Tracklist| -> Vec<f64> {
+        constraints
+            .iter()
+            .map(|c| c.satisfaction(tracklist, medium, &MatchOptions::default()))
+            .collect()
+    };
+
+    let target_sat = satisfaction_of(target);
+    let candidates: Vec<Vec<f64>> = TracklistPermutations::new(tracks)
+        .map(|perm| Tracklist::new(perm.into_iter().cloned().collect()))
+        .filter(|tracklist| is_feasible(tracklist, constraints, medium))
+        .map(|tracklist| satisfaction_of(&tracklist))
+        .collect();
+
+    let base: Vec<f64> = constraints.iter().map(|c| c.weight).collect();
+    let mut deltas = vec![0.0; constraints.len()];
+
+    for _ in 0..=candidates.len() {
+        let weights: Vec<f64> = base.iter().zip(&deltas).map(|(b, d)| b + d).collect();
+        let target_score = weighted_score(&target_sat, &weights);
+        let Some(competitor) = candidates
+            .iter()
+            .max_by(|a, b| weighted_score(a, &weights).total_cmp(&weighted_score(b, &weights)))
+        else {
+            return Some(deltas);
+        };
+        let gap = weighted_score(competitor, &weights) - target_score;
+        if gap <= 0.0 {
+            return Some(deltas);
+        }
+
+        let advantage = |i: usize| target_sat[i] - competitor[i];
+        let boost = (0..constraints.len())
+            .filter(|&i| advantage(i) > 0.0)
+            .max_by(|&a, &b| advantage(a).total_cmp(&advantage(b)).then(b.cmp(&a)));
+        if let Some(i) = boost {
+            deltas[i] += (gap / advantage(i)).ceil();
+            continue;
+        }
+
+        let cut = (0..constraints.len())
+            .filter(|&i| advantage(i) < 0.0 && weights[i] > 0.0)
+            .max_by(|&a, &b| advantage(b).total_cmp(&advantage(a)).then(b.cmp(&a)))?;
+        let needed = (gap / -advantage(cut)).ceil();
+        deltas[cut] -= needed.min(weights[cut]);
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bpm_similarity(a: &Track, b: &Track) -> f64 {
+        match (a.bpm, b.bpm) {
+            (Some(x), Some(y)) => 1.0 / (1.0 + (x - y).abs()),
+            _ => 0.0,
+        }
+    }
+
+    #[test]
+    fn test_best_coherent() {
+        let tracks = vec![
+            Track::new("Mid", 4.0).with_bpm(100.0),
+            Track::new("Fast", 4.0).with_bpm(120.0),
+            Track::new("Slow", 4.0).with_bpm(80.0),
+        ];
+        let medium = Medium::new("test_medium", 1, 12.0);
+
+        let (best, coherence) = best_coherent(&tracks, &medium, bpm_similarity).unwrap();
+        // Either monotone order is equally coherent; the first found wins.
+        assert_eq!(best.titles(), vec!["Fast", "Mid", "Slow"]);
+        assert!((coherence - 2.0 / 21.0).abs() < 1e-9);
+
+        let too_small = Medium::new("too_small", 1, 10.0);
+        assert!(best_coherent(&tracks, &too_small, bpm_similarity).is_none());
+    }
+
+    #[test]
+    fn test_best_tracklist_lexicographic() {
+        let tracks = vec![
+            Track::new("A", 4.0),
+            Track::new("B", 4.0),
+            Track::new("C", 4.0),
+        ];
+        let medium = Medium::new("test_medium", 1, 12.0);
+        let constraints = vec![
+            Constraint::new(ConstraintKind::AtPosition("A".into(), 0), 1).with_priority(1),
+            Constraint::new(ConstraintKind::AtPosition("B".into(), 0), 100).with_priority(2),
+            Constraint::new(ConstraintKind::Adjacent("B".into(), "C".into()), 1).with_priority(2),
+        ];
+
+        let (best, scores) = best_tracklist_lexicographic(&tracks, &constraints, &medium).unwrap();
+        // The heavy B-opener is sacrificed to keep A first
+        assert_eq!(best.titles(), vec!["A", "B", "C"]);
+        assert_eq!(scores, vec![1.0, 1.0]);
+        assert!(score_tracklist(&best, &constraints, &medium) < 100.0);
+    }
+
+    #[test]
+    fn test_min_weight_delta_for_optimal() {
+        let tracks = vec![
+            Track::new("A", 4.0),
+            Track::new("B", 4.0),
+            Track::new("C", 4.0),
+        ];
+        let medium = Medium::new("test_medium", 1, 12.0);
+        let constraints = vec![
+            Constraint::new(ConstraintKind::AtPosition("A".into(), 0), 5),
+            Constraint::new(ConstraintKind::AtPosition("B".into(), 0), 3),
+        ];
+
+        // Boosting the B-opener by 2 ties it with the A-opener
+        let target = Tracklist::from(vec![("B", 4.0), ("A", 4.0), ("C", 4.0)]);
+        let deltas = min_weight_delta_for_optimal(&tracks, &constraints, &medium, &target).unwrap();
+        assert_eq!(deltas, vec![0.0, 2.0]);
+
+        // Already optimal needs no change
+        let optimal = Tracklist::from(vec![("A", 4.0), ("B", 4.0), ("C", 4.0)]);
+        let deltas =
+            min_weight_delta_for_optimal(&tracks, &constraints, &medium, &optimal).unwrap();
+        assert_eq!(deltas, vec![0.0, 0.0]);
+
+        // A target that doesn't fit can never be optimal
+        let too_long = Tracklist::from(vec![("B", 4.0), ("A", 4.0), ("C", 5.0)]);
+        assert!(min_weight_delta_for_optimal(&tracks, &constraints, &medium, &too_long).is_none());
+    }
+
+    #[test]
+    fn test_top_arrangements() {
+        // Plenty of ties below the optimum of 6
+        let medium = Medium::new("test_medium", 1, 20.0);
+        let tracks: Vec<Track> = ["A", "B", "C", "D"]
+            .iter()
+            .map(|t| Track::new(*t, 4.0))
+            .collect();
+        let constraints = vec![
+            Constraint::new(ConstraintKind::AtPosition("A".into(), 0), 3),
+            Constraint::new(ConstraintKind::Adjacent("B".into(), "C".into()), 2),
+            Constraint::new(ConstraintKind::WithinLast("D".into(), 1), 1),
+        ];
+
+        let top = top_arrangements(&tracks, &constraints, &medium, 3);
+        assert_eq!(top.len(), 3);
+
+        let mut all: Vec<f64> = TracklistPermutations::new(&tracks)
+            .map(|perm| {
+                let tracklist = Tracklist::new(perm.into_iter().cloned().collect());
+                score_tracklist(&tracklist, &constraints, &medium)
+            })
+            .collect();
+        all.sort_unstable_by(|a, b| b.total_cmp(a));
+        let scores: Vec<f64> = top.iter().map(|(_, score)| *score).collect();
+        assert_eq!(scores, all[..3]);
+        for (tracklist, score) in &top {
+            assert_eq!(score_tracklist(tracklist, &constraints, &medium), *score);
+        }
+
+        // The single best agrees with the exhaustive search, tie-break included
+        let best = best_arrangement(&tracks, &constraints, &medium).unwrap();
+        assert_eq!(
+            top_arrangements(&tracks, &constraints, &medium, 1),
+            vec![best]
+        );
+        assert!(top_arrangements(&tracks, &constraints, &medium, 0).is_empty());
+    }
+
+    #[test]
+    fn test_best_subset_tracklist() {
+        let medium = Medium::new("test_medium", 1, 12.0);
+        let pool = vec![
+            Track::new("Opener", 4.0),
+            Track::new("Filler1", 4.0),
+            Track::new("Single", 4.0),
+            Track::new("Filler2", 4.0),
+            Track::new("Closer", 4.0),
+        ];
+        let constraints = vec![
+            Constraint::new(ConstraintKind::AtPosition("Opener".into(), 0), 3),
+            Constraint::new(
+                ConstraintKind::Adjacent("Opener".into(), "Single".into()),
+                2,
+            ),
+            Constraint::new(ConstraintKind::WithinLast("Closer".into(), 1), 3),
+        ];
+
+        let (best, score) = best_subset_tracklist(&pool, 3, &constraints, &medium).unwrap();
+        assert_eq!(best.titles(), vec!["Opener", "Single", "Closer"]);
+        assert_eq!(score, 8.0);
+
+        // Four tracks don't fit on a 12-minute side
+        assert!(best_subset_tracklist(&pool, 4, &constraints, &medium).is_none());
+        assert!(best_subset_tracklist(&pool, 6, &constraints, &medium).is_none());
+    }
+
+    #[test]
+    fn test_annealing_restarts() {
+        // Reversing the order earns 6 a point at a time, but the optimum of 10
+        // needs A, B, C up front, far from that local optimum.
+        let titles = ["A", "B", "C", "D", "E", "F"];
+        let tracks: Vec<Track> = titles
+            .iter()
+            .map(|t| Track::new(*t, 3.0).with_tag(t.to_lowercase()))
+            .collect();
+        let medium = Medium::new("test_medium", 2, 9.0);
+        let mut constraints: Vec<Constraint> = titles
+            .iter()
+            .rev()
+            .enumerate()
+            .map(|(i, t)| Constraint::new(ConstraintKind::AtPosition(t.to_string(), i), 1))
+            .collect();
+        constraints.push(Constraint::new(
+            ConstraintKind::TagSequence(vec!["a".into(), "b".into(), "c".into()]),
+            10,
+        ));
+        let (_, optimum) = best_arrangement(&tracks, &constraints, &medium).unwrap();
+        assert_eq!(optimum, 10.0);
+
+        // The first restart is this very run, which stalls short of the optimum
+        let first_seed = Rng::new(0).next_u64();
+        let (single, single_score) =
+            optimize_annealing(&tracks, &constraints, &medium, 100, first_seed).unwrap();
+        assert!(single_score < optimum);
+        assert_eq!(
+            score_tracklist(&single, &constraints, &medium),
+            single_score
+        );
+
+        let (best, score) =
+            optimize_annealing_restarts(&tracks, &constraints, &medium, 20, 100, 0).unwrap();
+        // Seeded, so the restarts that escape it always do
+        assert_eq!(score, optimum);
+        assert_eq!(score_tracklist(&best, &constraints, &medium), score);
+        assert_eq!(
+            optimize_annealing_restarts(&tracks, &constraints, &medium, 20, 100, 0)
+                .unwrap()
+                .0,
+            best
+        );
+    }
+
+    #[test]
+    fn test_best_tracklist_timed() {
+        let medium = Medium::new("test_medium", 2, 9.0);
+        let tracks: Vec<Track> = ["A", "B", "C", "D", "E", "F"]
+            .iter()
+            .map(|t| Track::new(*t, 3.0))
+            .collect();
+        let constraints = vec![
+            Constraint::new(ConstraintKind::AtPosition("F".into(), 0), 4),
+            Constraint::new(ConstraintKind::Adjacent("C".into(), "A".into()), 3),
+            Constraint::new(ConstraintKind::Adjacent("A".into(), "E".into()), 2),
+            Constraint::new(ConstraintKind::WithinLast("D".into(), 1), 2),
+        ];
+        let (_, optimum) = best_arrangement(&tracks, &constraints, &medium).unwrap();
+
+        // No time at all still returns the input order when it fits
+        let (input, input_score) =
+            best_tracklist_timed(&tracks, &constraints, &medium, std::time::Duration::ZERO)
+                .unwrap();
+        assert_eq!(input, Tracklist::new(tracks.clone()));
+        assert!(input_score < optimum);
+
+        // Seven hundred and twenty orderings are easily covered in a second
+        let (best, score) = best_tracklist_timed(
+            &tracks,
+            &constraints,
+            &medium,
+            std::time::Duration::from_secs(1),
+        )
+        .unwrap();
+        assert_eq!(score, optimum);
+        assert_eq!(score_tracklist(&best, &constraints, &medium), score);
+
+        // The input order spills, but a zero budget still finds one that fits
+        let medium = Medium::new("test_medium", 2, 10.0);
+        let tracks = vec![
+            Track::new("A", 6.0),
+            Track::new("B", 6.0),
+            Track::new("C", 4.0),
+            Track::new("D", 4.0),
+        ];
+        assert!(!medium.fits(&Tracklist::new(tracks.clone())));
+        let (found, _) =
+            best_tracklist_timed(&tracks, &[], &medium, std::time::Duration::ZERO).unwrap();
+        assert!(medium.fits(&found));
+
+        // Only when nothing fits is there nothing to return
+        let too_long = vec![Track::new("Epic", 12.0)];
+        assert_eq!(
+            best_tracklist_timed(&too_long, &[], &medium, std::time::Duration::ZERO),
+            None
+        );
+    }
+
+    #[test]
+    fn test_find_best() {
+        let medium = Medium::new("test_medium", 2, 10.0);
+        let tracks = vec![
+            Track::new("A", 6.0),
+            Track::new("B", 6.0),
+            Track::new("C", 4.0),
+            Track::new("D", 4.0),
+        ];
+        let constraints = vec![Constraint::new(
+            ConstraintKind::OnSameSide("A".into(), "B".into()),
+            5,
+        )];
+
+        // A and B can't share a side and fit, so the best is the first fitting ordering
+        let best = find_best(&tracks, &constraints, &medium).unwrap();
+        assert!(medium.fits(&best));
+        assert_eq!(best.titles(), vec!["A", "C", "B", "D"]);
+        assert_eq!(
+            best_arrangement(&tracks, &constraints, &medium),
+            Some((best, 0.0))
+        );
+
+        let too_small = Medium::new("too_small", 1, 10.0);
+        assert!(find_best(&tracks, &constraints, &too_small).is_none());
+    }
+
+    #[test]
+    fn test_top_arrangements_skips_unfit() {
+        let medium = Medium::new("test_medium", 2, 10.0);
+        let tracks = vec![
+            Track::new("A", 6.0),
+            Track::new("B", 6.0),
+            Track::new("C", 4.0),
+            Track::new("D", 4.0),
+        ];
+        let constraints = vec![
+            Constraint::new(ConstraintKind::AtPosition("C".into(), 0), 2),
+            Constraint::new(ConstraintKind::Adjacent("A".into(), "B".into()), 1),
+        ];
+
+        let top = top_arrangements(&tracks, &constraints, &medium, 3);
+        let scores: Vec<f64> = top.iter().map(|(_, score)| *score).collect();
+        assert_eq!(scores, vec![3.0, 2.0, 2.0]);
+        assert!(top.iter().all(|(tracklist, _)| medium.fits(tracklist)));
+        assert_eq!(top[0].0.titles(), vec!["C", "A", "B", "D"]);
+        assert_eq!(
+            top_arrangements(&tracks, &constraints, &medium, 1)[0].0,
+            find_best(&tracks, &constraints, &medium).unwrap()
+        );
+
+        // Only 16 of the 24 orderings fit (one 6 and one 4 per side)
+        assert_eq!(
+            top_arrangements(&tracks, &constraints, &medium, 30).len(),
+            16
+        );
+    }
+
+    #[test]
+    fn test_anneal() {
+        // Only E, C, A, B, D earns all 9
+        let medium = Medium::new("test_medium", 2, 9.0);
+        let tracks: Vec<Track> = ["A", "B", "C", "D", "E"]
+            .iter()
+            .map(|t| Track::new(*t, 3.0))
+            .collect();
+        let constraints = vec![
+            Constraint::new(ConstraintKind::AtPosition("E".into(), 0), 4),
+            Constraint::new(ConstraintKind::Adjacent("C".into(), "A".into()), 3),
+            Constraint::new(ConstraintKind::Adjacent("A".into(), "B".into()), 1),
+            Constraint::new(ConstraintKind::WithinLast("D".into(), 1), 1),
+        ];
+        let params = AnnealParams {
+            iterations: 2_000,
+            ..AnnealParams::default()
+        };
+
+        let best = anneal(&tracks, &constraints, &medium, params, 3).unwrap();
+        assert_eq!(best.titles(), vec!["E", "C", "A", "B", "D"]);
+        assert_eq!(
+            anneal(&tracks, &constraints, &medium, params, 3),
+            Some(best)
+        );
+
+        // Swap-only linear cooling also works, and an empty budget still
+        // returns the feasible starting shuffle
+        let linear = AnnealParams {
+            schedule: CoolingSchedule::Linear,
+            insert_rate: 0.0,
+            ..params
+        };
+        let best = anneal(&tracks, &constraints, &medium, linear, 3).unwrap();
+        assert_eq!(score_tracklist(&best, &constraints, &medium), 9.0);
+        let none = AnnealParams {
+            iterations: 0,
+            ..params
+        };
+        assert_eq!(
+            anneal(&tracks, &constraints, &medium, none, 3)
+                .unwrap()
+                .0
+                .len(),
+            5
+        );
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_best_arrangement_par() {
+        // Many orderings tie, across different leading tracks
+        let medium = Medium::new("test_medium", 2, 6.0);
+        let tracks: Vec<Track> = ["A", "B", "C", "D", "E"]
+            .iter()
+            .map(|t| Track::new(*t, 2.0))
+            .collect();
+        let constraints = vec![
+            Constraint::new(ConstraintKind::AdjacentEither("A".into(), "B".into()), 2),
+            Constraint::new(ConstraintKind::WithinLast("C".into(), 2), 1),
+        ];
+        assert_eq!(
+            best_arrangement_par(&tracks, &constraints, &medium),
+            best_arrangement(&tracks, &constraints, &medium)
+        );
+
+        // Nothing scores: the input order wins in both
+        let plain: Vec<Track> = (0..6).map(|i| Track::new(format!("T{i}"), 1.0)).collect();
+        assert_eq!(
+            best_arrangement_par(&plain, &[], &medium),
+            best_arrangement(&plain, &[], &medium)
+        );
+        assert_eq!(best_arrangement_par(&[], &[], &medium).unwrap().1, 0.0);
+    }
+
+    #[test]
+    fn test_sample() {
+        let medium = Medium::new("test_medium", 2, 9.0);
+        let tracks: Vec<Track> = ["A", "B", "C", "D", "E"]
+            .iter()
+            .map(|t| Track::new(*t, 3.0))
+            .collect();
+        let constraints = vec![
+            Constraint::new(ConstraintKind::AtPosition("B".into(), 0), 2),
+            Constraint::new(ConstraintKind::OnSameSide("C".into(), "D".into()), 1),
+        ];
+        let summary = sample(&tracks, &constraints, &medium, 500, 11);
+
+        assert_eq!(summary.histogram.iter().map(|(_, n)| n).sum::<usize>(), 500);
+        assert_eq!(
+            summary.histogram.last().map(|(score, _)| *score),
+            Some(summary.best_score)
+        );
+        assert!(summary.best_score <= max_achievable_score(&constraints));
+        assert!(!summary.best.is_empty());
+        for tracklist in &summary.best {
+            assert_eq!(
+                score_tracklist(tracklist, &constraints, &medium),
+                summary.best_score
+            );
+        }
+        assert_eq!(sample(&tracks, &constraints, &medium, 500, 11), summary);
+
+        let empty = sample(&tracks, &constraints, &medium, 0, 11);
+        assert!(empty.best.is_empty() && empty.histogram.is_empty());
+    }
+
+    #[test]
+    fn test_refine() {
+        let medium = Medium::new("test_medium", 1, 30.0);
+        let tracks = Tracklist::from(vec![
+            ("Intro", 2.0),
+            ("A", 4.0),
+            ("B", 4.0),
+            ("C", 4.0),
+            ("Outro", 2.0),
+        ]);
+        let constraints = vec![
+            Constraint::new(ConstraintKind::AtPosition("Intro".into(), 0), 5),
+            Constraint::new(ConstraintKind::WithinLast("Outro".into(), 1), 5),
+            Constraint::new(ConstraintKind::Adjacent("C".into(), "A".into()), 2),
+            Constraint::new(ConstraintKind::Adjacent("A".into(), "B".into()), 2),
+        ];
+
+        // A hand edit that moved the outro up front
+        let edited = Tracklist::from(vec![
+            ("Outro", 2.0),
+            ("Intro", 2.0),
+            ("A", 4.0),
+            ("B", 4.0),
+            ("C", 4.0),
+        ]);
+        let refined = refine(&edited, &constraints, &medium);
+        assert_eq!(score_tracklist(&refined, &constraints, &medium), 14.0);
+        assert_eq!(refined.titles(), vec!["Intro", "C", "A", "B", "Outro"]);
+
+        // An optimal order is left alone
+        assert_eq!(refine(&refined, &constraints, &medium), refined);
+        let polished = refine(&tracks, &constraints, &medium);
+        let mut titles = polished.titles();
+        titles.sort_unstable();
+        assert_eq!(titles, vec!["A", "B", "C", "Intro", "Outro"]);
+        assert!(
+            score_tracklist(&polished, &constraints, &medium)
+                >= score_tracklist(&tracks, &constraints, &medium)
+        );
+    }
+
+    #[test]
+    fn test_resume_arrangement() {
+        let medium = Medium::new("test_medium", 1, 10.0);
+        let tracks: Vec<Track> = ["A", "B", "C", "D", "E"]
+            .iter()
+            .map(|t| Track::new(*t, 2.0))
+            .collect();
+        let constraints = vec![
+            Constraint::new(ConstraintKind::Before("E".into(), "A".into()), 2),
+            Constraint::new(ConstraintKind::AtPosition("C".into(), 4), 1),
+        ];
+        let mut checkpoint = SearchCheckpoint::default();
+        let mut runs = 0;
+        while !checkpoint.finished {
+            checkpoint = resume_arrangement(&tracks, &constraints, &medium, checkpoint, 30);
+            #[cfg(feature = "serde")]
+            {
+                // As if saved to disk and reloaded
+                let saved = serde_json::to_string(&checkpoint).unwrap();
+                checkpoint = serde_json::from_str(&saved).unwrap();
+            }
+            runs += 1;
+        }
+        // 5! = 120 orderings in chunks of 30
+        assert_eq!(runs, 4);
+        assert_eq!(checkpoint.next_index, 120);
+        assert_eq!(
+            checkpoint.best_tracklist(&tracks),
+            best_arrangement(&tracks, &constraints, &medium)
+        );
+        assert_eq!(
+            resume_arrangement(&tracks, &constraints, &medium, checkpoint, 100),
+            checkpoint
+        );
+    }
+
+    #[test]
+    fn test_search_control() {
+        let medium = Medium::new("test_medium", 1, 20.0);
+        let tracks: Vec<Track> = ["A", "B", "C", "D", "E"]
+            .iter()
+            .map(|t| Track::new(*t, 3.0))
+            .collect();
+        let constraints = vec![Constraint::new(
+            ConstraintKind::Adjacent("D".into(), "B".into()),
+            1,
+        )];
+        let mut reports = Vec::new();
+        let (best, complete) = best_arrangement_controlled(
+            &tracks,
+            &constraints,
+            &medium,
+            &mut SearchControl::new()
+                .with_progress(|examined, best| reports.push((examined, best))),
+        );
+        assert!(complete);
+        assert_eq!(best, best_arrangement(&tracks, &constraints, &medium));
+        // 120 orderings is below one reporting interval
+        assert!(reports.is_empty());
+
+        let cancel = AtomicBool::new(false);
+        let mut polls = 0;
+        let params = AnnealParams::default();
+        let (best, complete) = anneal_controlled(
+            &tracks,
+            &constraints,
+            &medium,
+            params,
+            7,
+            &mut SearchControl::new()
+                .with_progress(|examined, _| {
+                    polls += 1;
+                    assert_eq!(
+                        examined,
+                        polls * CLOCK_CHECK_INTERVAL - CLOCK_CHECK_INTERVAL
+                    );
+                    if polls == 3 {
+                        cancel.store(true, Ordering::Relaxed);
+                    }
+                })
+                .with_cancel(&cancel),
+        );
+        assert!(!complete);
+        assert_eq!(polls, 3);
+        let (best, score) = best.unwrap();
+        assert_eq!(score, score_tracklist(&best, &constraints, &medium));
+
+        let (_, complete) = anneal_controlled(
+            &tracks,
+            &constraints,
+            &medium,
+            params,
+            7,
+            &mut SearchControl::new(),
+        );
+        assert!(complete);
+    }
+
+    #[test]
+    fn test_find_best_prunes_hard_pins() {
+        let medium = Medium::new("test_medium", 1, 10.0);
+        let tracks: Vec<Track> = ["A", "B", "C", "D", "E"]
+            .iter()
+            .map(|t| Track::new(*t, 2.0))
+            .collect();
+        // The soft opener loses to the hard pin
+        let constraints = vec![
+            Constraint::new(ConstraintKind::AtPosition("A".into(), 0), 5),
+            Constraint::new(ConstraintKind::Adjacent("B".into(), "C".into()), 3),
+            Constraint::new(ConstraintKind::AtPosition("E".into(), 0), 1).with_hard(),
+        ];
+        let best = find_best(&tracks, &constraints, &medium).unwrap();
+        assert_eq!(best.0[0].title, "E");
+        assert_eq!(score_tracklist(&best, &constraints, &medium), 4.0);
+        let expected = top_arrangements(&tracks, &constraints, &medium, 1).remove(0);
+        assert_eq!(score_tracklist(&best, &constraints, &medium), expected.1);
+    }
+
+    #[test]
+    fn test_best_arrangement_timed() {
+        let medium = Medium::new("test_medium", 2, 10.0);
+        let constraints = vec![
+            Constraint::new(ConstraintKind::AtPosition("C".into(), 0), 3),
+            Constraint::new(ConstraintKind::Adjacent("A".into(), "D".into()), 2),
+            Constraint::new(ConstraintKind::OnSameSide("B".into(), "C".into()), 1),
+        ];
+        let small = vec![
+            Track::new("A", 4.0),
+            Track::new("B", 5.0),
+            Track::new("C", 3.0),
+            Track::new("D", 6.0),
+        ];
+
+        let best = best_arrangement(&small, &constraints, &medium);
+        assert_eq!(best.as_ref().map(|(_, score)| *score), Some(6.0));
+        let (timed, complete) = best_arrangement_timed(
+            &small,
+            &constraints,
+            &medium,
+            std::time::Duration::from_secs(60),
+        );
+        assert!(complete);
+        assert_eq!(timed, best);
+
+        // 9! permutations can't be scored in no time at all
+        let large: Vec<Track> = (0..9).map(|i| Track::new(format!("T{i}"), 1.0)).collect();
+        let (partial, complete) =
+            best_arrangement_timed(&large, &constraints, &medium, std::time::Duration::ZERO);
+        assert!(!complete);
+        assert_eq!(partial.unwrap().0.0.len(), 9);
+    }
+
+    #[test]
+    fn test_best_tracklist_with_fixed_block() {
+        let tracks: Vec<Track> = ["A", "B", "C", "D", "E"]
+            .iter()
+            .map(|t| Track::new(*t, 2.0))
+            .collect();
+        let medium = Medium::new("test_medium", 1, 10.0);
+
+        let candidates: Vec<Tracklist> = fixed_block_candidates(&tracks, 1..3).collect();
+        assert_eq!(candidates.len(), 6);
+        for candidate in &candidates {
+            assert_eq!(candidate.0[1].title, "B");
+            assert_eq!(candidate.0[2].title, "C");
+        }
+
+        // B is locked at index 1, so only the E-opener can score
+        let constraints = vec![
+            Constraint::new(ConstraintKind::AtPosition("B".into(), 0), 10),
+            Constraint::new(ConstraintKind::AtPosition("E".into(), 0), 3),
+        ];
+        let (best, score) = best_tracklist_with_fixed_block(&tracks, 1..3, &constraints, &medium)
+            .unwrap()
+            .unwrap();
+        assert_eq!(best.titles(), vec!["E", "B", "C", "A", "D"]);
+        assert_eq!(score, 3.0);
+
+        assert_eq!(
+            best_tracklist_with_fixed_block(&tracks, 3..6, &constraints, &medium),
+            Err(IndexError { index: 6, len: 5 })
+        );
+    }
+
+    #[test]
+    fn test_optimize_genetic_reaches_optimum() {
+        // Two sides that only fit with one long track on each
+        let medium = Medium::new("test_medium", 2, 10.0);
+        let tracks = vec![
+            Track::new("A", 6.0),
+            Track::new("B", 6.0),
+            Track::new("C", 2.0),
+            Track::new("D", 2.0),
+            Track::new("E", 2.0),
+        ];
+        let constraints = vec![
+            Constraint::new(ConstraintKind::AtPosition("C".into(), 0), 3),
+            Constraint::new(ConstraintKind::Adjacent("D".into(), "E".into()), 2),
+            Constraint::new(ConstraintKind::OnSameSide("A".into(), "C".into()), 1),
+        ];
+        let (_, optimum) = best_arrangement(&tracks, &constraints, &medium).unwrap();
+
+        let params = GaParams {
+            population_size: 30,
+            generations: 60,
+            mutation_rate: 0.3,
+        };
+        let best = optimize_genetic(&tracks, &constraints, &medium, params, 7).unwrap();
+        assert_eq!(score_tracklist(&best, &constraints, &medium), optimum);
+
+        // Same seed, same answer
+        let again = optimize_genetic(&tracks, &constraints, &medium, params, 7);
+        assert_eq!(Some(best), again);
+    }
+
+    #[test]
+    fn test_optimize_genetic_seeks_a_fit() {
+        // Every side must be filled exactly, and the input order spills over
+        let tracks: Vec<Track> = [6.0, 6.0, 6.0, 4.0, 4.0, 4.0, 3.0, 3.0]
+            .iter()
+            .enumerate()
+            .map(|(i, &d)| Track::new(format!("T{i}"), d))
+            .collect();
+        let medium = Medium::new("test_medium", 3, 12.0);
+        let input = Tracklist::new(tracks.clone());
+        assert!(!medium.fits(&input));
+        assert!(fit_violation(&input, &medium) > 0.0);
+
+        let best = optimize_genetic(&tracks, &[], &medium, GaParams::default(), 1).unwrap();
+        assert!(medium.fits(&best));
+        assert_eq!(fit_violation(&best, &medium), 0.0);
+    }
+
+    #[test]
+    fn test_optimize_genetic_keeps_valid_permutations() {
+        // Uneven lengths, so some offspring overflow a side
+        let tracks: Vec<Track> = (0..8)
+            .map(|i| Track::new(format!("T{i}"), (i % 3 + 1) as f64))
+            .collect();
+        let medium = Medium::new("test_medium", 2, 8.0);
+        let constraints = vec![
+            Constraint::new(ConstraintKind::Adjacent("T1".into(), "T2".into()), 3),
+            Constraint::new(ConstraintKind::AtPosition("T7".into(), 0), 2),
+        ];
+        let mut generations = 0;
+        run_genetic(
+            &tracks,
+            &constraints,
+            &medium,
+            GaParams::default(),
+            3,
+            |order| {
+                let mut sorted = order.to_vec();
+                sorted.sort_unstable();
+                assert_eq!(sorted, (0..tracks.len()).collect::<Vec<_>>());
+                generations += 1;
+            },
+        );
+        assert_eq!(generations, GaParams::default().generations);
+    }
+
+    #[test]
+    fn test_best_tracklist_bb_matches_exhaustive() {
+        let medium = Medium::new("test_medium", 2, 9.0);
+        let tracks: Vec<Track> = ["A", "B", "C", "D", "E", "F"]
+            .iter()
+            .map(|t| Track::new(*t, 3.0))
+            .collect();
+        let constraints = vec![
+            Constraint::new(ConstraintKind::AtPosition("F".into(), 0), 4),
+            Constraint::new(ConstraintKind::Adjacent("C".into(), "A".into()), 3),
+            Constraint::new(ConstraintKind::OnSameSide("B".into(), "F".into()), 1),
+            Constraint::new(ConstraintKind::WithinLast("D".into(), 1), 2),
+        ];
+        assert_eq!(
+            best_tracklist_bb(&tracks, &constraints, &medium),
+            best_arrangement(&tracks, &constraints, &medium)
+        );
+
+        let too_small = Medium::new("too_small", 1, 9.0);
+        assert!(best_tracklist_bb(&tracks, &constraints, &too_small).is_none());
+
+        // Conflicting constraints over the kinds with dedicated bounds
+        let tagged: Vec<Track> = tracks
+            .iter()
+            .enumerate()
+            .map(|(i, t)| {
+                let t = t.clone().with_id(i);
+                if i % 2 == 0 { t.with_tag("loud") } else { t }
+            })
+            .collect();
+        let constraints = vec![
+            Constraint::new(ConstraintKind::AdjacentEither("A".into(), "C".into()), 3),
+            Constraint::new(ConstraintKind::AtPositionIdx(2, 1), 2),
+            Constraint::new(ConstraintKind::PositionParity("C".into(), true), 2),
+            Constraint::new(
+                ConstraintKind::TagSequence(vec!["loud".into(), "loud".into()]),
+                4,
+            ),
+            Constraint::new(ConstraintKind::MaxTagRun("loud".into(), 1), 3),
+            Constraint::new(ConstraintKind::MaxShortPerSide(4.0, 2), 1),
+        ];
+        let (_, score) = best_arrangement(&tagged, &constraints, &medium).unwrap();
+        assert!(score < max_achievable_score(&constraints));
+        assert_eq!(
+            best_tracklist_bb(&tagged, &constraints, &medium).map(|(_, s)| s),
+            Some(score)
+        );
+    }
+
+    #[test]
+    fn test_best_tracklist_bb_twelve_tracks() {
+        // 12! orderings are far beyond brute force; the bound finishes quickly
+        let tracks: Vec<Track> = (0..12)
+            .map(|i| {
+                let track = Track::new(format!("T{i}"), 3.0);
+                if i % 3 == 0 {
+                    track.with_tag("single")
+                } else {
+                    track
+                }
+            })
+            .collect();
+        let medium = Medium::new("test_medium", 2, 18.0);
+        let constraints = vec![
+            Constraint::new(ConstraintKind::AtPosition("T5".into(), 0), 4),
+            Constraint::new(ConstraintKind::Adjacent("T5".into(), "T2".into()), 3),
+            Constraint::new(ConstraintKind::AdjacentEither("T7".into(), "T8".into()), 3),
+            Constraint::new(ConstraintKind::AtPositionIdx(11, 11), 2),
+            Constraint::new(ConstraintKind::WithinLast("T1".into(), 2), 2),
+            Constraint::new(ConstraintKind::PositionParity("T4".into(), false), 1),
+            Constraint::new(ConstraintKind::MaxTagRun("single".into(), 1), 2),
+            Constraint::new(
+                ConstraintKind::TagSequence(vec!["*".into(), "*".into(), "single".into()]),
+                1,
+            ),
+        ];
+        let tracks: Vec<Track> = tracks
+            .into_iter()
+            .enumerate()
+            .map(|(i, t)| t.with_id(i))
+            .collect();
+
+        let (best, stats) = best_tracklist_bb_stats(&tracks, &constraints, &medium);
+        let (best, score) = best.unwrap();
+        assert_eq!(score, max_achievable_score(&constraints));
+        assert_eq!(score_tracklist(&best, &constraints, &medium), score);
+        assert!(stats.nodes_explored < 100_000, "{stats:?}");
+    }
+
+    #[test]
+    fn test_best_tracklist_bb_stats_prunes() {
+        let tracks: Vec<Track> = ["A", "B", "C", "D", "E", "F"]
+            .iter()
+            .map(|t| Track::new(*t, 1.0))
+            .collect();
+        let medium = Medium::new("test_medium", 1, 10.0);
+        // The first ordering already hits the bound, so every other opener is cut
+        let constraints = vec![Constraint::new(
+            ConstraintKind::AtPosition("A".into(), 0),
+            10,
+        )];
+
+        let (best, stats) = best_tracklist_bb_stats(&tracks, &constraints, &medium);
+        let (best, score) = best.unwrap();
+        assert_eq!(best.titles(), vec!["A", "B", "C", "D", "E", "F"]);
+        assert_eq!(score, 10.0);
+        assert!(stats.nodes_pruned > 0);
+        assert!(stats.nodes_explored < 720);
+        assert_eq!(stats.best_found_at_node, 7);
+    }
+
+    #[test]
+    fn test_best_tracklist_bb_checks_full_fit() {
+        // Every prefix fits with the last side open, but whichever side gets
+        // the third track holds only one
+        let tracks = vec![
+            Track::new("A", 6.0),
+            Track::new("B", 4.0),
+            Track::new("C", 6.0),
+        ];
+        let medium = Medium::new("test_medium", 2, 10.0).with_min_tracks_per_side(2);
+        assert!(best_tracklist_bb(&tracks, &[], &medium).is_none());
+        assert!(find_best(&tracks, &[], &medium).is_none());
+
+        let roomy = Medium::new("test_medium", 2, 16.0).with_min_tracks_per_side(2);
+        let (best, _) = best_tracklist_bb(&tracks, &[], &roomy).unwrap();
+        assert!(roomy.fits(&best));
+    }
+
+    #[test]
+    fn test_searches_reject_hard_violations() {
+        let tracks = vec![
+            Track::new("A", 3.0),
+            Track::new("B", 3.0),
+            Track::new("C", 3.0),
+        ];
+        let medium = Medium::new("test_medium", 1, 10.0);
+        // No ordering can open with a track that isn't there
+        let constraints = vec![
+            Constraint::new(ConstraintKind::AtPosition("Z".into(), 0), 1).with_hard(),
+            Constraint::new(ConstraintKind::Adjacent("A".into(), "B".into()), 5),
+        ];
+
+        assert_eq!(best_arrangement(&tracks, &constraints, &medium), None);
+        assert_eq!(best_tracklist_bb(&tracks, &constraints, &medium), None);
+        assert_eq!(find_best(&tracks, &constraints, &medium), None);
+        assert!(top_arrangements(&tracks, &constraints, &medium, 3).is_empty());
+        assert_eq!(
+            optimize_annealing(&tracks, &constraints, &medium, 100, 0),
+            None
+        );
+        assert_eq!(
+            optimize_genetic(&tracks, &constraints, &medium, GaParams::default(), 0),
+            None
+        );
+        let sampled = sample(&tracks, &constraints, &medium, 20, 0);
+        assert!(sampled.best.is_empty());
+
+        // Dropping the hard flag brings back the best soft score
+        let soft: Vec<Constraint> = constraints
+            .iter()
+            .cloned()
+            .map(|mut c| {
+                c.hard = false;
+                c
+            })
+            .collect();
+        let (_, score) = best_arrangement(&tracks, &soft, &medium).unwrap();
+        assert_eq!(score, 5.0);
+    }
+
+    #[test]
+    fn test_min_moves_to_fit() {
+        let medium = Medium::new("test_medium", 2, 10.0);
+
+        // Side A: 6 + 3, then 5 + 4 + 2 overflows side B; moving D up next to A fits
+        let tracks = Tracklist::from(vec![
+            ("A", 6.0),
+            ("B", 3.0),
+            ("C", 5.0),
+            ("D", 4.0),
+            ("E", 2.0),
+        ]);
+        assert!(!medium.fits(&tracks));
+        assert_eq!(min_moves_to_fit(&tracks, &medium), Some(1));
+
+        let fitting = Tracklist::from(vec![("A", 6.0), ("D", 4.0)]);
+        assert_eq!(min_moves_to_fit(&fitting, &medium), Some(0));
+
+        let too_long = Tracklist::from(vec![("A", 8.0), ("B", 8.0), ("C", 8.0)]);
+        assert_eq!(min_moves_to_fit(&too_long, &medium), None);
+    }
+
+    #[test]
+    fn test_max_achievable_bounds() {
+        // A hidden track shifts the audible positions the bounds reason about
+        let mut tracks: Vec<Track> = ["A", "B", "C", "D", "E"]
+            .iter()
+            .map(|t| Track::new(*t, 3.0))
+            .collect();
+        tracks.push(Track::new("Hidden", 1.0).with_hidden());
+        let medium = Medium::new("test_medium", 2, 9.0);
+        let constraints = vec![
+            Constraint::new(ConstraintKind::AtPosition("E".into(), 0), 4),
+            Constraint::new(ConstraintKind::Adjacent("C".into(), "A".into()), 3),
+            Constraint::new(ConstraintKind::WithinLast("D".into(), 1), 2),
+            Constraint::new(ConstraintKind::PositionParity("B".into(), false), 1),
+            Constraint::new(ConstraintKind::InPositionRange("A".into(), 1, 2), 2),
+            Constraint::new(ConstraintKind::OnSameSide("B".into(), "E".into()), 1),
+        ];
+        assert_eq!(max_achievable_score(&constraints), 13.0);
+
+        // Every prefix of every ordering: the bound never underestimates the best completion
+        for split in 0..=tracks.len() {
+            for perm in TracklistPermutations::new(&tracks).step_by(7) {
+                let (prefix, pool): (Vec<Track>, Vec<Track>) = (
+                    perm[..split].iter().map(|t| (*t).clone()).collect(),
+                    perm[split..].iter().map(|t| (*t).clone()).collect(),
+                );
+                let brute_force = TracklistPermutations::new(&pool)
+                    .map(|rest| {
+                        let full = prefix
+                            .iter()
+                            .cloned()
+                            .chain(rest.into_iter().cloned())
+                            .collect();
+                        score_tracklist(&Tracklist::new(full), &constraints, &medium)
+                    })
+                    .fold(0.0, f64::max);
+                let bound = max_achievable_given_prefix(&prefix, &pool, &constraints, &medium);
+                assert!(bound >= brute_force);
+                assert!(bound <= max_achievable_score(&constraints));
+            }
+        }
+
+        // Opening with A rules out the E opener, C directly before A and A's range
+        let prefix = vec![tracks[0].clone()];
+        let pool = tracks[1..].to_vec();
+        assert_eq!(
+            max_achievable_given_prefix(&prefix, &pool, &constraints, &medium),
+            4.0
+        );
+    }
+}
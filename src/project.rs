@@ -0,0 +1,205 @@
+//! Project files: a tracklist, its medium and its constraints in one JSON
+//! document.
+
+use std::path::Path;
+
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::{Constraint, Medium, Track, Tracklist};
+
+/// Everything a sequencing session works on.
+///
+/// Stored as JSON like
+///
+/// ```json
+/// {
+///   "tracklist": [{ "title": "Intro", "duration": 225.0 }],
+///   "medium": { "name": "LP", "sides": 2, "max_duration_per_side": 1320.0 },
+///   "constraints": [{ "kind": { "AtPosition": ["Intro", 0] }, "weight": 5 }]
+/// }
+/// ```
+///
+/// with the same field rules as `job::SequencingJob`; `constraints` may be
+/// left out.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Project {
+    pub tracklist: Tracklist,
+    pub medium: Medium,
+    pub constraints: Vec<Constraint>,
+}
+
+/// Why a project couldn't be loaded or saved.
+#[derive(Debug)]
+pub enum ProjectError {
+    Io(std::io::Error),
+    /// Not a JSON document, or not one that can be written (a `Custom`
+    /// constraint can't be serialized).
+    Json(serde_json::Error),
+    /// A field is missing or holds an invalid value. `path` names it, e.g.
+    /// `tracklist[2]` or `medium`.
+    Field {
+        path: String,
+        message: String,
+    },
+}
+
+impl std::fmt::Display for ProjectError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProjectError::Io(e) => write!(f, "{e}"),
+            ProjectError::Json(e) => write!(f, "invalid project JSON: {e}"),
+            ProjectError::Field { path, message } => write!(f, "{path}: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for ProjectError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ProjectError::Io(e) => Some(e),
+            ProjectError::Json(e) => Some(e),
+            ProjectError::Field { .. } => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ProjectError {
+    fn from(e: std::io::Error) -> Self {
+        ProjectError::Io(e)
+    }
+}
+
+fn field_error(path: &str, message: impl ToString) -> ProjectError {
+    ProjectError::Field {
+        path: path.to_string(),
+        message: message.to_string(),
+    }
+}
+
+/// Deserialize `value`, blaming `path` on failure.
+fn field<T: DeserializeOwned>(value: Value, path: &str) -> Result<T, ProjectError> {
+    serde_json::from_value(value).map_err(|e| field_error(path, e))
+}
+
+/// Deserialize each element of the array at `path`, blaming the element.
+fn elements<T: DeserializeOwned>(value: Value, path: &str) -> Result<Vec<T>, ProjectError> {
+    match value {
+        Value::Array(items) => items
+            .into_iter()
+            .enumerate()
+            .map(|(i, item)| field(item, &format!("{path}[{i}]")))
+            .collect(),
+        _ => Err(field_error(path, "expected an array")),
+    }
+}
+
+impl Project {
+    pub fn new(tracklist: Tracklist, medium: Medium, constraints: Vec<Constraint>) -> Self {
+        Project {
+            tracklist,
+            medium,
+            constraints,
+        }
+    }
+
+    /// Parse a project from JSON. Unknown top-level fields are ignored.
+    pub fn from_json_str(json: &str) -> Result<Self, ProjectError> {
+        let mut root = match serde_json::from_str(json).map_err(ProjectError::Json)? {
+            Value::Object(root) => root,
+            _ => return Err(field_error("project", "expected an object")),
+        };
+        let mut take = |name: &str| {
+            root.remove(name)
+                .ok_or_else(|| field_error(name, "missing field"))
+        };
+        let tracks: Vec<Track> = elements(take("tracklist")?, "tracklist")?;
+        let medium = field(take("medium")?, "medium")?;
+        let constraints = match take("constraints") {
+            Ok(value) => elements(value, "constraints")?,
+            Err(_) => Vec::new(),
+        };
+        Ok(Project::new(Tracklist(tracks), medium, constraints))
+    }
+
+    /// The project as pretty-printed JSON, readable by `from_json_str`.
+    pub fn to_json_string(&self) -> Result<String, ProjectError> {
+        serde_json::to_string_pretty(self).map_err(ProjectError::Json)
+    }
+
+    /// Read a project file written by `save` (or by hand).
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ProjectError> {
+        Project::from_json_str(&std::fs::read_to_string(path)?)
+    }
+
+    /// Write the project to `path` as JSON, replacing any existing file.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ProjectError> {
+        Ok(std::fs::write(path, self.to_json_string()?)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ConstraintKind;
+
+    #[test]
+    fn test_project_round_trip() {
+        let project = Project::new(
+            Tracklist::new(vec![
+                Track::new("Intro", 90.0).with_genre("ambient"),
+                Track::new("Song", 200.0),
+            ]),
+            Medium::lp().with_gap(2.0),
+            vec![Constraint::new(
+                ConstraintKind::Before("Intro".into(), "Song".into()),
+                2,
+            )],
+        );
+        let path = std::env::temp_dir().join(format!("albumseq-{}.json", std::process::id()));
+        project.save(&path).unwrap();
+        let loaded = Project::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        let loaded = loaded.unwrap();
+
+        assert_eq!(loaded.tracklist, project.tracklist);
+        assert_eq!(loaded.tracklist.0[0].genre.as_deref(), Some("ambient"));
+        assert_eq!(loaded.medium.gap, 2.0);
+        assert_eq!(
+            loaded.to_json_string().unwrap(),
+            project.to_json_string().unwrap()
+        );
+
+        // Constraints are optional
+        let bare = Project::from_json_str(
+            r#"{ "tracklist": [], "medium": { "name": "CD", "sides": 1, "max_duration_per_side": 4800.0 } }"#,
+        )
+        .unwrap();
+        assert!(bare.constraints.is_empty());
+    }
+
+    #[test]
+    fn test_project_errors_name_the_field() {
+        let medium = r#""medium": { "name": "LP", "sides": 2, "max_duration_per_side": 10.0 }"#;
+        let error = |json: &str| Project::from_json_str(json).unwrap_err().to_string();
+
+        let negative = format!(
+            r#"{{ "tracklist": [{{ "title": "A", "duration": 1.0 }}, {{ "title": "B", "duration": -2.0 }}], {medium} }}"#
+        );
+        assert!(error(&negative).starts_with("tracklist[1]: invalid duration -2"));
+
+        let unknown_kind = format!(
+            r#"{{ "tracklist": [], {medium}, "constraints": [{{ "kind": {{ "Nearby": ["A", "B"] }}, "weight": 1 }}] }}"#
+        );
+        assert!(error(&unknown_kind).starts_with("constraints[0]: unknown variant `Nearby`"));
+
+        let no_sides =
+            r#"{ "tracklist": [], "medium": { "name": "LP", "max_duration_per_side": 10.0 } }"#;
+        assert_eq!(error(no_sides), "medium: missing field `sides`");
+        assert_eq!(
+            error(&format!("{{ {medium} }}")),
+            "tracklist: missing field"
+        );
+        assert!(error("[1, 2").starts_with("invalid project JSON"));
+    }
+}